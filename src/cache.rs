@@ -0,0 +1,159 @@
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// How long a successful resolution is reused unless `RESOLVE_CACHE_TTL_SECS` says otherwise.
+pub const DEFAULT_HIT_TTL: Duration = Duration::from_secs(24 * 3600);
+
+/// Links with no Spotify match are cached briefly so a burst of reposts doesn't hammer Odesli,
+/// but a track that reaches Spotify later isn't missed for long. Failed lookups aren't cached.
+const MISS_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// In-memory URL → Spotify track ID cache, including negative (`None`) results.
+pub struct ResolveCache {
+    entries: DashMap<String, (Option<String>, Instant)>,
+    hit_ttl: Duration,
+    /// Never longer than `hit_ttl`
+    miss_ttl: Duration,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for ResolveCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_HIT_TTL)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub negative_entries: usize,
+}
+
+impl ResolveCache {
+    /// A cache keeping resolutions for `hit_ttl`; zero turns caching off.
+    pub fn new(hit_ttl: Duration) -> Self {
+        Self {
+            entries: DashMap::new(),
+            hit_ttl,
+            miss_ttl: hit_ttl.min(MISS_TTL),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn ttl(&self, track_id: &Option<String>) -> Duration {
+        if track_id.is_some() {
+            self.hit_ttl
+        } else {
+            self.miss_ttl
+        }
+    }
+
+    /// `Some(result)` if the URL has a fresh entry; `result` is `None` for a cached miss.
+    pub fn get(&self, url: &str) -> Option<Option<String>> {
+        let fresh = self.entries.get(url).and_then(|entry| {
+            let (track_id, cached_at) = entry.value();
+            (cached_at.elapsed() < self.ttl(track_id)).then(|| track_id.clone())
+        });
+        match fresh {
+            Some(track_id) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(track_id)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, url: &str, track_id: Option<String>) {
+        self.entries
+            .insert(url.to_string(), (track_id, Instant::now()));
+    }
+
+    pub fn remove(&self, url: &str) {
+        self.entries.remove(url);
+    }
+
+    /// Drops every link cached as resolving to `track_id`, e.g. once Spotify says the track
+    /// no longer exists, so those links resolve afresh next time.
+    pub fn forget_track(&self, track_id: &str) {
+        self.entries
+            .retain(|_, (cached, _)| cached.as_deref() != Some(track_id));
+    }
+
+    /// Drops expired entries.
+    pub fn prune(&self) {
+        self.entries
+            .retain(|_, (track_id, cached_at)| cached_at.elapsed() < self.ttl(track_id));
+    }
+
+    /// Empties the cache, returning how many entries were removed. Counters are kept.
+    pub fn clear(&self) -> usize {
+        let removed = self.entries.len();
+        self.entries.clear();
+        removed
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.len(),
+            negative_entries: self
+                .entries
+                .iter()
+                .filter(|e| e.value().0.is_none())
+                .count(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_counts_hits_and_misses_and_clears() {
+        let cache = ResolveCache::default();
+        assert_eq!(cache.get("https://youtu.be/a"), None);
+
+        cache.insert("https://youtu.be/a", Some("track_a".to_string()));
+        cache.insert("https://youtu.be/b", None);
+        assert_eq!(
+            cache.get("https://youtu.be/a"),
+            Some(Some("track_a".to_string()))
+        );
+        assert_eq!(cache.get("https://youtu.be/b"), Some(None));
+
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                entries: 2,
+                negative_entries: 1,
+            }
+        );
+        assert_eq!(cache.clear(), 2);
+        assert_eq!(cache.get("https://youtu.be/a"), None);
+    }
+
+    #[test]
+    fn failures_never_outlive_the_configured_ttl() {
+        assert_eq!(ResolveCache::default().miss_ttl, MISS_TTL);
+        let short = ResolveCache::new(Duration::from_secs(60));
+        assert_eq!(short.miss_ttl, Duration::from_secs(60));
+
+        let off = ResolveCache::new(Duration::ZERO);
+        off.insert("https://youtu.be/a", Some("track_a".to_string()));
+        off.insert("https://youtu.be/b", None);
+        assert_eq!(off.get("https://youtu.be/a"), None);
+        assert_eq!(off.get("https://youtu.be/b"), None);
+    }
+}
@@ -0,0 +1,1626 @@
+use crate::config::{parse_emoji_name, ReactionEmojis};
+use crate::history::{AddRecord, PeriodSummary};
+use crate::notify::TracksAdded;
+use crate::resolve::parse_spotify_track_id;
+use crate::spotify::{
+    duplicate_positions, item_uri, PlaylistAccess, PlaylistEntry, MAX_ITEMS_PER_REQUEST,
+};
+use crate::telemetry;
+use crate::{
+    add_resolved_tracks, backfill_existing_messages, forget_removed_tracks, message_urls,
+    notify_tracks_added, record_added_tracks, resolve_url, resolve_url_once, verify_slack_request,
+    AppState, BulkAdder,
+};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
+
+/// Slack requires a response to slash commands within 3 seconds. Commands that take longer
+/// are acknowledged immediately and their result is posted to `response_url` instead.
+const INLINE_RESPONSE_TIMEOUT: Duration = Duration::from_millis(2500);
+
+/// Longest log `/jamcraft trace` posts, so a chatty resolution still fits in one message.
+const MAX_TRACE_CHARS: usize = 3000;
+
+/// Most adds one `/jamcraft undo` rolls back.
+const MAX_UNDO_ADDS: usize = 500;
+/// Removed tracks listed in the `/jamcraft undo` reply; the rest are only counted.
+const UNDO_LIST_LIMIT: usize = 20;
+
+/// How many links `/jamcraft import` works through between progress updates.
+const IMPORT_PROGRESS_EVERY: usize = 25;
+/// Adds `/jamcraft recent` lists without a count, and the most it lists.
+const DEFAULT_RECENT_ADDS: usize = 10;
+const MAX_RECENT_ADDS: usize = 25;
+
+/// Scopes the bot needs whatever the playlist: read it for duplicate checks. Adding also needs
+/// the modify scope for the playlist's visibility (`PlaylistAccess::modify_scope`).
+pub(crate) const REQUIRED_SPOTIFY_SCOPES: &[&str] = &["playlist-read-private"];
+
+#[derive(Debug, Deserialize)]
+pub struct SlashCommand {
+    #[serde(default)]
+    pub text: String,
+    pub user_id: String,
+    pub channel_id: String,
+    pub response_url: String,
+}
+
+/// A message pulled out of the channel in curate mode, waiting for a moderator to approve it.
+#[derive(Debug, Clone)]
+pub struct PendingAdd {
+    pub channel: String,
+    pub ts: String,
+    pub user: Option<String>,
+    pub playlist_id: String,
+    pub track_ids: Vec<String>,
+    /// Platform each track was shared from, for the add history
+    pub sources: HashMap<String, &'static str>,
+    /// Text around the link(s), forwarded to the notify webhook once approved
+    pub note: Option<String>,
+    pub queued_at: Instant,
+}
+
+pub struct CommandReply {
+    text: String,
+    in_channel: bool,
+}
+
+impl CommandReply {
+    fn ephemeral(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            in_channel: false,
+        }
+    }
+
+    fn in_channel(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            in_channel: true,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "response_type": if self.in_channel { "in_channel" } else { "ephemeral" },
+            "text": self.text,
+        })
+    }
+}
+
+pub async fn slack_commands_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, StatusCode> {
+    verify_slack_request(&state, &headers, &body)?;
+
+    let command: SlashCommand = serde_urlencoded::from_bytes(&body).map_err(|e| {
+        warn!("Failed to parse slash command payload: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    Ok(Json(run_command(state, command).await))
+}
+
+/// Runs a slash command, however it arrived (HTTP or Socket Mode), and returns the reply to
+/// send inline. Commands that outlast `INLINE_RESPONSE_TIMEOUT` reply via `response_url`.
+pub async fn run_command(state: AppState, command: SlashCommand) -> Value {
+    info!(
+        "Received slash command from {}: {}",
+        command.user_id, command.text
+    );
+
+    let response_url = command.response_url.clone();
+    let slack = state.slack.clone();
+    let mut task = tokio::spawn(dispatch(state, command));
+
+    match tokio::time::timeout(INLINE_RESPONSE_TIMEOUT, &mut task).await {
+        Ok(Ok(reply)) => reply.to_json(),
+        Ok(Err(e)) => {
+            error!("Slash command task failed: {}", e);
+            CommandReply::ephemeral("Something went wrong running that command.").to_json()
+        }
+        Err(_) => {
+            // Still running: acknowledge now and deliver the result via response_url
+            tokio::spawn(async move {
+                if let Ok(reply) = task.await {
+                    if let Err(e) = slack
+                        .post_response_url(&response_url, &reply.to_json())
+                        .await
+                    {
+                        error!("Failed to post delayed command response: {}", e);
+                    }
+                }
+            });
+            CommandReply::ephemeral("Working on it…").to_json()
+        }
+    }
+}
+
+async fn dispatch(state: AppState, command: SlashCommand) -> CommandReply {
+    let mut args = command.text.split_whitespace();
+    match args.next().unwrap_or("help") {
+        "pending" => pending(&state),
+        "approve" => {
+            if !state.config.is_moderator(&command.user_id) {
+                return CommandReply::ephemeral("Only moderators can approve pending tracks.");
+            }
+            approve(&state, &command.user_id, &command.channel_id, args.next()).await
+        }
+        "diag" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can run diagnostics.");
+            }
+            diag(&state).await
+        }
+        "undo" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can roll back adds.");
+            }
+            undo(&state, &command.user_id, args.next()).await
+        }
+        "dedupe" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can dedupe the playlist.");
+            }
+            dedupe(&state).await
+        }
+        "bounds" => bounds(&state).await,
+        "cache" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can manage the resolution cache.");
+            }
+            cache(&state, args.next())
+        }
+        "ratelimit" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can view rate-limit status.");
+            }
+            ratelimit(&state)
+        }
+        "track" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can inspect track history.");
+            }
+            track(&state, args.next()).await
+        }
+        "import" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can bulk-import tracks.");
+            }
+            import(&state, &command)
+        }
+        "backfill" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can preview a backfill.");
+            }
+            match args.next() {
+                Some("--dry") => backfill_preview(&state, &command),
+                _ => CommandReply::ephemeral("Usage: `/jamcraft backfill --dry`"),
+            }
+        }
+        "trace" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can trace link resolution.");
+            }
+            trace(&state, &command, args.next())
+        }
+        "dryrun" => match args.next() {
+            None => show_dry_run(&state),
+            Some(value) => {
+                if !state.config.is_admin(&command.user_id) {
+                    return CommandReply::ephemeral("Only admins can switch dry-run mode.");
+                }
+                set_dry_run(&state, &command.user_id, value)
+            }
+        },
+        "target" => match args.next() {
+            None => show_target(&state),
+            Some(arg) => {
+                if !state.config.is_admin(&command.user_id) {
+                    return CommandReply::ephemeral("Only admins can change the target playlist.");
+                }
+                set_target(&state, &command.user_id, arg).await
+            }
+        },
+        "features" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can list feature settings.");
+            }
+            features(&state)
+        }
+        "quarantine" | "unquarantine" => {
+            if !state.config.is_admin(&command.user_id) {
+                return CommandReply::ephemeral("Only admins can quarantine users.");
+            }
+            let release = command.text.trim_start().starts_with("unquarantine");
+            quarantine(&state, &command.user_id, args.next(), release)
+        }
+        "recap" => recap(&state),
+        "recent" => recent(&state, args.next()),
+        "sources" => sources(&state, args.next()),
+        "emoji" => match (args.next(), args.next()) {
+            (None | Some("show"), _) => show_emojis(&state),
+            (Some(kind), value) => {
+                if !state.config.is_admin(&command.user_id) {
+                    return CommandReply::ephemeral("Only admins can change reaction emojis.");
+                }
+                set_emoji(&state, &command.user_id, kind, value)
+            }
+        },
+        _ => help(),
+    }
+}
+
+fn help() -> CommandReply {
+    CommandReply::ephemeral(
+        "Usage:\n\
+         • `/jamcraft pending` – list messages waiting for approval\n\
+         • `/jamcraft approve [channel:ts]` – add the oldest (or given) pending message's tracks (moderators)\n\
+         • `/jamcraft approve all` – add every pending track (moderators)\n\
+         • `/jamcraft bounds` – show the first and last tracks in the playlist\n\
+         • `/jamcraft recent [n]` – the last 10 (or n) tracks added, who added them and when\n\
+         • `/jamcraft recap` – this week's adds and top contributors vs last week\n\
+         • `/jamcraft sources [days]` – which platforms adds were shared from over the last 30 (or given) days\n\
+         • `/jamcraft emoji show` – list the emojis the bot reacts with\n\
+         • `/jamcraft emoji <kind> <:emoji:>` – change one of them until the next restart (admins)\n\
+         • `/jamcraft dryrun` – whether tracks are really being added\n\
+         • `/jamcraft dryrun on|off` – stop or resume real adds until the next restart (admins)\n\
+         • `/jamcraft features` – every feature setting as currently loaded (admins)\n\
+         • `/jamcraft target` – which playlist tracks are added to\n\
+         • `/jamcraft target <playlist>` – add to another playlist until the next restart, once the bot can write to it (admins)\n\
+         • `/jamcraft quarantine [@user]` – send a user's adds to the quarantine playlist, or list who's quarantined (admins)\n\
+         • `/jamcraft unquarantine <@user>` – send their adds to the usual playlist again (admins)\n\
+         • `/jamcraft diag` – check Slack and Spotify credentials, scopes and playlist access (admins)\n\
+         • `/jamcraft dedupe` – remove repeated tracks from the playlist, keeping the first (admins)\n\
+         • `/jamcraft undo <n>` – remove the last n tracks the bot added, imports included (admins)\n\
+         • `/jamcraft cache stats|clear` – show or flush the link resolution cache (admins)\n\
+         • `/jamcraft ratelimit` – show recent Spotify 429s and any backoff in effect (admins)\n\
+         • `/jamcraft track <url-or-id>` – whether a track is in the playlist, who added it and whether it's deduped (admins)\n\
+         • `/jamcraft import <links…>` – resolve and add a pasted list of links, one import at a time (admins)\n\
+         • `/jamcraft backfill --dry` – count what a backfill of the channel's history would add, without adding (admins)\n\
+         • `/jamcraft trace <url>` – step-by-step log of resolving a link, cache bypassed (admins)",
+    )
+}
+
+fn pending(state: &AppState) -> CommandReply {
+    let mut items: Vec<PendingAdd> = state.pending.iter().map(|e| e.value().clone()).collect();
+    if items.is_empty() {
+        return CommandReply::ephemeral("Nothing is waiting for approval.");
+    }
+    items.sort_by_key(|p| p.queued_at);
+
+    let lines: Vec<String> = items
+        .iter()
+        .map(|p| {
+            format!(
+                "• `{}:{}` – {} track(s), queued {} min ago",
+                p.channel,
+                p.ts,
+                p.track_ids.len(),
+                p.queued_at.elapsed().as_secs() / 60
+            )
+        })
+        .collect();
+    CommandReply::ephemeral(format!(
+        "{} message(s) pending approval:\n{}",
+        items.len(),
+        lines.join("\n")
+    ))
+}
+
+/// Adds pending messages' tracks. `target` is `all`, a `channel:ts` key as `/jamcraft pending`
+/// lists it, or a bare `ts` in `channel`; without one the oldest message goes.
+async fn approve(
+    state: &AppState,
+    moderator: &str,
+    channel: &str,
+    target: Option<&str>,
+) -> CommandReply {
+    let spotify_client = match &state.spotify {
+        Some(c) => c.clone(),
+        None => return CommandReply::ephemeral("Spotify is not configured."),
+    };
+
+    let keys: Vec<String> = match target {
+        Some("all") => state.pending.iter().map(|e| e.key().clone()).collect(),
+        Some(key) if key.contains(':') => vec![key.to_string()],
+        Some(ts) => vec![format!("{}:{}", channel, ts)],
+        None => state
+            .pending
+            .iter()
+            .min_by_key(|e| e.value().queued_at)
+            .map(|e| e.key().clone())
+            .into_iter()
+            .collect(),
+    };
+    if keys.is_empty() {
+        return CommandReply::ephemeral("Nothing is waiting for approval.");
+    }
+
+    let mut approved_messages = 0;
+    let mut added_total = 0;
+    let mut failed_total = 0;
+    let mut kept_pending = 0;
+    for key in keys {
+        let Some((_, item)) = state.pending.remove(&key) else {
+            continue;
+        };
+        approved_messages += 1;
+
+        let outcome = add_resolved_tracks(
+            state,
+            &spotify_client,
+            &item.channel,
+            &item.playlist_id,
+            item.track_ids.clone(),
+        )
+        .await;
+        // Nothing went in, so keep it for another approve rather than lose it
+        if outcome.added_ids.is_empty() && outcome.failed > 0 {
+            warn!(
+                "Approving {} added nothing ({} failed), keeping it pending",
+                key, outcome.failed
+            );
+            failed_total += outcome.failed;
+            kept_pending += 1;
+            state.pending.insert(key, item);
+            continue;
+        }
+        added_total += outcome.added_ids.len();
+        record_added_tracks(
+            state,
+            &item.channel,
+            &item.ts,
+            item.user.as_deref(),
+            &item.playlist_id,
+            &outcome.added_ids,
+            &item.sources,
+        );
+        notify_tracks_added(
+            state,
+            &TracksAdded {
+                channel: &item.channel,
+                ts: &item.ts,
+                user: item.user.as_deref(),
+                playlist_id: &item.playlist_id,
+                track_ids: &outcome.added_ids,
+                note: item.note.as_deref(),
+            },
+        );
+        failed_total += outcome.failed;
+
+        if !outcome.added_ids.is_empty() {
+            if let Err(e) = state
+                .slack
+                .reactions_add(&item.channel, &item.ts, &state.emojis().success)
+                .await
+            {
+                warn!("Failed to add reaction after approval: {}", e);
+            }
+            let message = format!(
+                "Approved by <@{}>: added {} track(s) to the playlist ✅",
+                moderator,
+                outcome.added_ids.len()
+            );
+            if let Err(e) = state
+                .slack
+                .chat_post_message(&item.channel, Some(&item.ts), &message)
+                .await
+            {
+                warn!("Failed to post approval message: {}", e);
+            }
+        }
+    }
+
+    if approved_messages == 0 {
+        return CommandReply::ephemeral("No pending message with that timestamp.");
+    }
+    let mut text = format!(
+        "Approved {} message(s): added {} track(s)",
+        approved_messages, added_total
+    );
+    if failed_total > 0 {
+        text.push_str(&format!(", {} failed", failed_total));
+    }
+    if kept_pending > 0 {
+        text.push_str(&format!(
+            "; {} message(s) added nothing and are still pending",
+            kept_pending
+        ));
+    }
+    CommandReply::in_channel(text)
+}
+
+/// Required scopes that are not in the space-separated `granted` list. Without the playlist's
+/// `access` either modify scope will do.
+pub(crate) fn missing_scopes(granted: &str, access: Option<&PlaylistAccess>) -> Vec<&'static str> {
+    let has = |scope: &str| granted.split(' ').any(|g| g == scope);
+    let mut missing: Vec<&'static str> = REQUIRED_SPOTIFY_SCOPES
+        .iter()
+        .filter(|s| !has(s))
+        .copied()
+        .collect();
+    match access.map(PlaylistAccess::modify_scope) {
+        Some(scope) if !has(scope) => missing.push(scope),
+        None if !has("playlist-modify-public") && !has("playlist-modify-private") => {
+            missing.push("playlist-modify-public or playlist-modify-private")
+        }
+        _ => {}
+    }
+    missing
+}
+
+/// The playlist's first and last tracks, with who added them when the bot remembers.
+async fn bounds(state: &AppState) -> CommandReply {
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let (first, last) = match spotify.playlist_ends(&spotify.playlist_id()).await {
+        Ok(Some(ends)) => ends,
+        Ok(None) => return CommandReply::ephemeral("The playlist is empty."),
+        Err(e) => return CommandReply::ephemeral(format!("Couldn't read the playlist: {}", e)),
+    };
+
+    let describe = |label: &str, entry: &PlaylistEntry| {
+        let mut line = format!(
+            "• {} (#{}): {} – {}",
+            label,
+            entry.position + 1,
+            entry.meta.title,
+            entry.meta.artists.join(", ")
+        );
+        let added_by = entry
+            .id
+            .as_deref()
+            .and_then(|id| state.history.last_add_of(id))
+            .and_then(|record| record.user);
+        match (added_by, &entry.added_at) {
+            (Some(user), _) => line.push_str(&format!(", added by <@{}>", user)),
+            (None, Some(added_at)) => {
+                line.push_str(&format!(", added {}", &added_at[..10.min(added_at.len())]))
+            }
+            (None, None) => {}
+        }
+        line
+    };
+    CommandReply::ephemeral(format!(
+        "{}\n{}",
+        describe("First", &first),
+        describe("Last", &last)
+    ))
+}
+
+/// Marks an import as running until dropped, so a panicking import doesn't block the next one.
+struct ImportRunning(Arc<AtomicBool>);
+
+impl Drop for ImportRunning {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+}
+
+/// Starts a background import of every music link in the command text, reporting progress
+/// and a final summary to `response_url`. Only one import runs at a time.
+fn import(state: &AppState, command: &SlashCommand) -> CommandReply {
+    let Some(spotify) = state.spotify.clone() else {
+        return CommandReply::ephemeral(
+            "Spotify isn't configured, so there's nothing to import into.",
+        );
+    };
+    let text = command.text.trim_start();
+    let urls = message_urls(&state.config, text.strip_prefix("import").unwrap_or(text));
+    if urls.is_empty() {
+        return CommandReply::ephemeral("Paste the links to import after `/jamcraft import`.");
+    }
+    if state.import_running.swap(true, Ordering::SeqCst) {
+        return CommandReply::ephemeral(
+            "An import is already running; try again once it finishes.",
+        );
+    }
+    let running = ImportRunning(state.import_running.clone());
+
+    let total = urls.len();
+    let state = state.clone();
+    let response_url = command.response_url.clone();
+    info!("{} started importing {} link(s)", command.user_id, total);
+    tokio::spawn(async move {
+        let mut adder = BulkAdder::new(
+            &spotify,
+            &state.config.music_channel_id,
+            &spotify.playlist_id(),
+        )
+        .await;
+        for (i, url) in urls.into_iter().enumerate() {
+            adder.add_url(&state, url).await;
+            let done = i + 1;
+            if done % IMPORT_PROGRESS_EVERY == 0 && done < total {
+                adder.flush(&state).await;
+                let progress = CommandReply::ephemeral(format!(
+                    "Import: {}/{} links processed, {} added so far…",
+                    done, total, adder.added
+                ));
+                if let Err(e) = state
+                    .slack
+                    .post_response_url(&response_url, &progress.to_json())
+                    .await
+                {
+                    warn!("Failed to post import progress: {}", e);
+                }
+            }
+        }
+        adder.flush(&state).await;
+        drop(running);
+        info!(
+            "Import complete: {} links, {} resolved, {} added, {} failed",
+            total, adder.resolved, adder.added, adder.failed
+        );
+
+        let summary = CommandReply::ephemeral(format!(
+            "Import finished: {} link(s), {} resolved, {} added, {} failed{}",
+            total,
+            adder.resolved,
+            adder.added,
+            adder.failed,
+            if state.dry_run() || state.config.shadow_mode {
+                " (dry run, nothing was actually added)"
+            } else {
+                ""
+            }
+        ));
+        if let Err(e) = state
+            .slack
+            .post_response_url(&response_url, &summary.to_json())
+            .await
+        {
+            error!("Failed to post import summary: {}", e);
+        }
+    });
+
+    CommandReply::ephemeral(format!(
+        "Importing {} link(s); I'll post progress here.",
+        total
+    ))
+}
+
+/// Runs the backfill scan in the background with adds forced off and posts the counts to
+/// `response_url`.
+fn backfill_preview(state: &AppState, command: &SlashCommand) -> CommandReply {
+    if state.spotify.is_none() {
+        return CommandReply::ephemeral(
+            "Spotify isn't configured, so a backfill wouldn't add anything.",
+        );
+    }
+
+    let state = state.clone();
+    let response_url = command.response_url.clone();
+    info!("{} started a backfill preview", command.user_id);
+    tokio::spawn(async move {
+        let reply = match backfill_existing_messages(state.clone(), true).await {
+            Ok(summary) => CommandReply::ephemeral(format!(
+                "Backfill preview: {} message(s) scanned, {} link(s) found, {} track(s) would be added, {} already in the playlist, {} couldn't be resolved. Nothing was added.",
+                summary.messages,
+                summary.urls,
+                summary.added,
+                summary.already_present,
+                summary.failed
+            )),
+            Err(e) => {
+                error!("Backfill preview failed: {}", e);
+                CommandReply::ephemeral(format!("Backfill preview failed: {}", e))
+            }
+        };
+        if let Err(e) = state
+            .slack
+            .post_response_url(&response_url, &reply.to_json())
+            .await
+        {
+            error!("Failed to post backfill preview: {}", e);
+        }
+    });
+
+    CommandReply::ephemeral(
+        "Scanning the channel history; I'll post what a backfill would add here.",
+    )
+}
+
+/// Removes the last `n` adds in the history from their playlists and from dedupe, so they can
+/// be added again. Adds a failed removal leaves in place go back into the history.
+async fn undo(state: &AppState, admin: &str, n: Option<&str>) -> CommandReply {
+    let n = match n.map(str::parse::<usize>) {
+        Some(Ok(n)) if (1..=MAX_UNDO_ADDS).contains(&n) => n,
+        _ => {
+            return CommandReply::ephemeral(format!(
+                "Usage: `/jamcraft undo <n>` with n from 1 to {}",
+                MAX_UNDO_ADDS
+            ))
+        }
+    };
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let records = state.history.take_latest(n);
+    if records.is_empty() {
+        return CommandReply::ephemeral("No adds in this run's history to undo.");
+    }
+
+    let mut by_playlist: Vec<(String, Vec<String>)> = Vec::new();
+    for record in &records {
+        match by_playlist
+            .iter_mut()
+            .find(|(id, _)| *id == record.playlist_id)
+        {
+            Some((_, track_ids)) => track_ids.push(record.track_id.clone()),
+            None => by_playlist.push((record.playlist_id.clone(), vec![record.track_id.clone()])),
+        }
+    }
+
+    let mut failures = Vec::new();
+    // (playlist, track) pairs actually taken out
+    let mut removed: Vec<(&str, &str)> = Vec::new();
+    for (playlist_id, track_ids) in &by_playlist {
+        // A request at a time, so a failure only keeps back what wasn't removed yet
+        let mut done = track_ids.len();
+        if !state.dry_run() && !state.config.shadow_mode {
+            done = 0;
+            for chunk in track_ids.chunks(MAX_ITEMS_PER_REQUEST) {
+                if let Err(e) = spotify.remove_tracks_from(playlist_id, chunk).await {
+                    let left = track_ids.len() - done;
+                    error!("Undo of {} add(s) to {} failed: {}", left, playlist_id, e);
+                    failures.push(format!(
+                        "• Couldn't remove {} from playlist {}: {}",
+                        left, playlist_id, e
+                    ));
+                    break;
+                }
+                done += chunk.len();
+            }
+        }
+        let gone = &track_ids[..done];
+        forget_removed_tracks(state, playlist_id, gone);
+        removed.extend(gone.iter().map(|id| (playlist_id.as_str(), id.as_str())));
+    }
+    let was_removed =
+        |r: &AddRecord| removed.contains(&(r.playlist_id.as_str(), r.track_id.as_str()));
+    // Put back what's still in a playlist, oldest first, so a later undo can retry it
+    for record in records.iter().rev().filter(|r| !was_removed(r)) {
+        state.history.record(record.clone());
+    }
+    info!(
+        "{} rolled back the last {} add(s), {} removed",
+        admin,
+        records.len(),
+        removed.len()
+    );
+
+    let mut lines = vec![format!(
+        "<@{}> removed the last {} added track(s):",
+        admin,
+        removed.len()
+    )];
+    lines.extend(
+        records
+            .iter()
+            .filter(|r| was_removed(r))
+            .take(UNDO_LIST_LIMIT)
+            .map(|r| {
+                format!(
+                    "• {}{}",
+                    item_link(&r.track_id),
+                    r.user
+                        .as_ref()
+                        .map(|u| format!(" (added by <@{}>)", u))
+                        .unwrap_or_default()
+                )
+            }),
+    );
+    if removed.len() > UNDO_LIST_LIMIT {
+        lines.push(format!("…and {} more", removed.len() - UNDO_LIST_LIMIT));
+    }
+    lines.extend(failures);
+    CommandReply::in_channel(lines.join("\n"))
+}
+
+/// A recorded add's item as a link, or its URI for episodes (stored as full `spotify:` URIs).
+fn item_link(track_id: &str) -> String {
+    if track_id.starts_with("spotify:") {
+        format!("`{}`", track_id)
+    } else {
+        format!("https://open.spotify.com/track/{}", track_id)
+    }
+}
+
+/// Lists the latest adds in this run's history, newest first.
+fn recent(state: &AppState, n: Option<&str>) -> CommandReply {
+    let n = match n.map(str::parse::<usize>) {
+        None => DEFAULT_RECENT_ADDS,
+        Some(Ok(n)) if (1..=MAX_RECENT_ADDS).contains(&n) => n,
+        Some(_) => {
+            return CommandReply::ephemeral(format!(
+                "Usage: `/jamcraft recent [n]` with n from 1 to {}",
+                MAX_RECENT_ADDS
+            ))
+        }
+    };
+    let records = state.history.latest(n);
+    if records.is_empty() {
+        return CommandReply::ephemeral("Nothing has been added since the bot started.");
+    }
+    let now = OffsetDateTime::now_utc();
+    let mut lines = vec![format!("Last {} added track(s):", records.len())];
+    lines.extend(records.iter().map(|record| recent_line(record, now)));
+    CommandReply::ephemeral(lines.join("\n"))
+}
+
+fn recent_line(record: &AddRecord, now: OffsetDateTime) -> String {
+    let minutes = (now - record.added_at).whole_minutes().max(0);
+    let ago = match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{} min ago", minutes),
+        60..=2879 => format!("{} h ago", minutes / 60),
+        _ => format!("{} days ago", minutes / 1440),
+    };
+    let by = record
+        .user
+        .as_ref()
+        .map(|u| format!("<@{}>", u))
+        .unwrap_or_else(|| "an import".to_string());
+    format!(
+        "• {} – added by {}, {}",
+        item_link(&record.track_id),
+        by,
+        ago
+    )
+}
+
+/// Resolves one link once, skipping the cache and retries, and posts every log line it
+/// produced (debug included) plus the outcome.
+fn trace(state: &AppState, command: &SlashCommand, url: Option<&str>) -> CommandReply {
+    let Some(url) = url.map(|url| unwrap_slack_link(url).to_string()) else {
+        return CommandReply::ephemeral("Usage: `/jamcraft trace <url>`");
+    };
+
+    let state = state.clone();
+    let response_url = command.response_url.clone();
+    info!("{} is tracing resolution of {}", command.user_id, url);
+    tokio::spawn(async move {
+        let (resolution, lines) = telemetry::capture(resolve_url_once(&state, &url, None)).await;
+        let track_id = resolution.track_id();
+        let reply = CommandReply::ephemeral(trace_report(&url, track_id.as_deref(), &lines));
+        if let Err(e) = state
+            .slack
+            .post_response_url(&response_url, &reply.to_json())
+            .await
+        {
+            error!("Failed to post resolution trace: {}", e);
+        }
+    });
+
+    CommandReply::ephemeral("Tracing that link; I'll post the log here.")
+}
+
+fn trace_report(url: &str, track_id: Option<&str>, lines: &[String]) -> String {
+    let mut report = format!("*Resolution trace for {}*\n```\n", url);
+    let mut shown = 0;
+    for line in lines {
+        if report.len() + line.len() > MAX_TRACE_CHARS {
+            break;
+        }
+        report.push_str(line);
+        report.push('\n');
+        shown += 1;
+    }
+    if lines.is_empty() {
+        report.push_str("(nothing logged)\n");
+    } else if shown < lines.len() {
+        report.push_str(&format!("… {} more line(s) cut\n", lines.len() - shown));
+    }
+    report.push_str("```\n");
+    report.push_str(&match track_id {
+        Some(track_id) => format!("Outcome: resolved to `{}`", track_id),
+        None => "Outcome: unresolved".to_string(),
+    });
+    report
+}
+
+/// Adds this week (Monday 00:00 UTC onwards) against last week, from the in-memory history.
+fn recap(state: &AppState) -> CommandReply {
+    let now = OffsetDateTime::now_utc();
+    let this_week = now
+        .date()
+        .saturating_sub(time::Duration::days(
+            now.weekday().number_days_from_monday().into(),
+        ))
+        .midnight()
+        .assume_utc();
+    let last_week = this_week - time::Duration::weeks(1);
+
+    let current = state.history.summarize(this_week, now);
+    let previous = state.history.summarize(last_week, this_week);
+
+    let describe = |label: &str, summary: &PeriodSummary| {
+        let top: Vec<String> = summary
+            .top_adders
+            .iter()
+            .take(3)
+            .map(|(user, count)| format!("<@{}> ({})", user, count))
+            .collect();
+        let mut line = format!("• {}: {} track(s)", label, summary.adds);
+        if !top.is_empty() {
+            line.push_str(&format!(" – top: {}", top.join(", ")));
+        }
+        line
+    };
+    let trend = match current.adds.cmp(&previous.adds) {
+        std::cmp::Ordering::Greater => "📈",
+        std::cmp::Ordering::Less => "📉",
+        std::cmp::Ordering::Equal => "➡️",
+    };
+    CommandReply::in_channel(format!(
+        "*Weekly recap* {}\n{}\n{}\n_Counts adds since the bot last restarted._",
+        trend,
+        describe("This week", &current),
+        describe("Last week", &previous)
+    ))
+}
+
+/// Period `/jamcraft sources` covers when no number of days is given.
+const DEFAULT_SOURCES_DAYS: i64 = 30;
+/// History is capped anyway; this just keeps the date arithmetic in range.
+const MAX_SOURCES_DAYS: i64 = 3650;
+
+fn sources(state: &AppState, days: Option<&str>) -> CommandReply {
+    let days = match days.map(str::parse::<i64>) {
+        None => DEFAULT_SOURCES_DAYS,
+        Some(Ok(days)) if (1..=MAX_SOURCES_DAYS).contains(&days) => days,
+        Some(_) => return CommandReply::ephemeral("Usage: `/jamcraft sources [days]`"),
+    };
+    let now = OffsetDateTime::now_utc();
+    let summary = state
+        .history
+        .summarize(now - time::Duration::days(days), now);
+    let known: usize = summary.by_source.iter().map(|(_, count)| count).sum();
+    if known == 0 {
+        return CommandReply::in_channel(format!(
+            "No adds in the last {} day(s) to break down.",
+            days
+        ));
+    }
+
+    let lines: Vec<String> = summary
+        .by_source
+        .iter()
+        .map(|(source, count)| {
+            format!(
+                "• {}: {}% ({})",
+                source,
+                (count * 100 + known / 2) / known,
+                count
+            )
+        })
+        .collect();
+    CommandReply::in_channel(format!(
+        "*Where the last {} day(s) of adds came from*\n{}\n_Counts adds since the bot last restarted._",
+        days,
+        lines.join("\n")
+    ))
+}
+
+fn features(state: &AppState) -> CommandReply {
+    let lines: Vec<String> = state
+        .config
+        .features()
+        .into_iter()
+        .map(|(name, value)| match name {
+            // Switchable at runtime, so the startup value may be stale
+            "DRY_RUN" => format!(
+                "• `{}`: {} (now)",
+                name,
+                if state.dry_run() { "on" } else { "off" }
+            ),
+            // So are the reactions (`/jamcraft emoji`)
+            "REACTION_SUCCESS" => format!("• `{}`: :{}: (now)", name, state.emojis().success),
+            "REACTION_FAILURE" => format!("• `{}`: :{}: (now)", name, state.emojis().unresolved),
+            "REACTION_DUPLICATE" => format!("• `{}`: :{}: (now)", name, state.emojis().duplicate),
+            _ => format!("• `{}`: {}", name, value),
+        })
+        .collect();
+    CommandReply::ephemeral(format!("*Feature settings*\n{}", lines.join("\n")))
+}
+
+fn show_dry_run(state: &AppState) -> CommandReply {
+    CommandReply::ephemeral(if state.dry_run() {
+        "Dry run is *on*: tracks are resolved but not added to the playlist."
+    } else {
+        "Dry run is *off*: tracks are added to the playlist."
+    })
+}
+
+fn set_dry_run(state: &AppState, admin: &str, value: &str) -> CommandReply {
+    let on = match value {
+        "on" => true,
+        "off" => false,
+        _ => return CommandReply::ephemeral("Usage: `/jamcraft dryrun [on|off]`"),
+    };
+    state.dry_run.store(on, Ordering::Relaxed);
+    info!("{} turned dry run {}", admin, value);
+    CommandReply::in_channel(format!(
+        "<@{}> turned dry run {}: tracks {} be added to the playlist until the next restart or change.",
+        admin,
+        value,
+        if on { "won't" } else { "will" }
+    ))
+}
+
+fn show_emojis(state: &AppState) -> CommandReply {
+    let emojis = state.emojis();
+    let lines: Vec<String> = ReactionEmojis::KINDS
+        .iter()
+        .filter_map(|kind| {
+            let emoji = emojis.get(kind)?;
+            Some(format!("• {}: :{}: (`{}`)", kind, emoji, emoji))
+        })
+        .collect();
+    CommandReply::ephemeral(format!("*Reaction emojis*\n{}", lines.join("\n")))
+}
+
+/// Changes one reaction emoji for the running process. Config (env) still wins on restart.
+fn set_emoji(state: &AppState, admin: &str, kind: &str, value: Option<&str>) -> CommandReply {
+    let usage = format!(
+        "Usage: `/jamcraft emoji <kind> <:emoji:>`, where kind is one of {}",
+        ReactionEmojis::KINDS.join(", ")
+    );
+    let Some(value) = value else {
+        return CommandReply::ephemeral(usage);
+    };
+    let name = match parse_emoji_name(value) {
+        Ok(name) => name,
+        Err(e) => return CommandReply::ephemeral(e),
+    };
+
+    let mut emojis = state.emojis.write().unwrap();
+    let other_trigger = match kind {
+        "retry" => Some(&emojis.undo),
+        "undo" => Some(&emojis.retry),
+        _ => None,
+    };
+    if other_trigger == Some(&name) {
+        return CommandReply::ephemeral("The retry and undo emojis must differ.");
+    }
+    let Some(emoji) = emojis.get_mut(kind) else {
+        return CommandReply::ephemeral(usage);
+    };
+    let previous = std::mem::replace(emoji, name.clone());
+    info!(
+        "{} changed the {} emoji from {} to {}",
+        admin, kind, previous, name
+    );
+    CommandReply::ephemeral(format!(
+        "The {} emoji is now :{}: (was :{}:). This lasts until the bot restarts.",
+        kind, name, previous
+    ))
+}
+
+/// Live version of the `spotify_check` script: token scopes, playlist ownership and Slack auth.
+async fn diag(state: &AppState) -> CommandReply {
+    let mut lines = vec!["*jamcraft diagnostics*".to_string()];
+
+    match state.slack.auth_test().await {
+        Ok(auth) => lines.push(format!(
+            "• Slack: ✅ connected as @{} ({}) in {}",
+            auth.user, auth.user_id, auth.team
+        )),
+        Err(e) => lines.push(format!("• Slack: ❌ auth.test failed: {}", e)),
+    }
+
+    let Some(spotify) = &state.spotify else {
+        lines.push("• Spotify: ❌ not configured".to_string());
+        return CommandReply::in_channel(lines.join("\n"));
+    };
+
+    let access = spotify.playlist_access(&spotify.playlist_id()).await;
+    match spotify.granted_scopes().await {
+        Ok(scopes) => {
+            let scopes = scopes.unwrap_or_default();
+            lines.push(format!(
+                "• Spotify token: ✅ scopes: {}",
+                if scopes.is_empty() {
+                    "(none reported)"
+                } else {
+                    &scopes
+                }
+            ));
+            let missing = missing_scopes(&scopes, access.as_ref().ok());
+            if !missing.is_empty() {
+                lines.push(format!(
+                    "  ⚠️ missing scopes: {} (re-run `spotify_auth`)",
+                    missing.join(", ")
+                ));
+            }
+        }
+        Err(e) => {
+            lines.push(format!("• Spotify token: ❌ {}", e));
+            return CommandReply::in_channel(lines.join("\n"));
+        }
+    }
+
+    match access {
+        Ok(access) if access.can_modify() => lines.push(format!(
+            "• Playlist: ✅ \"{}\" (owner {}, bot account {})",
+            access.playlist_name, access.owner_id, access.user_id
+        )),
+        Ok(access) => lines.push(format!(
+            "• Playlist: ⚠️ \"{}\" is owned by {} but the token belongs to {} – make the playlist collaborative or re-auth as the owner",
+            access.playlist_name, access.owner_id, access.user_id
+        )),
+        Err(e) => lines.push(format!("• Playlist: ❌ {}", e)),
+    }
+
+    CommandReply::in_channel(lines.join("\n"))
+}
+
+/// Removes every repeat of a track already earlier in the playlist, keeping the first one.
+async fn dedupe(state: &AppState) -> CommandReply {
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let playlist_id = &spotify.playlist_id();
+
+    // The positions are only valid for the snapshot they were read at, which goes with them
+    let (snapshot_id, uris) = match spotify
+        .get_playlist_item_uris_at_snapshot(playlist_id)
+        .await
+    {
+        Ok(read) => read,
+        Err(e) => return CommandReply::ephemeral(format!("Couldn't read the playlist: {}", e)),
+    };
+
+    let duplicates = duplicate_positions(&uris);
+    if duplicates.is_empty() {
+        return CommandReply::ephemeral(format!(
+            "No duplicates in the playlist ({} tracks).",
+            uris.len()
+        ));
+    }
+    let count = duplicates.len();
+    if state.dry_run() || state.config.shadow_mode {
+        return CommandReply::ephemeral(format!("[DRY RUN] Would remove {} duplicate(s).", count));
+    }
+
+    let removed = spotify
+        .remove_positions(playlist_id, duplicates, snapshot_id)
+        .await;
+    // Even a failure may have removed some chunks, so the next add refetches the playlist
+    state.live_playlists.remove(playlist_id);
+    match removed {
+        Ok(_) => {
+            info!("Removed {} duplicate(s) from playlist", count);
+            CommandReply::in_channel(format!(
+                "Removed {} duplicate track(s) from the playlist 🧹",
+                count
+            ))
+        }
+        Err(e) => {
+            error!("Dedupe failed: {}", e);
+            CommandReply::ephemeral(format!("Removing duplicates failed: {}", e))
+        }
+    }
+}
+
+/// `cache stats` (default) or `cache clear` for the URL resolution cache. Clearing lets links
+/// cached as having no Spotify match be looked up again, e.g. once the track is on Spotify.
+fn cache(state: &AppState, action: Option<&str>) -> CommandReply {
+    match action.unwrap_or("stats") {
+        "clear" => {
+            let removed = state.resolve_cache.clear();
+            info!("Resolution cache cleared ({} entries)", removed);
+            CommandReply::ephemeral(format!("Cleared {} cached resolution(s).", removed))
+        }
+        "stats" => {
+            let stats = state.resolve_cache.stats();
+            CommandReply::ephemeral(format!(
+                "*Resolution cache*\n• Entries: {} ({} with no match)\n• Hits: {}\n• Misses: {}",
+                stats.entries, stats.negative_entries, stats.hits, stats.misses
+            ))
+        }
+        _ => CommandReply::ephemeral("Usage: `/jamcraft cache stats` or `/jamcraft cache clear`"),
+    }
+}
+
+/// A user ID from an escaped mention (`<@U123|name>` or `<@U123>`) or a bare ID.
+fn user_id_arg(arg: &str) -> Option<&str> {
+    let id = match arg.strip_prefix("<@") {
+        Some(mention) => mention.strip_suffix('>')?.split('|').next()?,
+        None => arg,
+    };
+    (id.len() > 1 && id.starts_with(['U', 'W']) && id.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then_some(id)
+}
+
+fn quarantine(state: &AppState, admin: &str, target: Option<&str>, release: bool) -> CommandReply {
+    let Some(playlist_id) = &state.config.quarantine_playlist_id else {
+        return CommandReply::ephemeral("Set QUARANTINE_PLAYLIST_ID to use quarantine.");
+    };
+    let Some(target) = target else {
+        if release {
+            return CommandReply::ephemeral("Usage: `/jamcraft unquarantine <@user>`");
+        }
+        if state.quarantined.is_empty() {
+            return CommandReply::ephemeral("Nobody is quarantined.");
+        }
+        let lines: Vec<String> = state
+            .quarantined
+            .iter()
+            .map(|entry| format!("• <@{}> (by <@{}>)", entry.key(), entry.value()))
+            .collect();
+        return CommandReply::ephemeral(format!(
+            "Adds from these users go to playlist {}:\n{}",
+            playlist_id,
+            lines.join("\n")
+        ));
+    };
+    let Some(user) = user_id_arg(target) else {
+        return CommandReply::ephemeral(
+            "Couldn't tell who that is; mention them as @user or give their user ID.",
+        );
+    };
+
+    if release {
+        if state.quarantined.remove(user).is_none() {
+            return CommandReply::ephemeral(format!("<@{}> isn't quarantined.", user));
+        }
+        info!("{} released {} from quarantine", admin, user);
+        CommandReply::ephemeral(format!(
+            "<@{}>'s adds go to the usual playlist again.",
+            user
+        ))
+    } else {
+        state
+            .quarantined
+            .insert(user.to_string(), admin.to_string());
+        info!("{} quarantined {}", admin, user);
+        CommandReply::ephemeral(format!(
+            "<@{}>'s adds now go to playlist {} until `/jamcraft unquarantine`. This lasts until the bot restarts.",
+            user, playlist_id
+        ))
+    }
+}
+
+fn show_target(state: &AppState) -> CommandReply {
+    match &state.spotify {
+        Some(spotify) => CommandReply::ephemeral(format!(
+            "Tracks are added to playlist {} (tagged messages follow PLAYLIST_ROUTING).",
+            spotify.playlist_id()
+        )),
+        None => CommandReply::ephemeral("Spotify is not configured."),
+    }
+}
+
+/// Switches the main playlist, but only to one the bot's Spotify account can add to, so the
+/// switch can't leave every add failing.
+async fn set_target(state: &AppState, admin: &str, arg: &str) -> CommandReply {
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let Some(playlist_id) = playlist_id_arg(arg) else {
+        return CommandReply::ephemeral(
+            "Usage: `/jamcraft target <playlist link, spotify:playlist: URI or ID>`",
+        );
+    };
+    let access = match spotify.verify_can_modify(playlist_id).await {
+        Ok(access) => access,
+        Err(reason) => {
+            return CommandReply::ephemeral(format!("Not switching: {}.", reason));
+        }
+    };
+
+    let previous = spotify.playlist_id();
+    spotify.set_playlist_id(playlist_id);
+    // Drop both snapshots rather than trust copies from before the switch, and let tracks
+    // recently added to the old playlist be added to the new one
+    state.live_playlists.remove(&previous);
+    state.live_playlists.remove(playlist_id);
+    state.forget_playlist_dedupe(&previous);
+    info!(
+        "{} switched the target playlist from {} to {}",
+        admin, previous, playlist_id
+    );
+    CommandReply::in_channel(format!(
+        "<@{}> switched the playlist: tracks now go to \"{}\" ({}). This lasts until the bot restarts.",
+        admin, access.playlist_name, playlist_id
+    ))
+}
+
+/// A playlist ID from a Spotify playlist link, `spotify:playlist:` URI or bare ID.
+fn playlist_id_arg(arg: &str) -> Option<&str> {
+    let arg = unwrap_slack_link(arg);
+    let id = match arg.split_once("open.spotify.com/") {
+        Some((_, path)) => {
+            // Localized links put the language first: /intl-de/playlist/ID
+            let path = match path.split_once('/') {
+                Some((locale, rest)) if locale.starts_with("intl-") => rest,
+                _ => path,
+            };
+            path.strip_prefix("playlist/")?.split(['?', '/']).next()?
+        }
+        None => arg.strip_prefix("spotify:playlist:").unwrap_or(arg),
+    };
+    (!id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric())).then_some(id)
+}
+
+/// Slack may send links in command text as `<url>` or `<url|label>`.
+fn unwrap_slack_link(arg: &str) -> &str {
+    let arg = arg.trim_start_matches('<').trim_end_matches('>');
+    arg.split('|').next().unwrap_or(arg)
+}
+
+/// Track ID (or `spotify:` URI for episodes) named directly by a `track` argument: a Spotify
+/// link, URI or bare ID. Other links need resolving first.
+fn direct_track_id(arg: &str) -> Option<String> {
+    if let Some(id) = parse_spotify_track_id(arg) {
+        return Some(id);
+    }
+    if let Some(id) = arg.strip_prefix("spotify:track:") {
+        return Some(id.to_string());
+    }
+    if arg.starts_with("spotify:") || arg.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Some(arg.to_string());
+    }
+    None
+}
+
+/// Everything the bot knows about one track: playlist position and add date, who added it
+/// (history store) and whether a re-post would be skipped by the dedupe window.
+async fn track(state: &AppState, arg: Option<&str>) -> CommandReply {
+    let Some(arg) = arg else {
+        return CommandReply::ephemeral("Usage: `/jamcraft track <url-or-id>`");
+    };
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let arg = unwrap_slack_link(arg);
+    let track_id = match direct_track_id(arg) {
+        Some(id) => id,
+        None => match resolve_url(state, arg.to_string(), None).await.track_id() {
+            Some(id) => id,
+            None => {
+                return CommandReply::ephemeral(format!(
+                    "Couldn't resolve {} to a Spotify track.",
+                    arg
+                ))
+            }
+        },
+    };
+
+    let mut lines = vec![format!("*Track `{}`*", track_id)];
+
+    let playlist_id = &spotify.playlist_id();
+    let uri = item_uri(&track_id);
+    match spotify.get_playlist_item_uris(playlist_id).await {
+        Ok(uris) => match uris.iter().position(|u| u.as_deref() == Some(uri.as_str())) {
+            Some(position) => {
+                let mut line = format!("• Playlist: ✅ at #{}", position + 1);
+                match spotify
+                    .playlist_entry_at(playlist_id, position as u64)
+                    .await
+                {
+                    Ok(Some(entry)) => {
+                        line.push_str(&format!(
+                            " – {} – {}",
+                            entry.meta.title,
+                            entry.meta.artists.join(", ")
+                        ));
+                        if let Some(added_at) = entry.added_at {
+                            line.push_str(&format!(
+                                ", added {}",
+                                &added_at[..10.min(added_at.len())]
+                            ));
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Failed to fetch playlist entry {}: {}", position, e),
+                }
+                lines.push(line);
+            }
+            None => lines.push("• Playlist: not in the playlist".to_string()),
+        },
+        Err(e) => lines.push(format!("• Playlist: ❌ couldn't read it: {}", e)),
+    }
+
+    match state.history.last_add_of(&track_id) {
+        Some(record) => lines.push(format!(
+            "• Added by: {}",
+            record
+                .user
+                .map(|u| format!("<@{}>", u))
+                .unwrap_or_else(|| "unknown user".to_string())
+        )),
+        None => lines.push("• Added by: not in this run's history".to_string()),
+    }
+
+    let dedupe_key = state.dedupe_key(&state.config.music_channel_id, playlist_id, &track_id);
+    // Expired entries linger until the cleanup task runs, so check the age too
+    let seen = state
+        .dedupe
+        .get(&dedupe_key)
+        .map(|seen| seen.elapsed())
+        .filter(|age| *age < state.config.dedupe_window);
+    match seen {
+        Some(age) => lines.push(format!(
+            "• Dedupe: ⏭️ seen {} min ago, re-posts are skipped for another {} min",
+            age.as_secs() / 60,
+            (state.config.dedupe_window - age).as_secs().div_ceil(60)
+        )),
+        None => lines.push("• Dedupe: not in the current window".to_string()),
+    }
+
+    CommandReply::ephemeral(lines.join("\n"))
+}
+
+/// Spotify throttling as seen by this process: last `Retry-After`, 429s in the last hour and
+/// whether requests are currently held back.
+fn ratelimit(state: &AppState) -> CommandReply {
+    let Some(spotify) = &state.spotify else {
+        return CommandReply::ephemeral("Spotify is not configured.");
+    };
+    let status = spotify.rate_limit_status();
+
+    let last = match status.last_retry_after {
+        Some((secs, ago)) => format!("{}s ({} min ago)", secs, ago.as_secs() / 60),
+        None => "never".to_string(),
+    };
+    let backoff = match status.paused_for {
+        Some(left) => format!("⏸️ paused for another {}s", left.as_secs().max(1)),
+        None => "✅ not paused".to_string(),
+    };
+    CommandReply::ephemeral(format!(
+        "*Spotify rate limits*\n• 429s in the last hour: {}\n• Last Retry-After: {}\n• Backoff: {}",
+        status.hits_last_hour, last, backoff
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{bodies, mock_state};
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, ResponseTemplate};
+
+    #[test]
+    fn missing_scopes_needs_the_modify_scope_for_the_playlists_visibility() {
+        let access = |public| PlaylistAccess {
+            user_id: "bot".to_string(),
+            playlist_name: "Jams".to_string(),
+            owner_id: "bot".to_string(),
+            collaborative: false,
+            public,
+        };
+        let private_only = "playlist-read-private playlist-modify-private";
+        assert!(missing_scopes(private_only, Some(&access(false))).is_empty());
+        assert_eq!(
+            missing_scopes(private_only, Some(&access(true))),
+            vec!["playlist-modify-public"]
+        );
+        assert!(missing_scopes(private_only, None).is_empty());
+        assert_eq!(
+            missing_scopes("playlist-modify-public", None),
+            vec!["playlist-read-private"]
+        );
+        assert_eq!(
+            missing_scopes("playlist-read-private", None),
+            vec!["playlist-modify-public or playlist-modify-private"]
+        );
+    }
+
+    #[tokio::test]
+    async fn curate_mode_holds_a_message_until_approved() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.curate_mode = true;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        crate::process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        assert!(state.pending.contains_key("C1:1.1"));
+        let reactions = bodies(&slack, "POST", "/reactions.add").await;
+        assert_eq!(reactions[0]["name"], state.emojis().pending.as_str());
+
+        let reply = approve(&state, "UM", "C1", None).await;
+        assert_eq!(
+            reply.to_json()["text"],
+            "Approved 1 message(s): added 1 track(s)"
+        );
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"] })]
+        );
+        assert!(state.pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn approve_keeps_a_message_pending_when_nothing_could_be_added() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.curate_mode = true;
+        Mock::given(method("POST"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(403))
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        crate::process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        let reply = approve(&state, "UM", "C2", Some("C1:1.1")).await;
+        assert_eq!(
+            reply.to_json()["text"],
+            "Approved 1 message(s): added 0 track(s), 1 failed; 1 message(s) added nothing and are still pending"
+        );
+        assert!(state.pending.contains_key("C1:1.1"));
+    }
+
+    #[tokio::test]
+    async fn shadow_mode_dedupe_only_reports_duplicates() {
+        let (_slack, spotify, mut state) = mock_state(&["abc", "abc"]).await;
+        state.config.shadow_mode = true;
+        Mock::given(method("GET"))
+            .and(path("/playlists/pl"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+
+        let reply = dedupe(&state).await;
+        assert_eq!(
+            reply.to_json()["text"],
+            "[DRY RUN] Would remove 1 duplicate(s)."
+        );
+        assert!(bodies(&spotify, "DELETE", "/playlists/pl/items")
+            .await
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn undo_puts_back_only_the_adds_a_failed_chunk_left_in_place() {
+        let (_slack, spotify, state) = mock_state(&[]).await;
+        let count = MAX_ITEMS_PER_REQUEST + 2;
+        for i in 0..count {
+            state.history.record(AddRecord {
+                track_id: format!("t{}", i),
+                playlist_id: "pl".to_string(),
+                user: None,
+                source: None,
+                added_at: OffsetDateTime::now_utc(),
+            });
+        }
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(403))
+            .mount(&spotify)
+            .await;
+
+        let reply = undo(&state, "UA", Some(&count.to_string())).await;
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl/items")
+                .await
+                .len(),
+            2
+        );
+        let text = reply.to_json()["text"].as_str().unwrap().to_string();
+        assert!(text.contains(&format!(
+            "removed the last {} added track(s)",
+            MAX_ITEMS_PER_REQUEST
+        )));
+        assert!(text.contains("Couldn't remove 2 from playlist pl"));
+        let left: Vec<String> = state
+            .history
+            .take_latest(count)
+            .into_iter()
+            .map(|r| r.track_id)
+            .collect();
+        assert_eq!(left, vec!["t1", "t0"]);
+    }
+
+    #[test]
+    fn user_id_arg_reads_mentions_and_ids() {
+        assert_eq!(user_id_arg("<@U123ABC|sam>"), Some("U123ABC"));
+        assert_eq!(user_id_arg("<@W42>"), Some("W42"));
+        assert_eq!(user_id_arg("U999"), Some("U999"));
+        assert_eq!(user_id_arg("@sam"), None);
+        assert_eq!(user_id_arg("<#C123|general>"), None);
+    }
+
+    #[test]
+    fn recent_line_says_who_added_a_track_and_when() {
+        let now = OffsetDateTime::now_utc();
+        let record = |user: Option<&str>, track_id: &str, minutes| AddRecord {
+            track_id: track_id.to_string(),
+            playlist_id: "pl".to_string(),
+            user: user.map(str::to_string),
+            source: None,
+            added_at: now - time::Duration::minutes(minutes),
+        };
+        assert_eq!(
+            recent_line(&record(Some("U1"), "abc", 5), now),
+            "• https://open.spotify.com/track/abc – added by <@U1>, 5 min ago"
+        );
+        assert_eq!(
+            recent_line(&record(None, "spotify:episode:xyz", 3 * 60), now),
+            "• `spotify:episode:xyz` – added by an import, 3 h ago"
+        );
+        assert!(recent_line(&record(Some("U1"), "abc", 3 * 1440), now).ends_with("3 days ago"));
+    }
+
+    #[test]
+    fn trace_report_caps_the_log() {
+        let lines: Vec<String> = (0..200)
+            .map(|i| format!("DEBUG step {} of a long resolution", i))
+            .collect();
+        let report = trace_report("https://example.com/a", None, &lines);
+        assert!(report.len() <= MAX_TRACE_CHARS + 100);
+        assert!(report.contains("more line(s) cut"));
+        assert!(report.ends_with("Outcome: unresolved"));
+
+        let report = trace_report("https://example.com/a", Some("abc"), &[]);
+        assert!(report.contains("(nothing logged)"));
+        assert!(report.ends_with("resolved to `abc`"));
+    }
+
+    #[test]
+    fn playlist_id_arg_accepts_links_uris_and_ids() {
+        let id = Some("37i9dQZF1DX4WYpdgoIcn6");
+        assert_eq!(
+            playlist_id_arg("<https://open.spotify.com/playlist/37i9dQZF1DX4WYpdgoIcn6?si=x>"),
+            id
+        );
+        assert_eq!(
+            playlist_id_arg("spotify:playlist:37i9dQZF1DX4WYpdgoIcn6"),
+            id
+        );
+        assert_eq!(playlist_id_arg("37i9dQZF1DX4WYpdgoIcn6"), id);
+        assert_eq!(
+            playlist_id_arg("https://open.spotify.com/intl-pt-BR/playlist/37i9dQZF1DX4WYpdgoIcn6"),
+            id
+        );
+        assert_eq!(playlist_id_arg("https://open.spotify.com/track/abc"), None);
+    }
+
+    #[test]
+    fn direct_track_id_accepts_links_uris_and_ids() {
+        let id = Some("4cOdK2wGLETKBW3PvgPWqT".to_string());
+        assert_eq!(
+            direct_track_id(unwrap_slack_link(
+                "<https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT?si=x|open.spotify.com>"
+            )),
+            id
+        );
+        assert_eq!(direct_track_id("spotify:track:4cOdK2wGLETKBW3PvgPWqT"), id);
+        assert_eq!(direct_track_id("4cOdK2wGLETKBW3PvgPWqT"), id);
+        assert_eq!(
+            direct_track_id("spotify:episode:abc").as_deref(),
+            Some("spotify:episode:abc")
+        );
+        assert_eq!(direct_track_id("https://youtu.be/dQw4w9WgXcQ"), None);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::history::PeriodSummary;
+use crate::AppState;
+use time::{OffsetDateTime, PrimitiveDateTime, Time};
+use time_tz::{OffsetDateTimeExt, OffsetResult, PrimitiveDateTimeExt, Tz};
+use tracing::{error, info};
+
+/// Posts the day's add count to the music channel at `DAILY_SUMMARY_TIME` every day, for as
+/// long as the process runs. Days without adds are skipped rather than posting a zero.
+pub async fn run(state: AppState, at: Time) {
+    let tz = state.config.daily_summary_tz;
+    loop {
+        let now = OffsetDateTime::now_utc();
+        let next = next_run(now, at, tz);
+        info!("Next daily summary at {}", next);
+        tokio::time::sleep((next - now).unsigned_abs()).await;
+
+        let now = OffsetDateTime::now_utc();
+        let summary = state.history.summarize(start_of_day(now, tz), now);
+        let Some(text) = summary_text(&summary) else {
+            info!("No adds today, skipping the daily summary");
+            continue;
+        };
+        if let Err(e) = state
+            .slack
+            .chat_post_message(&state.config.music_channel_id, None, &text)
+            .await
+        {
+            error!("Failed to post daily summary: {}", e);
+        }
+    }
+}
+
+/// The first `at` (local time in `tz`) strictly after `now`.
+fn next_run(now: OffsetDateTime, at: Time, tz: &Tz) -> OffsetDateTime {
+    let today = now.to_timezone(tz).date();
+    [today, today.next_day().unwrap_or(today)]
+        .into_iter()
+        .map(|date| local_to_utc(PrimitiveDateTime::new(date, at), tz))
+        .find(|&candidate| candidate > now)
+        .unwrap_or_else(|| now + time::Duration::days(1))
+}
+
+/// Local midnight in `tz` on the day `now` falls on there.
+fn start_of_day(now: OffsetDateTime, tz: &Tz) -> OffsetDateTime {
+    local_to_utc(now.to_timezone(tz).date().midnight(), tz)
+}
+
+/// The instant a local time in `tz` refers to. A time repeated when clocks go back takes the
+/// first occurrence; one skipped when they go forward runs an hour later.
+fn local_to_utc(local: PrimitiveDateTime, tz: &Tz) -> OffsetDateTime {
+    match local.assume_timezone(tz) {
+        OffsetResult::Some(at) | OffsetResult::Ambiguous(at, _) => at,
+        OffsetResult::None => (local + time::Duration::hours(1))
+            .assume_timezone(tz)
+            .unwrap_first(),
+    }
+}
+
+fn summary_text(summary: &PeriodSummary) -> Option<String> {
+    if summary.adds == 0 {
+        return None;
+    }
+    let contributors = summary.top_adders.len();
+    Some(format!(
+        "Today we added {} track{} from {} contributor{} 🎵",
+        summary.adds,
+        if summary.adds == 1 { "" } else { "s" },
+        contributors,
+        if contributors == 1 { "" } else { "s" }
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{datetime, time};
+
+    #[test]
+    fn next_run_follows_the_local_clock_across_dst() {
+        let amsterdam = time_tz::timezones::get_by_name("Europe/Amsterdam").unwrap();
+        // 16:00 UTC is 18:00 in summer (UTC+2), so 18:00 local has just passed
+        assert_eq!(
+            next_run(datetime!(2026-07-01 16:00 UTC), time!(18:00), amsterdam),
+            datetime!(2026-07-02 16:00 UTC)
+        );
+        assert_eq!(
+            next_run(datetime!(2026-07-01 15:59 UTC), time!(18:00), amsterdam),
+            datetime!(2026-07-01 16:00 UTC)
+        );
+        // Clocks go back overnight on 25 October, so the next 18:00 is 17:00 UTC
+        assert_eq!(
+            next_run(datetime!(2026-10-24 17:00 UTC), time!(18:00), amsterdam),
+            datetime!(2026-10-25 17:00 UTC)
+        );
+        assert_eq!(
+            start_of_day(datetime!(2026-07-01 23:30 UTC), amsterdam),
+            datetime!(2026-07-01 22:00 UTC)
+        );
+    }
+
+    #[test]
+    fn summary_text_counts_tracks_and_contributors() {
+        let summary = |adds, adders: &[&str]| PeriodSummary {
+            adds,
+            top_adders: adders.iter().map(|u| (u.to_string(), 1)).collect(),
+            by_source: Vec::new(),
+        };
+        assert_eq!(summary_text(&summary(0, &[])), None);
+        assert_eq!(
+            summary_text(&summary(1, &["U1"])).as_deref(),
+            Some("Today we added 1 track from 1 contributor 🎵")
+        );
+        assert_eq!(
+            summary_text(&summary(5, &["U1", "U2"])).as_deref(),
+            Some("Today we added 5 tracks from 2 contributors 🎵")
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use time::OffsetDateTime;
+
+/// How many adds are remembered. Older ones fall off the front.
+pub const HISTORY_CAPACITY: usize = 5000;
+
+/// One track added by the bot on behalf of a Slack message.
+#[derive(Debug, Clone)]
+pub struct AddRecord {
+    pub track_id: String,
+    /// Playlist it went to
+    pub playlist_id: String,
+    pub user: Option<String>,
+    /// Platform the link was shared from, e.g. "YouTube" (see `resolve::platform`)
+    pub source: Option<&'static str>,
+    pub added_at: OffsetDateTime,
+}
+
+/// Adds over some period, for `/jamcraft recap`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodSummary {
+    pub adds: usize,
+    /// Users by number of adds, most first (ties by user ID)
+    pub top_adders: Vec<(String, usize)>,
+    /// Adds per source platform, most first (ties by name), for `/jamcraft sources`
+    pub by_source: Vec<(&'static str, usize)>,
+}
+
+/// Bounded in-memory log of recent adds, so commands can say who added a track. Lost on
+/// restart; Spotify itself only knows the bot account added everything.
+pub struct AddHistory {
+    capacity: usize,
+    records: Mutex<VecDeque<AddRecord>>,
+}
+
+impl AddHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, record: AddRecord) {
+        let mut records = self.records.lock().unwrap();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+
+    /// The most recent add of a track (bare ID or `spotify:` URI, as it was added).
+    pub fn last_add_of(&self, track_id: &str) -> Option<AddRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|r| r.track_id == track_id)
+            .cloned()
+    }
+
+    /// The `n` most recent adds, newest first, left in the history.
+    pub fn latest(&self, n: usize) -> Vec<AddRecord> {
+        self.records
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(n)
+            .cloned()
+            .collect()
+    }
+
+    /// Takes the `n` most recent adds out of the history, newest first, e.g. to roll them back.
+    pub fn take_latest(&self, n: usize) -> Vec<AddRecord> {
+        let mut records = self.records.lock().unwrap();
+        let keep = records.len().saturating_sub(n);
+        records.split_off(keep).into_iter().rev().collect()
+    }
+
+    /// Adds recorded in `[from, to)`.
+    pub fn summarize(&self, from: OffsetDateTime, to: OffsetDateTime) -> PeriodSummary {
+        let records = self.records.lock().unwrap();
+        let in_period: Vec<&AddRecord> = records
+            .iter()
+            .filter(|r| r.added_at >= from && r.added_at < to)
+            .collect();
+
+        let mut per_user: HashMap<&str, usize> = HashMap::new();
+        let mut per_source: HashMap<&'static str, usize> = HashMap::new();
+        for record in &in_period {
+            if let Some(user) = record.user.as_deref() {
+                *per_user.entry(user).or_default() += 1;
+            }
+            if let Some(source) = record.source {
+                *per_source.entry(source).or_default() += 1;
+            }
+        }
+        let mut top_adders: Vec<(String, usize)> = per_user
+            .into_iter()
+            .map(|(user, count)| (user.to_string(), count))
+            .collect();
+        top_adders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let mut by_source: Vec<(&'static str, usize)> = per_source.into_iter().collect();
+        by_source.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        PeriodSummary {
+            adds: in_period.len(),
+            top_adders,
+            by_source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add(track_id: &str, user: &str) -> AddRecord {
+        AddRecord {
+            track_id: track_id.to_string(),
+            playlist_id: "pl".to_string(),
+            user: Some(user.to_string()),
+            source: Some("Spotify"),
+            added_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    #[test]
+    fn take_latest_removes_the_newest_adds() {
+        let history = AddHistory::new(10);
+        for track_id in ["a", "b", "c"] {
+            history.record(add(track_id, "U1"));
+        }
+        let taken: Vec<String> = history
+            .take_latest(2)
+            .into_iter()
+            .map(|r| r.track_id)
+            .collect();
+        assert_eq!(taken, ["c", "b"]);
+        assert!(history.last_add_of("a").is_some());
+        assert!(history.last_add_of("b").is_none());
+        assert_eq!(history.latest(5).len(), 1);
+        assert_eq!(history.take_latest(5).len(), 1);
+    }
+
+    #[test]
+    fn history_keeps_latest_and_evicts_oldest() {
+        let history = AddHistory::new(2);
+        history.record(add("a", "U1"));
+        history.record(add("b", "U2"));
+        history.record(add("b", "U3"));
+
+        assert!(history.last_add_of("a").is_none());
+        assert_eq!(
+            history.last_add_of("b").and_then(|r| r.user).as_deref(),
+            Some("U3")
+        );
+    }
+
+    #[test]
+    fn summarize_counts_adds_in_period_by_user() {
+        let history = AddHistory::new(10);
+        let now = OffsetDateTime::now_utc();
+        let week = time::Duration::weeks(1);
+        for (user, source, added_at) in [
+            ("U1", "YouTube", now),
+            ("U2", "Spotify", now),
+            ("U2", "Spotify", now),
+            ("U1", "YouTube", now - week),
+        ] {
+            history.record(AddRecord {
+                source: Some(source),
+                added_at,
+                ..add("t", user)
+            });
+        }
+
+        let summary = history.summarize(now - time::Duration::hours(1), now + week);
+        assert_eq!(summary.adds, 3);
+        assert_eq!(
+            summary.top_adders,
+            vec![("U2".to_string(), 2), ("U1".to_string(), 1)]
+        );
+        assert_eq!(summary.by_source, vec![("Spotify", 2), ("YouTube", 1)]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{undo_added_tracks, verify_slack_request, AppState, UndoOutcome};
+use axum::{extract::State, http::HeaderMap, http::StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+/// `action_id` of the success message's undo button (`UNDO_BUTTON`). Its `value` is the
+/// `channel:ts` of the message whose tracks it removes.
+pub const UNDO_ACTION_ID: &str = "undo_add";
+
+/// A `block_actions` payload: someone pressed a button on one of the bot's messages.
+#[derive(Debug, Deserialize)]
+pub struct BlockActions {
+    #[serde(rename = "type")]
+    pub payload_type: String,
+    pub user: InteractionUser,
+    /// Where to send the answer; replaces or follows up on the message
+    pub response_url: String,
+    #[serde(default)]
+    pub actions: Vec<BlockAction>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InteractionUser {
+    pub id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockAction {
+    pub action_id: String,
+    pub value: Option<String>,
+}
+
+/// The form body Slack POSTs to the interactivity request URL.
+#[derive(Deserialize)]
+struct InteractionForm {
+    payload: String,
+}
+
+/// The success message as Block Kit, with an undo button for the message `message_key`.
+pub fn undo_blocks(text: &str, label: &str, message_key: &str) -> Value {
+    json!([
+        { "type": "section", "text": { "type": "mrkdwn", "text": text } },
+        {
+            "type": "actions",
+            "elements": [{
+                "type": "button",
+                "text": { "type": "plain_text", "text": label },
+                "action_id": UNDO_ACTION_ID,
+                "value": message_key,
+            }]
+        }
+    ])
+}
+
+pub async fn slack_interactions_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> StatusCode {
+    if let Err(status) = verify_slack_request(&state, &headers, &body) {
+        return status;
+    }
+    let payload = serde_urlencoded::from_bytes::<InteractionForm>(&body)
+        .map_err(|e| e.to_string())
+        .and_then(|form| serde_json::from_str(&form.payload).map_err(|e| e.to_string()));
+    match payload {
+        Ok(payload) => {
+            handle_interaction(state, payload);
+            StatusCode::OK
+        }
+        Err(e) => {
+            warn!("Failed to parse interaction payload: {}", e);
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+/// Handles an interaction, however it arrived (HTTP or Socket Mode). Work is spawned, so this
+/// returns right away and the interaction can be acknowledged within Slack's 3 seconds.
+pub fn handle_interaction(state: AppState, payload: Value) {
+    let interaction: BlockActions = match serde_json::from_value(payload) {
+        Ok(interaction) => interaction,
+        Err(e) => {
+            warn!("Ignoring interaction with an unexpected shape: {}", e);
+            return;
+        }
+    };
+    if interaction.payload_type != "block_actions" {
+        return;
+    }
+    for action in interaction.actions {
+        match (action.action_id.as_str(), action.value) {
+            (UNDO_ACTION_ID, Some(message_key)) => {
+                let state = state.clone();
+                let user = interaction.user.id.clone();
+                let response_url = interaction.response_url.clone();
+                tokio::spawn(async move {
+                    undo_button(&state, &message_key, &user, &response_url).await;
+                });
+            }
+            (action_id, _) => info!("Ignoring unknown action {}", action_id),
+        }
+    }
+}
+
+/// Removes what the message added and swaps the success message for `undone`, or tells just
+/// the presser why not.
+pub(crate) async fn undo_button(
+    state: &AppState,
+    message_key: &str,
+    user: &str,
+    response_url: &str,
+) {
+    let texts = &state.config.messages;
+    let reply = match undo_added_tracks(state, message_key, user).await {
+        UndoOutcome::Removed(_) => json!({ "replace_original": true, "text": texts.undone }),
+        UndoOutcome::NothingToUndo => json!({
+            "response_type": "ephemeral",
+            "replace_original": false,
+            "text": texts.undo_expired,
+        }),
+        UndoOutcome::NotAllowed => json!({
+            "response_type": "ephemeral",
+            "replace_original": false,
+            "text": texts.undo_not_allowed,
+        }),
+        UndoOutcome::Failed => json!({
+            "response_type": "ephemeral",
+            "replace_original": false,
+            "text": texts.failure,
+        }),
+    };
+    if let Err(e) = state.slack.post_response_url(response_url, &reply).await {
+        warn!("Failed to answer undo button on {}: {}", message_key, e);
+    }
+}
@@ -1,57 +1,600 @@
+mod cache;
+mod commands;
+mod config;
+mod daily_summary;
+mod history;
+mod interactions;
+mod matching;
+mod messages;
+mod metrics;
+mod notify;
+mod pacing;
 mod resolve;
+mod resolvers;
 mod slack;
+mod socket_mode;
 mod spotify;
+mod telemetry;
 mod types;
+mod voting;
 
 use axum::{
     extract::State,
-    http::{HeaderMap, StatusCode},
-    response::Json,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json},
     routing::{get, post},
     Router,
 };
+use cache::ResolveCache;
+use commands::PendingAdd;
+use config::{
+    Config, ConfirmationThreading, DedupeScope, DuplicateBehavior, NonMusicUrlBehavior,
+    PlaylistRoute, ReactionEmojis,
+};
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
+use futures::{stream, StreamExt};
+use history::{AddHistory, AddRecord, HISTORY_CAPACITY};
+use metrics::Metrics;
+use notify::{TracksAdded, WebhookNotifier};
+use pacing::AdaptiveDelay;
 use resolve::{
-    extract_urls, fetch_qobuz_track_metadata, parse_qobuz_track_id, resolve_to_spotify_track_id,
+    classify_url, extract_urls, fetch_qobuz_track_metadata, is_spotify_collection,
+    parse_qobuz_track_id, parse_spotify_show_id, parse_spotify_track_id,
+    resolve_to_spotify_track_id, strip_tracking_params, UnresolvedReason,
 };
+use resolvers::{build_resolvers, Resolution, UrlResolver};
 use serde_json::{json, Value};
 use slack::SlackWebClient;
-use spotify::SpotifyClient;
-use std::sync::Arc;
+use spotify::{SpotifyClient, SpotifyError};
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn};
 use types::SlackEnvelope;
 
+/// Maximum number of URLs from one message resolved at the same time.
+const RESOLVE_CONCURRENCY: usize = 5;
+
+/// How long a handled message is remembered, so a Slack retry or the backfill seeing it again
+/// doesn't react or reply twice.
+const PROCESSED_MESSAGE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// How long a delivered event's ID is remembered. Slack retries a delivery it got no timely
+/// answer for up to three times, the last about five minutes later.
+const SEEN_EVENT_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// How long an accepted request signature is remembered. A timestamp may be up to
+/// `SIGNATURE_MAX_SKEW` ahead of our clock and is accepted until it's as far behind, so twice
+/// the skew covers every moment the request could still verify.
+const SEEN_SIGNATURE_WINDOW: Duration =
+    Duration::from_secs(2 * slack::SIGNATURE_MAX_SKEW.as_secs());
+
+/// How long a fetched playlist's track IDs and titles are trusted before the next add refetches
+/// them (`SKIP_EXISTING_PLAYLIST_TRACKS`, `DEDUPE_BY_TITLE`).
+const PLAYLIST_TRACKS_REFRESH: Duration = Duration::from_secs(5 * 60);
+
+/// A playlist's track IDs as of `fetched_at`, plus the bot's own adds and removals since, and
+/// the title keys of its tracks as of `fetched_at`. Shared, so a message can check against it
+/// without copying the sets.
+#[derive(Clone)]
+struct PlaylistSnapshot {
+    fetched_at: Instant,
+    tracks: Arc<HashSet<String>>,
+    titles: Arc<HashSet<String>>,
+}
+
+/// Pause before re-running the resolution pipeline for a URL that failed.
+const RESOLVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
 #[derive(Clone)]
 struct AppState {
     slack: Arc<SlackWebClient>,
     spotify: Option<Arc<SpotifyClient>>,
     config: Config,
+    /// Reaction emojis, editable at runtime via `/jamcraft emoji`
+    emojis: Arc<RwLock<ReactionEmojis>>,
     dedupe: Arc<DashMap<String, Instant>>,
-    dry_run: bool,
+    /// Messages already handled, keyed by `channel:ts`, so Slack retries and backfill
+    /// don't react/reply to the same message twice
+    processed_messages: Arc<DashMap<String, Instant>>,
+    /// Event deliveries already handled, keyed by `event_id`, so Slack's retries of a slow
+    /// delivery are dropped
+    seen_events: Arc<DashMap<String, Instant>>,
+    /// Signatures of verified Slack requests, so a captured request can't be replayed
+    seen_signatures: Arc<DashMap<String, Instant>>,
+    /// Curate mode: resolved tracks waiting for moderator approval, keyed by message ts
+    pending: Arc<DashMap<String, PendingAdd>>,
+    /// What each message added, keyed by `channel:ts`, so the undo reaction can remove it
+    added_tracks: Arc<DashMap<String, AddedTracks>>,
+    /// Confirmation messages open to `REACTION_VOTING`, keyed by their own `channel:ts`
+    vote_cards: Arc<DashMap<String, voting::VoteCard>>,
+    /// Tracks removed via undo, with when, for `WARN_ON_READD`
+    recently_removed: Arc<DashMap<String, Instant>>,
+    /// `matching::title_key` of each track the bot added under `DEDUPE_BY_TITLE`, keyed by
+    /// `playlist/track`, so removing the track also ends its title's dedupe window
+    added_titles: Arc<DashMap<String, String>>,
+    /// URL → track ID results, so reposts of a link skip Odesli
+    resolve_cache: Arc<ResolveCache>,
+    /// Who added which track, for commands that report on the playlist
+    history: Arc<AddHistory>,
+    /// Counters served at `GET /metrics`
+    metrics: Arc<Metrics>,
+    /// Playlists' track IDs by playlist ID, as of the last reconcile of the main playlist
+    /// (`PLAYLIST_RECONCILE_INTERVAL_SECS`) or the last add that found its copy too old, so
+    /// adds can skip tracks already there (`SKIP_EXISTING_PLAYLIST_TRACKS`)
+    live_playlists: Arc<DashMap<String, PlaylistSnapshot>>,
+    /// Users whose adds go to `QUARANTINE_PLAYLIST_ID`, with the admin who quarantined them
+    quarantined: Arc<DashMap<String, String>>,
+    /// When each user last posted links, for `USER_ADD_COOLDOWN_SECS`
+    last_user_add: Arc<DashMap<String, Instant>>,
+    /// Names of the episodes show links resolved to, by episode URI, so the reply can say
+    /// which one was added without looking it up again (`ALLOW_SHOW_LATEST`)
+    latest_episodes: Arc<DashMap<String, String>>,
+    /// Set while a `/jamcraft import` runs, so only one bulk import happens at a time
+    import_running: Arc<AtomicBool>,
+    /// Link → Spotify backends, tried in `RESOLVERS` order
+    resolvers: Arc<Vec<Box<dyn UrlResolver>>>,
+    /// Posts added tracks to `NOTIFY_WEBHOOK_URL`, if set
+    notifier: Option<Arc<WebhookNotifier>>,
+    /// The bot's own Slack user ID (from `auth.test`), used to ignore its own messages
+    bot_user_id: Option<String>,
+    /// `DRY_RUN` at startup, switchable with `/jamcraft dryrun`
+    dry_run: Arc<AtomicBool>,
 }
 
-#[derive(Clone)]
-struct Config {
-    signing_secret: String,
-    music_channel_id: String,
+impl AppState {
+    fn new(
+        config: Config,
+        slack: Arc<SlackWebClient>,
+        spotify: Option<Arc<SpotifyClient>>,
+        bot_user_id: Option<String>,
+    ) -> Self {
+        Self {
+            slack,
+            spotify: spotify.clone(),
+            emojis: Arc::new(RwLock::new(ReactionEmojis::from_config(&config))),
+            resolvers: Arc::new(build_resolvers(
+                &config.resolvers,
+                spotify,
+                config.entity_search_threshold,
+            )),
+            live_playlists: Arc::new(DashMap::new()),
+            quarantined: Arc::new(DashMap::new()),
+            last_user_add: Arc::new(DashMap::new()),
+            latest_episodes: Arc::new(DashMap::new()),
+            import_running: Arc::new(AtomicBool::new(false)),
+            dedupe: Arc::new(DashMap::new()),
+            processed_messages: Arc::new(DashMap::new()),
+            seen_events: Arc::new(DashMap::new()),
+            seen_signatures: Arc::new(DashMap::new()),
+            pending: Arc::new(DashMap::new()),
+            added_tracks: Arc::new(DashMap::new()),
+            vote_cards: Arc::new(DashMap::new()),
+            recently_removed: Arc::new(DashMap::new()),
+            added_titles: Arc::new(DashMap::new()),
+            resolve_cache: Arc::new(ResolveCache::new(config.resolve_cache_ttl)),
+            history: Arc::new(AddHistory::new(HISTORY_CAPACITY)),
+            metrics: Arc::new(Metrics::default()),
+            notifier: config
+                .notify_webhook_url
+                .clone()
+                .map(|url| Arc::new(WebhookNotifier::new(url))),
+            bot_user_id,
+            dry_run: Arc::new(AtomicBool::new(config.dry_run)),
+            config,
+        }
+    }
+
+    /// Current reaction emojis (a copy, so no lock is held across awaits).
+    fn emojis(&self) -> ReactionEmojis {
+        self.emojis.read().unwrap().clone()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.dry_run.load(Ordering::Relaxed)
+    }
+
+    /// `playlist_id`'s snapshot, refetched once it's older than `PLAYLIST_TRACKS_REFRESH`. A
+    /// failed refetch falls back to the stale snapshot; `None` if there is none, or neither
+    /// `SKIP_EXISTING_PLAYLIST_TRACKS` nor `DEDUPE_BY_TITLE` needs it.
+    async fn existing_playlist(
+        &self,
+        spotify: &SpotifyClient,
+        playlist_id: &str,
+    ) -> Option<PlaylistSnapshot> {
+        if !self.config.skip_existing_playlist_tracks && !self.config.dedupe_by_title {
+            return None;
+        }
+        let cached = self
+            .live_playlists
+            .get(playlist_id)
+            .map(|snapshot| snapshot.clone());
+        if let Some(snapshot) = &cached {
+            if snapshot.fetched_at.elapsed() < PLAYLIST_TRACKS_REFRESH {
+                return cached;
+            }
+        }
+        refresh_playlist(self, spotify, playlist_id)
+            .await
+            .or(cached)
+    }
+
+    /// Applies one of the bot's own changes to `playlist_id`'s snapshot, if there is one.
+    fn update_live_playlist(&self, playlist_id: &str, update: impl FnOnce(&mut PlaylistSnapshot)) {
+        if let Some(mut snapshot) = self.live_playlists.get_mut(playlist_id) {
+            update(&mut snapshot);
+        }
+    }
+
+    /// Key in `dedupe` for a track ID or `title:` key added from `channel` to `playlist_id`.
+    /// Only `DEDUPE_SCOPE=per_channel` keeps the channel and playlist in it.
+    fn dedupe_key(&self, channel: &str, playlist_id: &str, key: &str) -> String {
+        match self.config.dedupe_scope {
+            DedupeScope::Global => key.to_string(),
+            DedupeScope::PerChannel => format!("{}/{}/{}", channel, playlist_id, key),
+        }
+    }
+
+    /// Starts the dedupe window for `key` unless it's already open, in one step, so a live
+    /// message and the backfill can't both claim the same track. `false` if it was open.
+    fn reserve_dedupe(&self, key: String, now: Instant) -> bool {
+        match self.dedupe.entry(key) {
+            Entry::Occupied(entry)
+                if now.saturating_duration_since(*entry.get()) < self.config.dedupe_window =>
+            {
+                false
+            }
+            Entry::Occupied(mut entry) => {
+                entry.insert(now);
+                true
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(now);
+                true
+            }
+        }
+    }
+
+    /// Undoes a [`reserve_dedupe`](Self::reserve_dedupe) for a track that wasn't added after
+    /// all. Left alone if something else has started the window since.
+    fn release_dedupe(&self, key: &str, reserved_at: Instant) {
+        self.dedupe.remove_if(key, |_, at| *at == reserved_at);
+    }
+
+    /// Ends the dedupe window for a track in `playlist_id`, whichever channel added it.
+    fn forget_dedupe(&self, playlist_id: &str, track_id: &str) {
+        match self.config.dedupe_scope {
+            DedupeScope::Global => {
+                self.dedupe.remove(track_id);
+            }
+            DedupeScope::PerChannel => {
+                let suffix = format!("/{}/{}", playlist_id, track_id);
+                self.dedupe.retain(|key, _| !key.ends_with(&suffix));
+            }
+        }
+    }
+
+    /// Ends every dedupe window for `playlist_id`, for when it stops being the main playlist.
+    /// `DEDUPE_SCOPE=global` keys don't say which playlist they're for, so all of them go.
+    fn forget_playlist_dedupe(&self, playlist_id: &str) {
+        match self.config.dedupe_scope {
+            DedupeScope::Global => self.dedupe.clear(),
+            DedupeScope::PerChannel => self
+                .dedupe
+                .retain(|key, _| key.split('/').nth(1) != Some(playlist_id)),
+        }
+    }
+}
+
+/// Pause between backfill adds: starts at the minimum, doubles on each 429 up to the maximum
+/// and shrinks by the step after every add that wasn't throttled.
+const MIN_BACKFILL_ADD_DELAY: Duration = Duration::from_millis(20);
+const MAX_BACKFILL_ADD_DELAY: Duration = Duration::from_secs(10);
+const BACKFILL_ADD_DELAY_STEP: Duration = Duration::from_millis(20);
+
+/// Refetches the main playlist on the `PLAYLIST_RECONCILE_INTERVAL_SECS` schedule.
+async fn reconcile_playlist(state: &AppState, spotify_client: &SpotifyClient) {
+    refresh_playlist(state, spotify_client, &spotify_client.playlist_id()).await;
+}
+
+/// Refetches a playlist's snapshot and compares it with the last one, logging tracks added or
+/// removed outside the bot. Removed tracks are dropped from dedupe so re-posting them adds them
+/// again instead of being skipped as a recent duplicate. `None` if the fetch failed.
+async fn refresh_playlist(
+    state: &AppState,
+    spotify_client: &SpotifyClient,
+    playlist_id: &str,
+) -> Option<PlaylistSnapshot> {
+    let contents = match spotify_client.get_playlist_contents(playlist_id).await {
+        Ok(contents) => contents,
+        Err(e) => {
+            warn!("Couldn't refresh the tracks of {}: {}", playlist_id, e);
+            return None;
+        }
+    };
+    let snapshot = PlaylistSnapshot {
+        fetched_at: Instant::now(),
+        tracks: Arc::new(contents.track_ids),
+        titles: Arc::new(contents.title_keys),
+    };
+    let current = snapshot.tracks.clone();
+    let previous = state
+        .live_playlists
+        .insert(playlist_id.to_string(), snapshot.clone());
+    let Some(previous) = previous else {
+        info!(
+            "Playlist {}: tracking {} tracks",
+            playlist_id,
+            current.len()
+        );
+        return Some(snapshot);
+    };
+
+    let (added, removed) = playlist_changes(&previous.tracks, &current);
+    if !added.is_empty() || !removed.is_empty() {
+        info!(
+            "Playlist {}: {} track(s) added and {} removed outside the bot since the last check (added: {:?}, removed: {:?})",
+            playlist_id,
+            added.len(),
+            removed.len(),
+            added,
+            removed
+        );
+    }
+    for track_id in removed {
+        state.forget_dedupe(playlist_id, track_id);
+    }
+    Some(snapshot)
+}
+
+/// Tracks in `current` but not `previous`, and the other way round, each sorted.
+fn playlist_changes<'a>(
+    previous: &'a HashSet<String>,
+    current: &'a HashSet<String>,
+) -> (Vec<&'a String>, Vec<&'a String>) {
+    let mut added: Vec<&String> = current.difference(previous).collect();
+    let mut removed: Vec<&String> = previous.difference(current).collect();
+    added.sort();
+    removed.sort();
+    (added, removed)
+}
+
+/// Resolves links one at a time and adds them to the main playlist in batches, skipping
+/// tracks already there or within the shared dedupe window, with AIMD pacing between batches.
+/// Shared by the startup backfill and `/jamcraft import`; call [`flush`](Self::flush) to add
+/// what's queued.
+pub(crate) struct BulkAdder {
+    spotify: Arc<SpotifyClient>,
+    /// Channel the links came from, for the dedupe window
+    channel_id: String,
+    playlist_id: String,
+    /// The playlist's tracks, including those this run has added or queued
+    existing: std::collections::HashSet<String>,
+    /// Tracks waiting for the next batch, with their source and when their dedupe window was
+    /// reserved
+    queued: Vec<(String, &'static str, Instant)>,
+    delay: AdaptiveDelay,
+    /// Only count what would be added, whatever `DRY_RUN` says
+    preview: bool,
+    /// Set aside resolved tracks for moderator approval instead of adding them (`CURATE_MODE`)
+    hold: bool,
+    /// Tracks set aside since the last [`take_held`](Self::take_held), with their source
+    held: Vec<(String, &'static str)>,
+    pub(crate) resolved: usize,
+    /// Tracks added, or that would be in a dry run or preview
+    pub(crate) added: usize,
+    /// Resolved tracks skipped because the playlist already has them, from before or this run
+    pub(crate) already_present: usize,
+    /// Links that didn't resolve plus adds Spotify rejected
+    pub(crate) failed: usize,
+}
+
+impl BulkAdder {
+    pub(crate) async fn new(
+        spotify: &Arc<SpotifyClient>,
+        channel_id: &str,
+        playlist_id: &str,
+    ) -> Self {
+        let existing = spotify
+            .get_playlist_track_ids_for(playlist_id)
+            .await
+            .unwrap_or_default();
+        Self {
+            spotify: spotify.clone(),
+            channel_id: channel_id.to_string(),
+            playlist_id: playlist_id.to_string(),
+            existing,
+            queued: Vec::new(),
+            delay: AdaptiveDelay::new(
+                MIN_BACKFILL_ADD_DELAY,
+                MAX_BACKFILL_ADD_DELAY,
+                BACKFILL_ADD_DELAY_STEP,
+            ),
+            preview: false,
+            hold: false,
+            held: Vec::new(),
+            resolved: 0,
+            added: 0,
+            already_present: 0,
+            failed: 0,
+        }
+    }
+
+    /// Counts instead of adding, as in a dry run.
+    pub(crate) fn preview(mut self) -> Self {
+        self.preview = true;
+        self
+    }
+
+    /// Sets tracks aside for approval instead of adding them, as curate mode does.
+    pub(crate) fn hold_for_approval(mut self) -> Self {
+        self.hold = true;
+        self
+    }
+
+    /// Tracks set aside since the last call, to queue for approval.
+    pub(crate) fn take_held(&mut self) -> Vec<(String, &'static str)> {
+        std::mem::take(&mut self.held)
+    }
+
+    /// Resolves a link and queues its track, adding the queue once it fills a request.
+    pub(crate) async fn add_url(&mut self, state: &AppState, url: String) {
+        let source = resolve::platform(&url);
+        let Some(track_id) = resolve_url(state, url, Some(&self.playlist_id))
+            .await
+            .track_id()
+        else {
+            self.failed += 1;
+            return;
+        };
+        let track_id = self
+            .spotify
+            .canonical_track_id_for(&track_id, &self.playlist_id)
+            .await;
+        self.resolved += 1;
+        if self.existing.contains(&track_id) {
+            self.already_present += 1;
+            if !self.preview {
+                state.metrics.track_deduped();
+            }
+            return;
+        }
+
+        // Dedupe waits for approval, as it does for a live message in curate mode
+        if self.hold && !self.preview {
+            self.existing.insert(track_id.clone());
+            self.held.push((track_id, source));
+            return;
+        }
+
+        // Claimed in the shared dedupe window, like a live message would, so a live post of
+        // the same track mid-run isn't added twice. A preview adds nothing, so claims nothing.
+        let key = state.dedupe_key(&self.channel_id, &self.playlist_id, &track_id);
+        let now = Instant::now();
+        if !self.preview && !state.reserve_dedupe(key, now) {
+            info!(
+                "Skipping {} in bulk: added within the dedupe window",
+                track_id
+            );
+            state.metrics.track_deduped();
+            return;
+        }
+        self.existing.insert(track_id.clone());
+
+        // Shadow mode doesn't flood the review channel with a bulk run
+        if self.preview || state.dry_run() || state.config.shadow_mode {
+            info!("[DRY RUN] Would add track in bulk: {}", track_id);
+            self.added += 1;
+            return;
+        }
+        self.queued.push((track_id, source, now));
+        if self.queued.len() >= spotify::MAX_ITEMS_PER_REQUEST {
+            self.flush(state).await;
+        }
+    }
+
+    /// Adds the queued tracks in as few requests as Spotify allows, then pauses.
+    pub(crate) async fn flush(&mut self, state: &AppState) {
+        if self.queued.is_empty() {
+            return;
+        }
+        let queued = std::mem::take(&mut self.queued);
+        let track_ids: Vec<String> = queued.iter().map(|(id, ..)| id.clone()).collect();
+        let playlist_id = self.playlist_id.clone();
+
+        let hits_before = self.spotify.rate_limit_hits();
+        let outcome = self.spotify.add_tracks_to(&playlist_id, &track_ids).await;
+        let failed: HashMap<&str, &SpotifyError> = outcome
+            .failed
+            .iter()
+            .map(|(id, e)| (id.as_str(), e))
+            .collect();
+        for (track_id, source, reserved_at) in queued {
+            if let Some(e) = failed.get(track_id.as_str()) {
+                warn!("Failed to add track {} in bulk: {}", track_id, e);
+                state.metrics.spotify_add_error(e);
+                let key = state.dedupe_key(&self.channel_id, &playlist_id, &track_id);
+                state.release_dedupe(&key, reserved_at);
+                self.existing.remove(&track_id);
+                self.failed += 1;
+                continue;
+            }
+            self.added += 1;
+            state.metrics.tracks_added(1);
+            // Recorded so `/jamcraft undo` can roll back a mistaken import
+            state.history.record(AddRecord {
+                track_id,
+                playlist_id: playlist_id.clone(),
+                user: None,
+                source: Some(source),
+                added_at: time::OffsetDateTime::now_utc(),
+            });
+        }
+
+        if self.spotify.rate_limit_hits() > hits_before {
+            self.delay.on_throttled();
+            info!(
+                "Bulk add throttled, now pausing {:?} between adds",
+                self.delay.current()
+            );
+        } else {
+            self.delay.on_success();
+        }
+        tokio::time::sleep(self.delay.current()).await;
+    }
+}
+
+/// Source recorded for tracks found from an upload's caption rather than a link.
+const UPLOAD_SOURCE: &str = "Upload";
+
+/// How long what a message added stays undoable, by reaction or button.
+const UNDO_WINDOW: Duration = Duration::from_secs(3600);
+
+/// How long after an undo a re-post of the same track is flagged (`WARN_ON_READD`).
+const READD_WARN_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Tracks added on behalf of one message.
+#[derive(Clone, Debug)]
+struct AddedTracks {
+    user: Option<String>,
+    /// Track IDs by the playlist they went to, e.g. the target and `SPILLOVER_PLAYLIST_ID`
+    playlists: Vec<(String, Vec<String>)>,
+    added_at: Instant,
+}
+
+impl AddedTracks {
+    fn count(&self) -> usize {
+        self.playlists.iter().map(|(_, ids)| ids.len()).sum()
+    }
+}
+
+/// First route whose `#tag` appears in the message as a whole word.
+fn route_for_message<'a>(routes: &'a [PlaylistRoute], text: &str) -> Option<&'a PlaylistRoute> {
+    let text = text.to_lowercase();
+    routes.iter().find(|route| {
+        let needle = format!("#{}", route.tag);
+        text.match_indices(&needle).any(|(i, _)| {
+            text[i + needle.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| !c.is_alphanumeric() && c != '_' && c != '-')
+        })
+    })
 }
 
 #[tokio::main]
 async fn main() {
     dotenvy::dotenv().ok();
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "jamcraft=info".into()),
-        )
-        .init();
+    let tracer_provider = telemetry::init();
 
     // --resolve URL : test resolve flow and exit (no Slack/Spotify needed for Qobuz step)
     if let Some(url) = std::env::args().nth(1).filter(|a| a.starts_with("http")) {
         println!("Resolving: {}\n", url);
         println!("1. Odesli...");
-        let spotify_id = resolve_to_spotify_track_id(&url).await;
+        let spotify_id = resolve_to_spotify_track_id(&url).await.ok().flatten();
         if let Some(ref id) = spotify_id {
             println!("   -> Resolved to Spotify: {}", id);
             return;
@@ -60,7 +603,7 @@ async fn main() {
         if let Some(qobuz_id) = parse_qobuz_track_id(&url) {
             println!("2. Qobuz fallback: track_id={}", qobuz_id);
             match fetch_qobuz_track_metadata(&qobuz_id).await {
-                Some((artist, title)) => {
+                Ok(Some((artist, title))) => {
                     println!("   -> Got: {} - {}\n", artist, title);
                     if let (Ok(cid), Ok(cs), Ok(rt), Ok(pid)) = (
                         std::env::var("SPOTIFY_CLIENT_ID"),
@@ -68,8 +611,7 @@ async fn main() {
                         std::env::var("SPOTIFY_REFRESH_TOKEN"),
                         std::env::var("SPOTIFY_PLAYLIST_ID"),
                     ) {
-                        let spotify =
-                            SpotifyClient::new(cid, cs, rt, pid);
+                        let spotify = SpotifyClient::new(cid, cs, rt, pid);
                         println!("3. Spotify search...");
                         match spotify.search_track(&artist, &title).await {
                             Ok(Some(id)) => println!("   -> Found: {} (would add to playlist)", id),
@@ -80,272 +622,702 @@ async fn main() {
                         println!("3. (Set Spotify env vars to test search)");
                     }
                 }
-                None => println!("   -> Qobuz API failed (404 or error)"),
+                Ok(None) => println!("   -> Qobuz doesn't know that track"),
+                Err(_) => println!("   -> Qobuz API failed (network error or 5xx)"),
             }
         }
         return;
     }
 
-    // Read configuration
-    let bot_token =
-        std::env::var("SLACK_BOT_TOKEN").expect("SLACK_BOT_TOKEN must be set in .env file");
-    let signing_secret = std::env::var("SLACK_SIGNING_SECRET")
-        .expect("SLACK_SIGNING_SECRET must be set in .env file");
+    let mut config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprint!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Spotify credentials (required for full functionality)
-    let spotify_client_id = std::env::var("SPOTIFY_CLIENT_ID").unwrap_or_else(|_| {
-        eprintln!("\n⚠️  WARNING: SPOTIFY_CLIENT_ID not set");
+    if !config.missing_spotify_vars.is_empty() {
+        eprintln!(
+            "\n⚠️  WARNING: {} not set",
+            config.missing_spotify_vars.join(", ")
+        );
         eprintln!("   The bot will start but won't be able to add tracks to Spotify.");
-        eprintln!("   Get credentials from: https://developer.spotify.com/dashboard\n");
-        String::new()
-    });
-    let spotify_client_secret = std::env::var("SPOTIFY_CLIENT_SECRET").unwrap_or_else(|_| {
-        eprintln!("⚠️  WARNING: SPOTIFY_CLIENT_SECRET not set\n");
-        String::new()
-    });
-    let spotify_refresh_token = std::env::var("SPOTIFY_REFRESH_TOKEN").unwrap_or_else(|_| {
-        eprintln!("⚠️  WARNING: SPOTIFY_REFRESH_TOKEN not set");
-        eprintln!("   Run: cargo run --bin spotify_auth\n");
-        String::new()
-    });
-    let spotify_playlist_id = std::env::var("SPOTIFY_PLAYLIST_ID").unwrap_or_else(|_| {
-        eprintln!("⚠️  WARNING: SPOTIFY_PLAYLIST_ID not set\n");
-        String::new()
-    });
-    let music_channel_name =
-        std::env::var("MUSIC_CHANNEL_NAME").unwrap_or_else(|_| "jamcraft".to_string());
-    let port = std::env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse::<u16>()
-        .expect("PORT must be a valid u16");
-    let dry_run = std::env::var("DRY_RUN")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
-    let scan_existing_on_startup = std::env::var("SCAN_EXISTING_ON_STARTUP")
-        .unwrap_or_else(|_| "false".to_string())
-        .parse::<bool>()
-        .unwrap_or(false);
-
-    if dry_run {
+        eprintln!("   Get credentials from: https://developer.spotify.com/dashboard");
+        eprintln!("   and run: cargo run --bin spotify_auth\n");
+    }
+    if config.spillover_playlist_id.is_some() && config.max_urls_per_message.is_none() {
+        warn!(
+            "SPILLOVER_PLAYLIST_ID is set but MAX_URLS_PER_MESSAGE isn't - nothing will spill over"
+        );
+    }
+    if config.dry_run {
         warn!("DRY_RUN mode enabled - tracks will NOT be added to Spotify");
     }
+    if config.shadow_mode {
+        warn!(
+            "SHADOW_MODE enabled - tracks are posted to SHADOW_CHANNEL instead of added to Spotify"
+        );
+    }
+    if config.curate_mode {
+        info!("CURATE_MODE enabled - tracks are queued until a moderator approves them");
+        if config.admin_user_ids.is_empty() && config.moderator_user_ids.is_empty() {
+            warn!("CURATE_MODE is on but no ADMIN_USER_IDS/MODERATOR_USER_IDS are set - nothing can be approved");
+        }
+    }
 
     // Initialize clients
-    let slack_client = Arc::new(SlackWebClient::new(bot_token));
+    let slack_client = Arc::new(
+        SlackWebClient::new(config.bot_token.clone())
+            .with_write_budget(config.slack_writes_per_minute),
+    );
+
+    if let Some(proxy) = &config.spotify_http_proxy {
+        info!(
+            "Sending Spotify requests through proxy {}",
+            config::without_credentials(proxy)
+        );
+    }
 
     // Only initialize Spotify client if credentials are provided
-    let spotify_client = if spotify_client_id.is_empty()
-        || spotify_client_secret.is_empty()
-        || spotify_refresh_token.is_empty()
-        || spotify_playlist_id.is_empty()
-    {
-        warn!("Spotify credentials incomplete - bot will run but won't add tracks to Spotify");
-        None
-    } else {
-        Some(Arc::new(SpotifyClient::new(
-            spotify_client_id,
-            spotify_client_secret,
-            spotify_refresh_token,
-            spotify_playlist_id,
-        )))
+    let spotify_client = match &config.spotify {
+        None => {
+            warn!("Spotify credentials incomplete - bot will run but won't add tracks to Spotify");
+            None
+        }
+        Some(creds) => Some(Arc::new(
+            SpotifyClient::new(
+                creds.client_id.clone(),
+                creds.client_secret.clone(),
+                creds.refresh_token.clone(),
+                creds.playlist_id.clone(),
+            )
+            .with_http_client(
+                &config.spotify_user_agent,
+                config.spotify_http_proxy.as_deref(),
+            )
+            .with_match_threshold(config.search_match_threshold)
+            .with_max_retries(config.spotify_max_retries)
+            .with_market(config.spotify_market.as_deref())
+            .with_playlist_markets(
+                config
+                    .playlist_routes
+                    .iter()
+                    .filter_map(|r| Some((r.playlist_id.clone(), r.market.clone()?)))
+                    .collect(),
+            ),
+        )),
     };
 
-    // Resolve channel ID (with timeout to avoid blocking server startup)
-    info!("Resolving channel ID for #{}", music_channel_name);
-    let music_channel_id = tokio::time::timeout(
-        Duration::from_secs(10),
-        slack_client.resolve_channel_id_by_name(&music_channel_name),
-    )
-    .await;
-
-    let music_channel_id = match music_channel_id {
-        Ok(Ok(Some(id))) => {
-            info!("Found channel ID: {}", id);
-            id
-        }
-        Ok(Ok(None)) => {
-            error!("Channel #{} not found", music_channel_name);
+    if let Some(spotify) = &spotify_client {
+        if !check_spotify_token(spotify).await && config.spotify_strict_startup {
+            error!("SPOTIFY_STRICT_STARTUP is set - exiting");
             std::process::exit(1);
         }
-        Ok(Err(e)) => {
-            error!("Failed to resolve channel: {}", e);
-            std::process::exit(1);
+        if config.spotify_proactive_token_refresh {
+            spotify.clone().spawn_token_refresher();
         }
-        Err(_) => {
-            error!("Channel resolution timed out after 10 seconds");
-            std::process::exit(1);
+    }
+
+    // Resolve channel IDs (with timeout to avoid blocking server startup)
+    for channel in &mut config.music_channels {
+        info!("Resolving channel ID for #{}", channel.name);
+        let channel_id = tokio::time::timeout(
+            Duration::from_secs(10),
+            slack_client.resolve_channel_id_by_name(&channel.name),
+        )
+        .await;
+
+        channel.id = match channel_id {
+            Ok(Ok(Some(id))) => {
+                info!("Found channel ID: {}", id);
+                id
+            }
+            Ok(Ok(None)) => {
+                error!("Channel #{} not found", channel.name);
+                std::process::exit(1);
+            }
+            Ok(Err(e)) => {
+                error!("Failed to resolve channel #{}: {}", channel.name, e);
+                std::process::exit(1);
+            }
+            Err(_) => {
+                error!(
+                    "Resolving channel #{} timed out after 10 seconds",
+                    channel.name
+                );
+                std::process::exit(1);
+            }
+        };
+    }
+
+    // Our own user ID, so we never act on the bot's own messages (feedback loops)
+    let bot_user_id = match slack_client.auth_test().await {
+        Ok(auth) => {
+            info!("Running as Slack user {} ({})", auth.user, auth.user_id);
+            Some(auth.user_id)
+        }
+        Err(e) => {
+            warn!(
+                "auth.test failed, can't filter the bot's own messages by user: {}",
+                e
+            );
+            None
         }
     };
 
-    let config = Config {
-        signing_secret,
-        music_channel_id,
-    };
+    config.music_channel_id = config.music_channels[0].id.clone();
 
-    let state = AppState {
-        slack: slack_client,
-        spotify: spotify_client,
-        config,
-        dedupe: Arc::new(DashMap::new()),
-        dry_run,
-    };
+    let state = AppState::new(config, slack_client, spotify_client, bot_user_id);
 
     // Cleanup old dedupe entries periodically
     let dedupe_cleanup = state.dedupe.clone();
+    let processed_cleanup = state.processed_messages.clone();
+    let events_cleanup = state.seen_events.clone();
+    let signatures_cleanup = state.seen_signatures.clone();
+    let added_cleanup = state.added_tracks.clone();
+    let removed_cleanup = state.recently_removed.clone();
+    let cache_cleanup = state.resolve_cache.clone();
+    let vote_cleanup = state.vote_cards.clone();
+    let dedupe_window = state.config.dedupe_window;
     tokio::spawn(async move {
         let mut interval = tokio::time::interval(Duration::from_secs(300)); // Every 5 minutes
         loop {
             interval.tick().await;
             let now = Instant::now();
-            dedupe_cleanup.retain(|_, &mut timestamp| {
-                now.duration_since(timestamp) < Duration::from_secs(3600)
+            dedupe_cleanup
+                .retain(|_, &mut timestamp| now.duration_since(timestamp) < dedupe_window);
+            processed_cleanup.retain(|_, &mut timestamp| {
+                now.duration_since(timestamp) < PROCESSED_MESSAGE_WINDOW
             });
+            events_cleanup
+                .retain(|_, &mut timestamp| now.duration_since(timestamp) < SEEN_EVENT_WINDOW);
+            signatures_cleanup
+                .retain(|_, &mut timestamp| now.duration_since(timestamp) < SEEN_SIGNATURE_WINDOW);
+            added_cleanup.retain(|_, added| now.duration_since(added.added_at) < UNDO_WINDOW);
+            removed_cleanup
+                .retain(|_, &mut timestamp| now.duration_since(timestamp) < READD_WARN_WINDOW);
+            cache_cleanup.prune();
+            vote_cleanup.retain(|_, card| now.duration_since(card.posted_at) < voting::VOTE_WINDOW);
         }
     });
 
+    if let (Some(period), Some(spotify)) = (
+        state.config.playlist_reconcile_interval,
+        state.spotify.clone(),
+    ) {
+        let reconcile_state = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                reconcile_playlist(&reconcile_state, &spotify).await;
+            }
+        });
+    }
+
     // Optional: scan existing channel messages and add tracks to playlist
-    if scan_existing_on_startup {
+    if state.config.scan_existing_on_startup {
         let backfill_state = state.clone();
         tokio::spawn(async move {
-            if let Err(e) = backfill_existing_messages(backfill_state).await {
+            if let Err(e) = backfill_existing_messages(backfill_state, false).await {
                 error!("Backfill failed: {}", e);
             }
         });
     }
 
-    // Build router
-    let app = Router::new()
-        .route("/health", get(health_handler))
-        .route("/slack/events", post(slack_events_handler))
-        .layer(tower_http::trace::TraceLayer::new_for_http())
-        .with_state(state);
+    if let Some(at) = state.config.daily_summary_time {
+        tokio::spawn(daily_summary::run(state.clone(), at));
+    }
+
+    // Socket Mode delivers events over a WebSocket; the HTTP server still serves /health
+    if let Some(app_token) = state
+        .config
+        .app_token
+        .clone()
+        .filter(|_| state.config.socket_mode)
+    {
+        info!("SLACK_SOCKET_MODE is on, receiving events over Socket Mode");
+        tokio::spawn(socket_mode::run(state.clone(), app_token));
+    }
+
+    let addr = format!("0.0.0.0:{}", state.config.port);
+
+    let app = build_router(state);
 
     // Start server
-    let addr = format!("0.0.0.0:{}", port);
     info!("Starting server on {}", addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
+
+    if let Some(provider) = tracer_provider {
+        if let Err(e) = provider.shutdown() {
+            warn!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+/// The HTTP routes. In Socket Mode Slack delivers everything over the WebSocket, so the Slack
+/// request URLs aren't served: with no signing secret they couldn't be verified.
+fn build_router(state: AppState) -> Router {
+    let mut router = Router::new()
+        .route("/health", get(health_handler))
+        .route("/health/deep", get(deep_health_handler))
+        .route("/metrics", get(metrics_handler));
+    if !state.config.socket_mode {
+        router = router
+            .route("/slack/events", post(slack_events_handler))
+            .route("/slack/commands", post(commands::slack_commands_handler))
+            .route(
+                "/slack/interactions",
+                post(interactions::slack_interactions_handler),
+            );
+    }
+    if state.config.enable_debug_endpoints {
+        warn!("ENABLE_DEBUG_ENDPOINTS is on: GET /debug/config is served without authentication");
+        router = router.route("/debug/config", get(debug_config_handler));
+    }
+    router
+        .layer(tower_http::trace::TraceLayer::new_for_http())
+        .with_state(state)
 }
 
 async fn health_handler() -> &'static str {
     "ok"
 }
 
-async fn backfill_existing_messages(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
-    info!(
-        "Starting backfill: scanning existing messages in #{}",
-        state.config.music_channel_id
-    );
+/// How long `/health/deep` waits on each dependency.
+const DEEP_HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Readiness check: 200 only if Slack accepts the bot token and Spotify an API call,
+/// otherwise 503 with the failing dependency's error. Spotify counts as healthy when it isn't
+/// configured, since the bot then runs without it on purpose.
+async fn deep_health_handler(State(state): State<AppState>) -> (StatusCode, Json<Value>) {
+    let check = |result: Result<Result<(), String>, tokio::time::error::Elapsed>| match result
+        .unwrap_or_else(|_| Err("timed out".to_string()))
+    {
+        Ok(()) => json!({ "ok": true }),
+        Err(e) => json!({ "ok": false, "error": e }),
+    };
+    let slack = async {
+        tokio::time::timeout(DEEP_HEALTH_TIMEOUT, async {
+            state.slack.auth_test().await.map(|_| ())
+        })
+        .await
+    };
+    let spotify = async {
+        let spotify = state.spotify.as_ref()?;
+        Some(
+            tokio::time::timeout(DEEP_HEALTH_TIMEOUT, async {
+                spotify
+                    .current_user_id()
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| e.to_string())
+            })
+            .await,
+        )
+    };
+    let (slack, spotify) = tokio::join!(slack, spotify);
+
+    let slack = check(slack);
+    let spotify = match spotify {
+        Some(result) => check(result),
+        None => json!({ "ok": true, "configured": false }),
+    };
+    let healthy = slack["ok"] == true && spotify["ok"] == true;
+    if !healthy {
+        warn!(
+            "Deep health check failed: slack={}, spotify={}",
+            slack, spotify
+        );
+    }
+    (
+        if healthy {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(json!({
+            "status": if healthy { "ok" } else { "unavailable" },
+            "slack": slack,
+            "spotify": spotify,
+        })),
+    )
+}
+
+/// Prometheus scrape endpoint.
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(&state.resolve_cache.stats()),
+    )
+}
+
+/// The effective config with secrets redacted, for operators who can reach the server but not
+/// Slack. Only routed when `ENABLE_DEBUG_ENDPOINTS` is set.
+async fn debug_config_handler(State(state): State<AppState>) -> Json<Value> {
+    Json(state.config.debug_dump())
+}
+
+/// What a backfill scanned and added (or, previewing, would add).
+#[derive(Debug, Default)]
+pub(crate) struct BackfillSummary {
+    pub(crate) messages: usize,
+    pub(crate) urls: usize,
+    pub(crate) added: usize,
+    pub(crate) already_present: usize,
+    pub(crate) failed: usize,
+}
 
+/// Scans each music channel's history and adds every linked track not yet in its playlist.
+/// With `preview` nothing is added and messages aren't marked processed, so a real backfill
+/// afterwards still picks them up (`/jamcraft backfill --dry`).
+pub(crate) async fn backfill_existing_messages(
+    state: AppState,
+    preview: bool,
+) -> Result<BackfillSummary, Box<dyn std::error::Error + Send + Sync>> {
     let spotify_client = match &state.spotify {
         Some(c) => c,
         None => {
             info!("Spotify not configured, skipping backfill");
-            return Ok(());
+            return Ok(BackfillSummary::default());
         }
     };
 
-    // Fetch existing playlist tracks to avoid duplicates
-    let existing_tracks = spotify_client
-        .get_playlist_track_ids()
-        .await
-        .unwrap_or_default();
+    let mut summary = BackfillSummary::default();
+    for channel in &state.config.music_channels {
+        let playlist_id = channel
+            .playlist_id
+            .clone()
+            .unwrap_or_else(|| spotify_client.playlist_id());
+        let channel_summary =
+            backfill_channel(&state, spotify_client, &channel.id, &playlist_id, preview).await?;
+        summary.messages += channel_summary.messages;
+        summary.urls += channel_summary.urls;
+        summary.added += channel_summary.added;
+        summary.already_present += channel_summary.already_present;
+        summary.failed += channel_summary.failed;
+    }
+    Ok(summary)
+}
+
+/// Backfills one music channel into `playlist_id`.
+async fn backfill_channel(
+    state: &AppState,
+    spotify_client: &Arc<SpotifyClient>,
+    channel_id: &str,
+    playlist_id: &str,
+    preview: bool,
+) -> Result<BackfillSummary, Box<dyn std::error::Error + Send + Sync>> {
     info!(
-        "Playlist has {} existing tracks, will skip duplicates",
-        existing_tracks.len()
+        "Starting backfill{}: scanning existing messages in #{}",
+        if preview { " preview" } else { "" },
+        channel_id
     );
 
-    let texts = state
+    // BACKFILL_MAX_MESSAGE_AGE: Slack only returns threads started after the cutoff, and
+    // replies in them are checked against it below
+    let cutoff = state.config.backfill_max_message_age.map(|age| {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        now.saturating_sub(age).as_secs_f64()
+    });
+    let oldest = cutoff.map(|cutoff| format!("{:.6}", cutoff));
+    let messages = state
         .slack
-        .fetch_channel_messages(&state.config.music_channel_id)
+        .fetch_channel_messages(
+            channel_id,
+            state.config.backfill_thread_concurrency,
+            oldest.as_deref(),
+        )
         .await
         .map_err(|e| format!("Failed to fetch channel history: {}", e))?;
 
-    let mut seen_track_ids = std::collections::HashSet::new();
-    let mut resolved_count = 0;
-    let mut added_count = 0;
+    let mut adder = BulkAdder::new(spotify_client, channel_id, playlist_id).await;
+    if preview {
+        adder = adder.preview();
+    } else if state.config.curate_mode {
+        adder = adder.hold_for_approval();
+    }
+    info!(
+        "Playlist {} has {} existing tracks, will skip duplicates",
+        playlist_id,
+        adder.existing.len()
+    );
 
-    for text in &texts {
-        let urls = extract_urls(text);
-        for url in urls {
-            let mut track_id = resolve_to_spotify_track_id(&url).await;
-
-            // Qobuz fallback: metadata → Spotify search
-            if track_id.is_none() {
-                if let Some(qobuz_id) = parse_qobuz_track_id(&url) {
-                    if let Some((artist, title)) = fetch_qobuz_track_metadata(&qobuz_id).await {
-                        if let Ok(Some(id)) = spotify_client.search_track(&artist, &title).await {
-                            track_id = Some(id);
-                        }
-                    }
-                }
+    let mut url_count = 0;
+    for message in &messages {
+        if is_own_message(state, message.user.as_deref())
+            || !state.config.can_add(message.user.as_deref())
+        {
+            continue;
+        }
+        let posted_at = message.ts.as_deref().and_then(|ts| ts.parse::<f64>().ok());
+        if let (Some(cutoff), Some(posted_at)) = (cutoff, posted_at) {
+            if posted_at < cutoff {
+                continue;
             }
+        }
+        let text = message.text.as_deref().unwrap_or_default();
+        let urls = message_urls(&state.config, text);
+        if urls.is_empty() {
+            continue;
+        }
+        if let Some(ref ts) = message.ts {
+            let key = format!("{}:{}", channel_id, ts);
+            let skip = if preview {
+                state.processed_messages.contains_key(&key)
+            } else {
+                !claim_message(state, channel_id, ts)
+            };
+            if skip {
+                continue;
+            }
+        }
+        url_count += urls.len();
+        let added_before = adder.added;
+        // One message's links (often a whole album) go to Spotify together
+        for url in urls {
+            adder.add_url(state, url).await;
+        }
+        adder.flush(state).await;
 
-            if let Some(track_id) = track_id {
-                resolved_count += 1;
-                if seen_track_ids.contains(&track_id) {
-                    continue;
-                }
-                seen_track_ids.insert(track_id.clone());
-
-                // Skip if already in playlist
-                if existing_tracks.contains(&track_id) {
-                    continue;
-                }
+        let held = adder.take_held();
+        if let (Some(ts), false) = (&message.ts, held.is_empty()) {
+            queue_backfilled_message(state, channel_id, ts, message, playlist_id, held).await;
+            continue;
+        }
 
-                if state.dry_run {
-                    info!("[DRY RUN] Would add track from backfill: {}", track_id);
-                    added_count += 1;
-                } else {
-                    match spotify_client.add_track(&track_id).await {
-                        Ok(()) => {
-                            added_count += 1;
-                            state.dedupe.insert(track_id, Instant::now());
-                        }
-                        Err(e) => {
-                            warn!("Failed to add track {} during backfill: {}", track_id, e);
-                        }
-                    }
+        // Off by default: a large backfill would otherwise react on hundreds of old messages
+        if state.config.backfill_react && !preview && adder.added > added_before {
+            if let Some(ref ts) = message.ts {
+                if let Err(e) = state
+                    .slack
+                    .reactions_add(channel_id, ts, &state.emojis().success)
+                    .await
+                {
+                    warn!("Failed to react to backfilled message {}: {}", ts, e);
                 }
-                tokio::time::sleep(Duration::from_millis(100)).await;
             }
         }
     }
 
     info!(
-        "Backfill complete: {} messages scanned, {} tracks resolved, {} added to playlist",
-        texts.len(),
-        resolved_count,
-        added_count
+        "Backfill{} of #{} complete: {} messages scanned, {} tracks resolved, {} {} playlist",
+        if preview { " preview" } else { "" },
+        channel_id,
+        messages.len(),
+        adder.resolved,
+        adder.added,
+        if preview {
+            "would be added to"
+        } else {
+            "added to"
+        }
     );
-    Ok(())
+    Ok(BackfillSummary {
+        messages: messages.len(),
+        urls: url_count,
+        added: adder.added,
+        already_present: adder.already_present,
+        failed: adder.failed,
+    })
 }
 
-async fn slack_events_handler(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    body: axum::body::Bytes,
-) -> Result<Json<Value>, StatusCode> {
+/// Queues a backfilled message's tracks for approval in curate mode, like a live message.
+async fn queue_backfilled_message(
+    state: &AppState,
+    channel_id: &str,
+    ts: &str,
+    message: &slack::SlackMessage,
+    playlist_id: &str,
+    held: Vec<(String, &'static str)>,
+) {
+    let sources = held
+        .iter()
+        .map(|(id, source)| (id.clone(), *source))
+        .collect();
+    let track_ids: Vec<String> = held.into_iter().map(|(id, _)| id).collect();
     info!(
-        "Received request to /slack/events, body length: {} bytes",
-        body.len()
+        "Queued {} backfilled track(s) from {} for approval",
+        track_ids.len(),
+        ts
     );
-
-    // Parse JSON first to check if it's a URL verification challenge
-    // (we need to respond to challenges even if signature verification fails)
-    let envelope: SlackEnvelope = match serde_json::from_slice(&body) {
-        Ok(e) => e,
-        Err(e) => {
-            warn!("Failed to parse Slack envelope: {}", e);
-            return Ok(Json(json!({})));
+    state.pending.insert(
+        format!("{}:{}", channel_id, ts),
+        PendingAdd {
+            channel: channel_id.to_string(),
+            ts: ts.to_string(),
+            user: message.user.clone(),
+            playlist_id: playlist_id.to_string(),
+            track_ids,
+            sources,
+            note: message.text.as_deref().and_then(messages::extract_note),
+            queued_at: Instant::now(),
+        },
+    );
+    if state.config.backfill_react {
+        if let Err(e) = state
+            .slack
+            .reactions_add(channel_id, ts, &state.emojis().pending)
+            .await
+        {
+            warn!("Failed to react to backfilled message {}: {}", ts, e);
         }
-    };
+    }
+}
+
+/// Remembers what a message added so the undo reaction can take it back out.
+fn record_added_tracks(
+    state: &AppState,
+    channel: &str,
+    ts: &str,
+    user: Option<&str>,
+    playlist_id: &str,
+    track_ids: &[String],
+    sources: &HashMap<String, &'static str>,
+) {
+    let mut entry = state
+        .added_tracks
+        .entry(format!("{}:{}", channel, ts))
+        .or_insert_with(|| AddedTracks {
+            user: user.map(str::to_string),
+            playlists: Vec::new(),
+            added_at: Instant::now(),
+        });
+    match entry.playlists.iter_mut().find(|(id, _)| id == playlist_id) {
+        Some((_, ids)) => ids.extend(track_ids.iter().cloned()),
+        None => entry
+            .playlists
+            .push((playlist_id.to_string(), track_ids.to_vec())),
+    }
+    entry.added_at = Instant::now();
+
+    for track_id in track_ids {
+        state.history.record(AddRecord {
+            track_id: track_id.clone(),
+            playlist_id: playlist_id.to_string(),
+            user: user.map(str::to_string),
+            source: sources.get(track_id).copied(),
+            added_at: time::OffsetDateTime::now_utc(),
+        });
+    }
+}
+
+/// Sends the event to the notify webhook in the background; failures are only logged.
+fn notify_tracks_added(state: &AppState, event: &TracksAdded<'_>) {
+    let Some(notifier) = state.notifier.clone() else {
+        return;
+    };
+    if event.track_ids.is_empty() {
+        return;
+    }
+    let payload = serde_json::to_value(event).unwrap_or_default();
+    tokio::spawn(async move {
+        if let Err(e) = notifier.post(&payload).await {
+            warn!("Failed to notify webhook: {}", e);
+        }
+    });
+}
+
+/// Refreshes the Spotify token once at startup so a dead or under-scoped refresh token shows
+/// up in the logs immediately instead of as failed adds later. Returns false if it's unusable.
+async fn check_spotify_token(spotify: &SpotifyClient) -> bool {
+    match spotify.granted_scopes().await {
+        Ok(scopes) => {
+            let scopes = scopes.unwrap_or_default();
+            info!("Spotify token OK, granted scopes: {}", scopes);
+            // The modify scope needed depends on whether the playlist is public
+            let access = spotify.playlist_access(&spotify.playlist_id()).await.ok();
+            let missing = commands::missing_scopes(&scopes, access.as_ref());
+            if missing.is_empty() {
+                true
+            } else {
+                error!(
+                    "Spotify token is missing scopes: {} - re-run spotify_auth",
+                    missing.join(", ")
+                );
+                false
+            }
+        }
+        Err(e) => {
+            error!("Spotify token refresh failed at startup: {}", e);
+            false
+        }
+    }
+}
+
+/// Verifies the Slack signing headers on an incoming request.
+fn verify_slack_request(
+    state: &AppState,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(), StatusCode> {
+    // An empty key would let anyone compute a valid signature
+    if state.config.signing_secret.is_empty() {
+        warn!("Rejecting Slack request: no SLACK_SIGNING_SECRET to verify it with");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let timestamp = headers
+        .get("X-Slack-Request-Timestamp")
+        .and_then(|h| h.to_str().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let signature = headers
+        .get("X-Slack-Signature")
+        .and_then(|h| h.to_str().ok())
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    SlackWebClient::verify_signature(&state.config.signing_secret, timestamp, signature, body)
+        .inspect_err(|e| {
+            warn!("Signature verification failed: {:?}", e);
+        })?;
+
+    // A captured request would otherwise verify again for as long as its timestamp is fresh
+    let now = Instant::now();
+    match state.seen_signatures.entry(signature.to_string()) {
+        Entry::Occupied(entry) if now.duration_since(*entry.get()) < SEEN_SIGNATURE_WINDOW => {
+            warn!("Rejecting replayed Slack request (timestamp {})", timestamp);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+        Entry::Occupied(mut entry) => {
+            entry.insert(now);
+            Ok(())
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(now);
+            Ok(())
+        }
+    }
+}
+
+async fn slack_events_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<Json<Value>, StatusCode> {
+    info!(
+        "Received request to /slack/events, body length: {} bytes",
+        body.len()
+    );
+
+    // Parse JSON first to check if it's a URL verification challenge
+    // (we need to respond to challenges even if signature verification fails)
+    let envelope = match SlackEnvelope::parse(&body) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("Failed to parse Slack envelope: {}", e);
+            return Ok(Json(json!({})));
+        }
+    };
 
     // Handle URL verification immediately (before signature check)
     // Slack requires this for initial endpoint verification
-    if envelope.event_type == "url_verification" {
-        if let Some(challenge) = envelope.challenge {
+    if let SlackEnvelope::UrlVerification { challenge } = &envelope {
+        if let Some(challenge) = challenge {
             info!("Received URL verification challenge: {}", challenge);
             // For url_verification, we should still verify signature if headers are present
             // But we respond to the challenge regardless to allow Slack to verify the endpoint
@@ -382,240 +1354,2697 @@ async fn slack_events_handler(
     }
 
     // For all other events, verify signature
-    let timestamp = headers
-        .get("X-Slack-Request-Timestamp")
-        .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::BAD_REQUEST)?;
+    verify_slack_request(&state, &headers, &body)?;
 
-    let signature = headers
-        .get("X-Slack-Signature")
-        .and_then(|h| h.to_str().ok())
-        .ok_or(StatusCode::BAD_REQUEST)?;
+    // Handle event callback
+    if let SlackEnvelope::Event(event) = envelope {
+        if let Some(retry) = headers
+            .get("X-Slack-Retry-Num")
+            .and_then(|h| h.to_str().ok())
+        {
+            let reason = headers
+                .get("X-Slack-Retry-Reason")
+                .and_then(|h| h.to_str().ok())
+                .unwrap_or("unknown");
+            info!(
+                "Slack retry #{} ({}) of event {}",
+                retry,
+                reason,
+                event.event_id.as_deref().unwrap_or("without an ID")
+            );
+        }
+        handle_event(state, *event);
+    }
 
-    SlackWebClient::verify_signature(&state.config.signing_secret, timestamp, signature, &body)
-        .map_err(|e| {
-            warn!("Signature verification failed: {:?}", e);
-            e
-        })?;
+    Ok(Json(json!({})))
+}
 
-    // Handle event callback
-    if envelope.event_type == "event_callback" {
-        if let Some(event) = envelope.event {
-            // Ignore bot messages and subtypes
-            if event.bot_id.is_some() || event.subtype.is_some() {
-                return Ok(Json(json!({})));
+/// Handles an event callback, however it arrived (HTTP or Socket Mode). Work is spawned, so
+/// this returns right away and the event can be acknowledged.
+fn handle_event(state: AppState, event: types::SlackEvent) {
+    if let Some(event_id) = &event.event_id {
+        if !claim_event(&state, event_id) {
+            info!("Dropping redelivered event {}", event_id);
+            return;
+        }
+    }
+    if event.event_type == "reaction_added" || event.event_type == "reaction_removed" {
+        handle_reaction(state, event);
+        return;
+    }
+
+    // Check channel matches
+    let channel = event.channel.as_deref().unwrap_or_default();
+    if state.config.music_channel(channel).is_none() {
+        return;
+    }
+
+    // Process message
+    if let Some(target) = message_target(event) {
+        tokio::spawn(async move {
+            if let Err(e) = process_message(
+                state.clone(),
+                &target.channel,
+                &target.ts,
+                target.user.as_deref(),
+                &target.text,
+                target.file_share,
+                false,
+            )
+            .await
+            {
+                error!("Error processing message: {}", e);
             }
+        });
+    }
+}
 
-            // Check channel matches
-            if let Some(ref channel) = event.channel {
-                if channel != &state.config.music_channel_id {
-                    return Ok(Json(json!({})));
-                }
-            } else {
-                return Ok(Json(json!({})));
-            }
-
-            // Process message
-            if let Some(text) = event.text {
-                if let Some(ts) = event.ts {
-                    if let Some(channel) = event.channel {
-                        tokio::spawn(async move {
-                            if let Err(e) =
-                                process_message(state.clone(), &channel, &ts, &text).await
-                            {
-                                error!("Error processing message: {}", e);
-                            }
-                        });
-                    }
-                }
+/// The message an event is about, with the ts to react and reply to.
+#[derive(Debug, PartialEq)]
+struct MessageTarget {
+    channel: String,
+    ts: String,
+    user: Option<String>,
+    text: String,
+    /// A file upload, whose caption may name a song instead of linking one
+    file_share: bool,
+}
+
+/// Picks out the message to process from a `message` event. Edits (`message_changed`) are
+/// handled so a link added by editing still gets picked up; their event `ts` is the edit's, so
+/// the original message's ts from the nested `message` is used instead. Other subtypes are
+/// only processed if a member posted them (`types::USER_MESSAGE_SUBTYPES`, e.g. uploads kept
+/// for their caption and scheduled messages). Links that only appear in the message's blocks,
+/// as in forwards, are appended to the text. Bot messages, other subtypes and messages with
+/// neither text nor links yield `None`.
+fn message_target(event: types::SlackEvent) -> Option<MessageTarget> {
+    let channel = event.channel?;
+    if event.bot_id.is_some() {
+        return None;
+    }
+    let file_share = event.subtype.as_deref() == Some("file_share");
+    let (ts, user, mut text, links) = match event.subtype.as_deref() {
+        subtype if types::is_user_message(subtype) => {
+            let links = types::block_link_urls(event.blocks.as_ref(), event.attachments.as_ref());
+            (event.ts?, event.user, event.text.unwrap_or_default(), links)
+        }
+        Some("message_changed") => {
+            let message = event.message?;
+            if message.bot_id.is_some() {
+                return None;
             }
+            let links =
+                types::block_link_urls(message.blocks.as_ref(), message.attachments.as_ref());
+            (
+                message.ts?,
+                message.user,
+                message.text.unwrap_or_default(),
+                links,
+            )
+        }
+        _ => return None,
+    };
+    for url in links {
+        if !text.contains(&url) {
+            text.push_str(&format!("\n<{}>", url));
         }
     }
-
-    Ok(Json(json!({})))
+    if text.trim().is_empty() {
+        return None;
+    }
+    Some(MessageTarget {
+        channel,
+        ts,
+        user,
+        text,
+        file_share,
+    })
 }
 
-async fn process_message(
-    state: AppState,
-    channel: &str,
-    thread_ts: &str,
-    text: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Extract URLs
-    let urls = extract_urls(text);
-    if urls.is_empty() {
-        return Ok(());
+/// Reactions on messages in the music channel: the retry emoji re-runs a message through
+/// `process_message`, the undo emoji removes the tracks that message added, and under
+/// `REACTION_VOTING` reactions on the bot's confirmations are counted as votes.
+fn handle_reaction(state: AppState, event: types::SlackEvent) {
+    let Some(types::ReactionItem {
+        item_type,
+        channel: Some(channel),
+        ts: Some(ts),
+    }) = event.item
+    else {
+        return;
+    };
+    if item_type != "message" || state.config.music_channel(&channel).is_none() {
+        return;
     }
 
-    // Resolve to Spotify track IDs
-    let mut track_ids = Vec::new();
-    for url in &urls {
-        info!("Attempting to resolve URL: {}", url);
-        let mut track_id = resolve_to_spotify_track_id(url).await;
-
-        // Qobuz fallback: Odesli doesn't support Qobuz, so try metadata → Spotify search
-        if track_id.is_none() {
-            if let Some(qobuz_id) = parse_qobuz_track_id(url) {
-                if let Some((artist, title)) = fetch_qobuz_track_metadata(&qobuz_id).await {
-                    if let Some(ref spotify) = state.spotify {
-                        if let Ok(Some(id)) = spotify.search_track(&artist, &title).await {
-                            info!("Resolved Qobuz {} to Spotify via search: {}", url, id);
-                            track_id = Some(id);
-                        }
-                    }
-                }
-            }
+    let reaction = event.reaction.unwrap_or_default();
+    if state.config.reaction_voting {
+        if let Some(user) = event
+            .user
+            .clone()
+            .filter(|u| !is_own_message(&state, Some(u)))
+        {
+            let added = event.event_type == "reaction_added";
+            tokio::spawn(voting::on_reaction(
+                state.clone(),
+                channel.clone(),
+                ts.clone(),
+                user,
+                reaction.clone(),
+                added,
+            ));
         }
+    }
+    if event.event_type != "reaction_added" {
+        return;
+    }
 
-        if let Some(id) = track_id {
-            info!("Successfully resolved {} to track ID: {}", url, id);
-            track_ids.push(id);
-        } else {
-            warn!("Failed to resolve URL: {}", url);
+    let emojis = state.emojis();
+    if reaction == emojis.retry {
+        tokio::spawn(retry_message(state, channel, ts, event.user));
+    } else if reaction == emojis.undo {
+        if let Some(user) = event.user {
+            tokio::spawn(undo_message(state, channel, ts, user));
         }
     }
+}
 
-    if track_ids.is_empty() {
-        // Couldn't resolve any track
-        state
-            .slack
-            .reactions_add(channel, thread_ts, "grey_question")
-            .await
-            .map_err(|e| format!("Failed to add reaction: {}", e))?;
+/// Bookkeeping once the bot has taken tracks back out of a playlist (undo or vote): they and
+/// their `DEDUPE_BY_TITLE` titles leave the dedupe window and the live playlist, they count as
+/// recently removed for `WARN_ON_READD`, and vote cards on them close, so a late vote can't
+/// remove them again after a re-add.
+pub(crate) fn forget_removed_tracks(state: &AppState, playlist_id: &str, track_ids: &[String]) {
+    let now = Instant::now();
+    let mut titles = Vec::new();
+    for track_id in track_ids {
+        state.forget_dedupe(playlist_id, track_id);
+        state.recently_removed.insert(track_id.clone(), now);
+        if let Some((_, title)) = state
+            .added_titles
+            .remove(&format!("{}/{}", playlist_id, track_id))
+        {
+            state.forget_dedupe(playlist_id, &format!("title:{}", title));
+            titles.push(title);
+        }
+    }
+    state.vote_cards.retain(|_, card| {
+        card.playlist_id != playlist_id || !card.track_ids.iter().any(|t| track_ids.contains(t))
+    });
+    state.update_live_playlist(playlist_id, |snapshot| {
+        let tracks = Arc::make_mut(&mut snapshot.tracks);
+        for track_id in track_ids {
+            tracks.remove(track_id);
+        }
+        let titles_left = Arc::make_mut(&mut snapshot.titles);
+        for title in &titles {
+            titles_left.remove(title);
+        }
+    });
+}
 
-        state
-            .slack
-            .chat_post_message(
-                channel,
-                Some(thread_ts),
-                "Couldn't resolve that link—try a Spotify link or include artist + title.",
-            )
-            .await
-            .map_err(|e| format!("Failed to post message: {}", e))?;
+/// Re-processes a message, e.g. after a failed resolution once the link or the resolver has
+/// been fixed.
+async fn retry_message(state: AppState, channel: String, ts: String, reactor: Option<String>) {
+    let message = match state.slack.fetch_message(&channel, &ts).await {
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            warn!(
+                "Retry requested for {}:{} but the message wasn't found",
+                channel, ts
+            );
+            return;
+        }
+        Err(e) => {
+            error!("Failed to fetch message for retry: {}", e);
+            return;
+        }
+    };
+    let Some(text) = message.text else {
+        return;
+    };
 
-        return Ok(());
+    info!(
+        "Retry reaction from {} on {}:{}, re-processing",
+        reactor.as_deref().unwrap_or("unknown user"),
+        channel,
+        ts
+    );
+    state
+        .processed_messages
+        .remove(&format!("{}:{}", channel, ts));
+    // A retry should really retry, not replay a cached miss
+    for url in message_urls(&state.config, &text) {
+        state.resolve_cache.remove(&url);
+    }
+    let file_share = message.subtype.as_deref() == Some("file_share");
+    if let Err(e) = process_message(
+        state.clone(),
+        &channel,
+        &ts,
+        message.user.as_deref(),
+        &text,
+        file_share,
+        true,
+    )
+    .await
+    {
+        error!("Error re-processing message: {}", e);
     }
+}
 
-    // Check if Spotify is configured
-    let spotify_client = match &state.spotify {
-        Some(client) => client,
-        None => {
-            warn!("Spotify not configured - cannot add tracks to playlist");
-            state
-                .slack
-                .reactions_add(channel, thread_ts, "grey_question")
-                .await
-                .map_err(|e| format!("Failed to add reaction: {}", e))?;
+/// What an undo (reaction or button) came to.
+#[derive(Debug, PartialEq)]
+pub(crate) enum UndoOutcome {
+    /// This many tracks were taken back out
+    Removed(usize),
+    /// Nothing recorded for the message, e.g. already undone or over an hour old
+    NothingToUndo,
+    /// Someone other than the poster or a moderator asked
+    NotAllowed,
+    Failed,
+}
 
-            state
-                .slack
-                .chat_post_message(
-                    channel,
-                    Some(thread_ts),
-                    "Spotify is not configured. Please set SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET, SPOTIFY_REFRESH_TOKEN, and SPOTIFY_PLAYLIST_ID in your .env file.",
-                )
-                .await
-                .map_err(|e| format!("Failed to post message: {}", e))?;
+/// Removes the tracks the message `channel:ts` (`key`) added. Only the original poster or a
+/// moderator can undo.
+pub(crate) async fn undo_added_tracks(state: &AppState, key: &str, actor: &str) -> UndoOutcome {
+    let Some(added) = state.added_tracks.get(key).map(|e| e.value().clone()) else {
+        return UndoOutcome::NothingToUndo;
+    };
+    if added.user.as_deref() != Some(actor) && !state.config.is_moderator(actor) {
+        info!("Ignoring undo on {} from {} (not the poster)", key, actor);
+        return UndoOutcome::NotAllowed;
+    }
+    let Some(spotify) = &state.spotify else {
+        return UndoOutcome::NothingToUndo;
+    };
 
-            return Ok(());
+    for (playlist_id, track_ids) in &added.playlists {
+        if !state.dry_run() && !state.config.shadow_mode {
+            if let Err(e) = spotify.remove_tracks_from(playlist_id, track_ids).await {
+                error!("Failed to undo {} on {}: {}", key, playlist_id, e);
+                return UndoOutcome::Failed;
+            }
         }
-    };
+        // Removed from this playlist, so a retried undo only has the rest left to do
+        if let Some(mut entry) = state.added_tracks.get_mut(key) {
+            entry.playlists.retain(|(id, _)| id != playlist_id);
+        }
+        forget_removed_tracks(state, playlist_id, track_ids);
+    }
+    state.added_tracks.remove(key);
+    info!(
+        "Undo by {}: removed {} track(s) added by {}",
+        actor,
+        added.count(),
+        key
+    );
+    UndoOutcome::Removed(added.count())
+}
 
-    // Fetch existing playlist tracks (skip duplicates already in playlist)
-    let existing_tracks = if !state.dry_run {
-        spotify_client.get_playlist_track_ids().await.ok()
-    } else {
-        None
+/// Undoes a message on its poster's (or a moderator's) undo reaction and says so in thread.
+async fn undo_message(state: AppState, channel: String, ts: String, reactor: String) {
+    let key = format!("{}:{}", channel, ts);
+    let UndoOutcome::Removed(count) = undo_added_tracks(&state, &key, &reactor).await else {
+        return;
     };
 
-    // Dedupe and add tracks
+    let message = messages::render(
+        &state.config.messages.undone_by_reaction,
+        &[
+            ("count", &count.to_string()),
+            ("user", &format!("<@{}>", reactor)),
+        ],
+    );
+    if let Err(e) = state
+        .slack
+        .chat_post_message(&channel, Some(&ts), &message)
+        .await
+    {
+        warn!("Failed to post undo message: {}", e);
+    }
+}
+
+/// True if the message was posted by the bot itself. Complements the `bot_id` filter for
+/// cases where our own confirmations come back as regular user messages (unfurls, quotes).
+fn is_own_message(state: &AppState, user: Option<&str>) -> bool {
+    matches!((user, &state.bot_user_id), (Some(user), Some(bot)) if user == bot)
+}
+
+/// Records an event delivery as handled. Returns false if it was already handled within
+/// `SEEN_EVENT_WINDOW`, i.e. this is Slack retrying it.
+fn claim_event(state: &AppState, event_id: &str) -> bool {
     let now = Instant::now();
-    let mut added_count = 0;
-    let mut failed_count = 0;
+    match state.seen_events.entry(event_id.to_string()) {
+        Entry::Occupied(entry) if now.duration_since(*entry.get()) < SEEN_EVENT_WINDOW => false,
+        Entry::Occupied(mut entry) => {
+            entry.insert(now);
+            true
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(now);
+            true
+        }
+    }
+}
 
-    for track_id in track_ids {
-        // Check in-memory dedupe (last hour)
-        if let Some(existing) = state.dedupe.get(&track_id) {
-            if now.duration_since(*existing) < Duration::from_secs(3600) {
-                continue; // Skip if seen in last hour
-            }
+/// Records a message as processed. Returns false if it was already handled within
+/// `PROCESSED_MESSAGE_WINDOW` (Slack retry, or both backfill and a live event seeing it).
+fn claim_message(state: &AppState, channel: &str, ts: &str) -> bool {
+    let now = Instant::now();
+    match state
+        .processed_messages
+        .entry(format!("{}:{}", channel, ts))
+    {
+        Entry::Occupied(entry) if now.duration_since(*entry.get()) < PROCESSED_MESSAGE_WINDOW => {
+            false
+        }
+        Entry::Occupied(mut entry) => {
+            entry.insert(now);
+            true
         }
+        Entry::Vacant(entry) => {
+            entry.insert(now);
+            true
+        }
+    }
+}
 
-        // Skip if already in playlist
-        if let Some(ref existing) = existing_tracks {
-            if existing.contains(&track_id) {
-                continue;
-            }
+/// Extracts the music URLs from a message, stripping tracking params when configured. Links
+/// to non-music hosts are dropped before any resolution is attempted.
+pub(crate) fn message_urls(config: &Config, text: &str) -> Vec<String> {
+    let (urls, non_music): (Vec<String>, Vec<String>) = extract_urls(text)
+        .into_iter()
+        .partition(|url| classify_url(url) == resolve::UrlKind::Music);
+    if !non_music.is_empty() {
+        debug!("Skipping non-music link(s): {:?}", non_music);
+    }
+    if !config.strip_tracking_params {
+        return urls;
+    }
+    urls.iter().map(|url| strip_tracking_params(url)).collect()
+}
+
+/// Whether `user` is clear of `USER_ADD_COOLDOWN_SECS`. The cooldown only starts once a
+/// message of theirs actually adds something (`start_add_cooldown`).
+fn add_cooldown_elapsed(state: &AppState, user: &str) -> bool {
+    let cooldown = state.config.user_add_cooldown;
+    cooldown.is_zero()
+        || state
+            .last_user_add
+            .get(user)
+            .is_none_or(|last| last.elapsed() >= cooldown)
+}
+
+/// Starts `user`'s `USER_ADD_COOLDOWN_SECS` after a message of theirs added tracks.
+fn start_add_cooldown(state: &AppState, user: Option<&str>) {
+    if let Some(user) = user.filter(|_| !state.config.user_add_cooldown.is_zero()) {
+        state.last_user_add.insert(user.to_string(), Instant::now());
+    }
+}
+
+/// Resolves a URL, re-running the whole pipeline up to `RESOLVE_MAX_RETRIES` more times so a
+/// transient failure (Odesli 5xx, DNS blip) doesn't immediately count as unresolvable. A link
+/// every resolver answered for but none could match isn't retried. Only the track is cached,
+/// so a cached miss doesn't say why. `playlist_id` is where the track is going, `None` for
+/// the main playlist.
+pub(crate) async fn resolve_url(
+    state: &AppState,
+    url: String,
+    playlist_id: Option<&str>,
+) -> Resolution {
+    // Show links resolve to whatever the newest episode is right now, so never cache them
+    let cacheable = parse_spotify_show_id(&url).is_none();
+    if cacheable {
+        if let Some(cached) = state.resolve_cache.get(&url) {
+            debug!("Resolution cache hit for {}: {:?}", url, cached);
+            return cached.map_or(
+                Resolution::Missed(UnresolvedReason::Unknown),
+                Resolution::Resolved,
+            );
         }
+    }
 
-        // Add to playlist (or simulate in dry-run mode)
-        if state.dry_run {
-            info!("[DRY RUN] Would add track: {}", track_id);
-            state.dedupe.insert(track_id.clone(), now);
-            added_count += 1;
-        } else {
-            match spotify_client.add_track(&track_id).await {
-                Ok(()) => {
-                    state.dedupe.insert(track_id.clone(), now);
-                    added_count += 1;
+    let mut resolution = Resolution::Failed;
+    for attempt in 0..=state.config.resolve_max_retries {
+        if attempt > 0 {
+            tokio::time::sleep(RESOLVE_RETRY_DELAY).await;
+            info!("Retrying resolution of {} (attempt {})", url, attempt + 1);
+        }
+        resolution = resolve_url_once(state, &url, playlist_id).await;
+        if resolution != Resolution::Failed {
+            break;
+        }
+    }
+
+    // A failure that may be gone next time isn't worth remembering
+    if cacheable && resolution != Resolution::Failed {
+        state
+            .resolve_cache
+            .insert(&url, resolution.clone().track_id());
+    }
+    resolution
+}
+
+/// Resolves a single URL to a Spotify track ID: Spotify links directly, anything else through
+/// the configured resolvers (`RESOLVERS`) in order, searching in the market of the playlist the
+/// track is going to.
+#[tracing::instrument(skip(state))]
+pub(crate) async fn resolve_url_once(
+    state: &AppState,
+    url: &str,
+    playlist_id: Option<&str>,
+) -> Resolution {
+    info!("Attempting to resolve URL: {}", url);
+
+    // Podcast show links mean "add the newest episode"
+    if state.config.allow_show_latest {
+        if let (Some(show_id), Some(spotify)) = (parse_spotify_show_id(url), &state.spotify) {
+            return match spotify.latest_episode(&show_id).await {
+                Ok(Some(episode)) => {
+                    info!(
+                        "Resolved show {} to latest episode {} ({})",
+                        show_id, episode.id, episode.name
+                    );
+                    let uri = format!("spotify:episode:{}", episode.id);
+                    state.latest_episodes.insert(uri.clone(), episode.name);
+                    Resolution::Resolved(uri)
                 }
+                Ok(None) => Resolution::Missed(UnresolvedReason::Unknown),
                 Err(e) => {
-                    warn!("Failed to add track {}: {}", track_id, e);
-                    failed_count += 1;
+                    warn!("Failed to fetch episodes for show {}: {}", show_id, e);
+                    Resolution::Failed
                 }
-            }
+            };
         }
     }
 
-    if added_count > 0 {
-        // Success
-        state
-            .slack
-            .reactions_add(channel, thread_ts, "musical_note")
+    // Spotify links need no lookup
+    if let Some(track_id) = parse_spotify_track_id(url) {
+        return Resolution::Resolved(track_id);
+    }
+    // Whole albums and playlists aren't added, so the reply says to share one of their tracks
+    if is_spotify_collection(url) {
+        return Resolution::Missed(UnresolvedReason::NotATrack);
+    }
+
+    debug!("Not a direct Spotify track link, trying the configured resolvers");
+    let target = playlist_id
+        .map(str::to_string)
+        .or_else(|| state.spotify.as_ref().map(|s| s.playlist_id()))
+        .unwrap_or_default();
+    // Only worth retrying if a resolver that might have matched couldn't answer. Otherwise
+    // the first resolver that could say why it missed explains the miss.
+    let mut failed = false;
+    let mut reason = UnresolvedReason::Unknown;
+    for resolver in state.resolvers.iter() {
+        match resolver.resolve(url, &target).await {
+            Resolution::Resolved(track_id) => {
+                debug!("{} resolved {}", resolver.name(), url);
+                return Resolution::Resolved(track_id);
+            }
+            // Gone is gone, whoever else is asked
+            Resolution::Missed(UnresolvedReason::Unavailable) => {
+                debug!("{} says {} is unavailable", resolver.name(), url);
+                return Resolution::Missed(UnresolvedReason::Unavailable);
+            }
+            Resolution::Missed(missed) => {
+                debug!("{} found no Spotify match", resolver.name());
+                if reason == UnresolvedReason::Unknown {
+                    reason = missed;
+                }
+            }
+            Resolution::Failed => {
+                debug!("{} failed to look {} up", resolver.name(), url);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        Resolution::Failed
+    } else {
+        Resolution::Missed(reason)
+    }
+}
+
+/// Resolves URLs concurrently (at most `RESOLVE_CONCURRENCY` in flight) and returns the
+/// results in the order the URLs were given, regardless of which resolution finishes first.
+/// Playlist add order therefore matches message order.
+async fn resolve_in_order<F, Fut, T>(urls: &[String], resolve: F) -> Vec<T>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let mut results: Vec<(usize, T)> = stream::iter(urls.iter().cloned().enumerate())
+        .map(|(index, url)| {
+            let resolution = resolve(url);
+            async move { (index, resolution.await) }
+        })
+        .buffer_unordered(RESOLVE_CONCURRENCY)
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    results
+        .into_iter()
+        .map(|(_, resolution)| resolution)
+        .collect()
+}
+
+/// Counts from adding a batch of resolved tracks to the playlist.
+struct AddOutcome {
+    added_ids: Vec<String>,
+    failed: usize,
+    /// How many of the failures were 403s
+    forbidden: usize,
+    /// How many of the failures were tracks Spotify no longer has (404)
+    not_found: usize,
+    /// How many of the added tracks were also added to `MIRROR_PLAYLIST_ID`
+    mirrored: usize,
+}
+
+/// Adds resolved tracks to the playlist, skipping recent duplicates and tracks already in
+/// the playlist, and copies each added track to the mirror playlist if one is set. Honors
+/// dry-run and shadow mode.
+async fn add_resolved_tracks(
+    state: &AppState,
+    spotify_client: &SpotifyClient,
+    channel: &str,
+    playlist_id: &str,
+    track_ids: Vec<String>,
+) -> AddOutcome {
+    // Tracks already in the playlist are skipped, as of the last (cached) fetch
+    let existing = if !state.dry_run() {
+        state.existing_playlist(spotify_client, playlist_id).await
+    } else {
+        None
+    };
+    let existing_tracks = existing
+        .as_ref()
+        .filter(|_| state.config.skip_existing_playlist_tracks)
+        .map(|snapshot| snapshot.tracks.clone());
+    // DEDUPE_BY_TITLE also skips other versions (remaster, single edit) of songs already there
+    let existing_titles = existing
+        .filter(|_| state.config.dedupe_by_title)
+        .map(|snapshot| snapshot.titles);
+    // Track ID → its title key, for tracks about to be added
+    let mut title_keys: HashMap<String, String> = HashMap::new();
+
+    // Dedupe and add tracks
+    let now = Instant::now();
+    let mut added_ids = Vec::new();
+    let mut failed_count = 0;
+    let mut forbidden_count = 0;
+    let mut not_found_count = 0;
+    let mut mirrored_count = 0;
+    // Real adds go to Spotify together once the duplicates are filtered out
+    let mut to_add = Vec::new();
+
+    for track_id in track_ids {
+        // The same song can arrive under different market-relinked IDs
+        let track_id = spotify_client
+            .canonical_track_id_for(&track_id, playlist_id)
+            .await;
+
+        // Check in-memory dedupe (`DEDUPE_WINDOW_SECS`), claiming the track so a concurrent backfill or
+        // message skips it; the claim is released below if it isn't added after all
+        let dedupe_key = state.dedupe_key(channel, playlist_id, &track_id);
+        if !state.reserve_dedupe(dedupe_key.clone(), now) {
+            state.metrics.track_deduped();
+            continue; // Skip if seen within the window
+        }
+
+        // Skip if already in playlist
+        if let Some(ref existing) = existing_tracks {
+            if existing.contains(&track_id) {
+                state.release_dedupe(&dedupe_key, now);
+                state.metrics.track_deduped();
+                continue;
+            }
+        }
+
+        if state.config.dedupe_by_title && !track_id.starts_with("spotify:") {
+            match spotify_client.get_track_metadata(&track_id).await {
+                Ok(meta) => {
+                    let key = matching::title_key(&meta.artists, &meta.title);
+                    let title_key =
+                        state.dedupe_key(channel, playlist_id, &format!("title:{}", key));
+                    let recent = state.dedupe.get(&title_key).is_some_and(|added_at| {
+                        now.duration_since(*added_at) < state.config.dedupe_window
+                    });
+                    if recent
+                        || existing_titles.as_ref().is_some_and(|t| t.contains(&key))
+                        || title_keys.values().any(|k| *k == key)
+                    {
+                        info!(
+                            "Skipping {}: another version of \"{}\" is already in the playlist",
+                            track_id, meta.title
+                        );
+                        state.release_dedupe(&dedupe_key, now);
+                        state.metrics.track_deduped();
+                        continue;
+                    }
+                    title_keys.insert(track_id.clone(), key);
+                }
+                Err(e) => warn!(
+                    "No metadata for {}, deduping it by ID only: {}",
+                    track_id, e
+                ),
+            }
+        }
+
+        // Add to playlist (or simulate in dry-run mode, or hand to reviewers in shadow mode)
+        if state.dry_run() {
+            info!("[DRY RUN] Would add track: {}", track_id);
+            mark_added(state, channel, playlist_id, &title_keys, &track_id, now);
+            added_ids.push(track_id);
+        } else if let Some(shadow_channel) = state
+            .config
+            .shadow_channel
+            .as_deref()
+            .filter(|_| state.config.shadow_mode)
+        {
+            match post_shadow_add(state, spotify_client, shadow_channel, &track_id).await {
+                Ok(()) => {
+                    mark_added(state, channel, playlist_id, &title_keys, &track_id, now);
+                    added_ids.push(track_id);
+                }
+                Err(e) => {
+                    warn!("Failed to post shadow add for {}: {}", track_id, e);
+                    state.release_dedupe(&dedupe_key, now);
+                    failed_count += 1;
+                }
+            }
+        } else {
+            to_add.push(track_id);
+        }
+    }
+
+    if !to_add.is_empty() {
+        let outcome = spotify_client.add_tracks_to(playlist_id, &to_add).await;
+        for (track_id, e) in &outcome.failed {
+            warn!("Failed to add track {}: {}", track_id, e);
+            state.release_dedupe(&state.dedupe_key(channel, playlist_id, track_id), now);
+            state.metrics.spotify_add_error(e);
+            failed_count += 1;
+            match e {
+                SpotifyError::Forbidden(_) => forbidden_count += 1,
+                // The ID will never add, so links to it shouldn't keep resolving to it
+                SpotifyError::NotFound(_) => {
+                    not_found_count += 1;
+                    state.resolve_cache.forget_track(track_id);
+                }
+                _ => {}
+            }
+        }
+        for track_id in &outcome.added {
+            mark_added(state, channel, playlist_id, &title_keys, track_id, now);
+        }
+        state.update_live_playlist(playlist_id, |snapshot| {
+            Arc::make_mut(&mut snapshot.tracks).extend(outcome.added.iter().cloned())
+        });
+        // Quarantined adds haven't been reviewed, so they aren't mirrored
+        let quarantined = state.config.quarantine_playlist_id.as_deref() == Some(playlist_id);
+        if let Some(mirror_playlist_id) = state
+            .config
+            .mirror_playlist_id
+            .as_ref()
+            .filter(|_| !quarantined)
+        {
+            let mirror = spotify_client
+                .add_tracks_to(mirror_playlist_id, &outcome.added)
+                .await;
+            for (track_id, e) in &mirror.failed {
+                warn!("Failed to mirror track {}: {}", track_id, e);
+            }
+            mirrored_count = mirror.added.len();
+        }
+        added_ids.extend(outcome.added);
+    }
+    state.metrics.tracks_added(added_ids.len());
+
+    AddOutcome {
+        added_ids,
+        failed: failed_count,
+        forbidden: forbidden_count,
+        not_found: not_found_count,
+        mirrored: mirrored_count,
+    }
+}
+
+/// Starts the dedupe window for an added track, and for its title key under `DEDUPE_BY_TITLE`.
+fn mark_added(
+    state: &AppState,
+    channel: &str,
+    playlist_id: &str,
+    title_keys: &HashMap<String, String>,
+    track_id: &str,
+    now: Instant,
+) {
+    state
+        .dedupe
+        .insert(state.dedupe_key(channel, playlist_id, track_id), now);
+    if let Some(key) = title_keys.get(track_id) {
+        state.dedupe.insert(
+            state.dedupe_key(channel, playlist_id, &format!("title:{}", key)),
+            now,
+        );
+        state
+            .added_titles
+            .insert(format!("{}/{}", playlist_id, track_id), key.clone());
+    }
+}
+
+/// Shadow mode's stand-in for adding a track: "would add: Artist – Title" in the review channel,
+/// with a link so a moderator can add it by hand.
+async fn post_shadow_add(
+    state: &AppState,
+    spotify_client: &SpotifyClient,
+    shadow_channel: &str,
+    track_id: &str,
+) -> Result<(), String> {
+    let (label, link) = match track_id.strip_prefix("spotify:episode:") {
+        Some(episode_id) => (
+            spotify_client
+                .episode_name(episode_id)
+                .await
+                .unwrap_or_else(|_| track_id.to_string()),
+            format!("https://open.spotify.com/episode/{}", episode_id),
+        ),
+        None => (
+            match spotify_client.get_track_metadata(track_id).await {
+                Ok(meta) => format!("{} – {}", meta.artists.join(", "), meta.title),
+                Err(e) => {
+                    warn!("Failed to fetch metadata for {}: {}", track_id, e);
+                    track_id.to_string()
+                }
+            },
+            format!("https://open.spotify.com/track/{}", track_id),
+        ),
+    };
+    info!("[SHADOW] Would add: {}", label);
+    state
+        .slack
+        .chat_post_message(
+            shadow_channel,
+            None,
+            &format!("would add: {} ({})", label, link),
+        )
+        .await
+        .map(|_| ())
+}
+
+/// The success emoji, or with `REACT_WITH_MOOD` one matching the first added track's audio
+/// features. Falls back to the success emoji for episodes or when features aren't available.
+async fn success_reaction(
+    state: &AppState,
+    spotify_client: &SpotifyClient,
+    added_ids: &[String],
+) -> String {
+    let track_id = added_ids.iter().find(|id| !id.starts_with("spotify:"));
+    if let (true, Some(track_id)) = (state.config.react_with_mood, track_id) {
+        match spotify_client.get_audio_features(track_id).await {
+            Ok(features) => return features.mood_emoji().to_string(),
+            Err(e) => warn!("No audio features for {}: {}", track_id, e),
+        }
+    }
+    state.emojis().success
+}
+
+/// Confirmation text for a successful add: `SUCCESS_MESSAGE_TEMPLATE` when configured,
+/// otherwise the locale's wording. Track metadata is only fetched if the template uses it.
+async fn success_message(
+    state: &AppState,
+    spotify_client: &SpotifyClient,
+    added_ids: &[String],
+    user: Option<&str>,
+) -> String {
+    let count = added_ids.len().to_string();
+    if state.config.shadow_mode {
+        return messages::render(&state.config.messages.shadow_success, &[("count", &count)]);
+    }
+    let template = state
+        .config
+        .success_message_template
+        .as_ref()
+        .unwrap_or(&state.config.messages.success);
+
+    let used = messages::placeholders(template);
+    let (mut artist, mut title) = (String::new(), String::new());
+    let (mut album, mut duration) = (String::new(), String::new());
+    if ["artist", "title", "album", "duration"]
+        .iter()
+        .any(|name| used.contains(name))
+    {
+        if let Some(track_id) = added_ids.iter().find(|id| !id.starts_with("spotify:")) {
+            match spotify_client.get_track_metadata(track_id).await {
+                Ok(meta) => {
+                    artist = meta.artists.join(", ");
+                    title = meta.title;
+                    album = meta.album.unwrap_or_default();
+                    duration = meta
+                        .duration
+                        .map(messages::format_duration)
+                        .unwrap_or_default();
+                }
+                Err(e) => warn!("Failed to fetch metadata for {}: {}", track_id, e),
+            }
+        }
+    }
+
+    let user = user.map(|u| format!("<@{}>", u)).unwrap_or_default();
+    messages::render(
+        template,
+        &[
+            ("count", &count),
+            ("artist", &artist),
+            ("title", &title),
+            ("album", &album),
+            ("duration", &duration),
+            ("user", &user),
+        ],
+    )
+}
+
+#[tracing::instrument(skip(state, text), fields(channel = %channel, ts = %thread_ts))]
+async fn process_message(
+    state: AppState,
+    channel: &str,
+    thread_ts: &str,
+    user: Option<&str>,
+    text: &str,
+    file_share: bool,
+    retry: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if is_own_message(&state, user) {
+        return Ok(());
+    }
+    let reply_thread = match state.config.confirmation_threading {
+        ConfirmationThreading::Thread => Some(thread_ts),
+        ConfirmationThreading::Channel => None,
+    };
+
+    // Extract URLs. An upload without links can still name the song in its caption.
+    let mut urls = message_urls(&state.config, text);
+    let caption = if file_share && urls.is_empty() {
+        matching::parse_artist_title(text)
+    } else {
+        None
+    };
+    if urls.is_empty() && caption.is_none() {
+        if state.config.non_music_url_behavior == NonMusicUrlBehavior::React
+            && !extract_urls(text).is_empty()
+            && claim_message(&state, channel, thread_ts)
+        {
+            state
+                .slack
+                .reactions_add(channel, thread_ts, &state.emojis().link)
+                .await
+                .map_err(|e| format!("Failed to add reaction: {}", e))?;
+        }
+        return Ok(());
+    }
+
+    if !claim_message(&state, channel, thread_ts) {
+        info!(
+            "Message {}:{} already processed, skipping",
+            channel, thread_ts
+        );
+        return Ok(());
+    }
+
+    if !state.config.can_add(user) {
+        info!(
+            "Ignoring links from {} (not in ALLOWED_ADDER_IDS)",
+            user.unwrap_or("unknown user")
+        );
+        if state.config.notify_disallowed_adders {
+            state
+                .slack
+                .chat_post_message(channel, reply_thread, &state.config.messages.not_allowed)
+                .await
+                .map_err(|e| format!("Failed to post message: {}", e))?;
+        }
+        return Ok(());
+    }
+
+    // A retry (or confirming a WARN_ON_READD re-add) finishes what the message started, so it
+    // isn't held to the cooldown
+    if let Some(user) = user.filter(|user| !retry && !add_cooldown_elapsed(&state, user)) {
+        info!(
+            "Ignoring links from {}: still within USER_ADD_COOLDOWN_SECS",
+            user
+        );
+        state
+            .slack
+            .chat_post_ephemeral(
+                channel,
+                user,
+                &messages::render(
+                    &state.config.messages.cooldown,
+                    &[(
+                        "secs",
+                        &state.config.user_add_cooldown.as_secs().to_string(),
+                    )],
+                ),
+            )
+            .await
+            .map_err(|e| format!("Failed to post ephemeral message: {}", e))?;
+        return Ok(());
+    }
+
+    // FIRST_LINK_ONLY is channel policy rather than a capacity limit, so it applies first and
+    // the poster is told quietly
+    if state.config.first_link_only && urls.len() > 1 {
+        let ignored = urls.len() - 1;
+        urls.truncate(1);
+        info!(
+            "FIRST_LINK_ONLY is on, ignoring {} more link(s) in the message",
+            ignored
+        );
+        if let Some(user) = user {
+            state
+                .slack
+                .chat_post_ephemeral(
+                    channel,
+                    user,
+                    &messages::render(
+                        &state.config.messages.first_link_only,
+                        &[("count", &ignored.to_string())],
+                    ),
+                )
+                .await
+                .map_err(|e| format!("Failed to post ephemeral message: {}", e))?;
+        }
+    }
+
+    // Without a spillover playlist to take them, links past the cap are dropped up front
+    let mut dropped_urls = 0;
+    if let Some(max) = state.config.max_urls_per_message {
+        let spills = state.config.spillover_playlist_id.is_some() && !state.config.curate_mode;
+        if urls.len() > max && !spills {
+            dropped_urls = urls.len() - max;
+            urls.truncate(max);
+            info!(
+                "Message has more than {} links, ignoring the last {}",
+                max, dropped_urls
+            );
+        }
+    }
+
+    // A quarantined user's tracks go to the quarantine playlist, whatever the message says
+    let quarantine_playlist_id = user
+        .filter(|user| state.quarantined.contains_key(*user))
+        .and(state.config.quarantine_playlist_id.as_ref());
+    if let (Some(user), Some(quarantine_playlist_id)) = (user, quarantine_playlist_id) {
+        info!(
+            "{} is quarantined, adding to playlist {}",
+            user, quarantine_playlist_id
+        );
+    }
+
+    // A configured #tag in the message routes it to another playlist
+    let route = route_for_message(&state.config.playlist_routes, text)
+        .filter(|_| quarantine_playlist_id.is_none());
+    // Otherwise the channel's own playlist; `None` means the default playlist
+    let playlist_id = quarantine_playlist_id
+        .cloned()
+        .or_else(|| route.map(|r| r.playlist_id.clone()))
+        .or_else(|| {
+            state
+                .config
+                .music_channel(channel)
+                .and_then(|c| c.playlist_id.clone())
+        });
+    if let Some(route) = route {
+        info!(
+            "Routing message to #{} playlist {}",
+            route.tag, route.playlist_id
+        );
+    }
+
+    // Resolve to Spotify track IDs, keeping the order the links appeared in the message
+    let resolved = resolve_in_order(&urls, |url| {
+        resolve_url(&state, url, playlist_id.as_deref())
+    })
+    .await;
+    let mut track_ids = Vec::new();
+    // Where each track was shared from, for `/jamcraft sources`
+    let mut sources = HashMap::new();
+    if let (Some((artist, title)), Some(spotify)) = (&caption, &state.spotify) {
+        let target = playlist_id.clone().unwrap_or_else(|| spotify.playlist_id());
+        match spotify.search_track_for(artist, title, &target).await {
+            Ok(Some(id)) => {
+                info!(
+                    "Found \"{} - {}\" from upload caption: {}",
+                    artist, title, id
+                );
+                state.metrics.track_resolved();
+                sources.insert(id.clone(), UPLOAD_SOURCE);
+                track_ids.push(id);
+            }
+            Ok(None) => {
+                state.metrics.resolution_failed();
+                warn!(
+                    "No Spotify match for upload caption \"{} - {}\"",
+                    artist, title
+                );
+            }
+            Err(e) => {
+                warn!("Spotify search for upload caption failed: {}", e);
+                state.metrics.resolution_failed();
+            }
+        }
+    }
+    // Why the last link that didn't resolve missed, for when it's the only one
+    let mut reason = UnresolvedReason::Unknown;
+    for (url, resolution) in urls.iter().zip(resolved) {
+        match resolution {
+            Resolution::Resolved(id) => {
+                info!("Successfully resolved {} to track ID: {}", url, id);
+                state.metrics.track_resolved();
+                sources.entry(id.clone()).or_insert(resolve::platform(url));
+                track_ids.push(id);
+            }
+            Resolution::Missed(missed) => {
+                warn!("Failed to resolve URL: {} ({:?})", url, missed);
+                state.metrics.resolution_failed();
+                reason = missed;
+            }
+            Resolution::Failed => {
+                warn!("Failed to resolve URL: {}", url);
+                state.metrics.resolution_failed();
+                reason = UnresolvedReason::Unknown;
+            }
+        }
+    }
+
+    if track_ids.is_empty() {
+        // Couldn't resolve any track
+        state
+            .slack
+            .reactions_add(channel, thread_ts, &state.emojis().unresolved)
             .await
             .map_err(|e| format!("Failed to add reaction: {}", e))?;
 
-        let message = format!("Added {} track(s) to the playlist ✅", added_count);
+        // With a single link, say whether it's broken/private or just not on Spotify
+        if urls.len() != 1 {
+            reason = UnresolvedReason::Unknown;
+        }
+        let texts = &state.config.messages;
+        let message = match reason {
+            _ if caption.is_some() => texts.unresolved_caption.clone(),
+            UnresolvedReason::Unavailable => texts.unavailable.clone(),
+            UnresolvedReason::NotOnSpotify { artist, title } => messages::render(
+                &texts.not_on_spotify,
+                &[("artist", &artist), ("title", &title)],
+            ),
+            UnresolvedReason::NotATrack => texts.not_a_track.clone(),
+            UnresolvedReason::Unknown => texts.unresolved.clone(),
+        };
         state
             .slack
-            .chat_post_message(channel, Some(thread_ts), &message)
+            .chat_post_message(channel, reply_thread, &message)
             .await
             .map_err(|e| format!("Failed to post message: {}", e))?;
-    } else if failed_count > 0 {
-        // Add attempts failed (e.g. 403)
+
+        return Ok(());
+    }
+
+    // Check if Spotify is configured
+    let spotify_client = match &state.spotify {
+        Some(client) => client,
+        None => {
+            warn!("Spotify not configured - cannot add tracks to playlist");
+            state
+                .slack
+                .reactions_add(channel, thread_ts, &state.emojis().unresolved)
+                .await
+                .map_err(|e| format!("Failed to add reaction: {}", e))?;
+
+            state
+                .slack
+                .chat_post_message(channel, reply_thread, &state.config.messages.not_configured)
+                .await
+                .map_err(|e| format!("Failed to post message: {}", e))?;
+
+            return Ok(());
+        }
+    };
+
+    let playlist_id = playlist_id.unwrap_or_else(|| spotify_client.playlist_id().to_string());
+
+    // Canonical IDs up front, so sources line up with the IDs add_resolved_tracks reports
+    for track_id in &mut track_ids {
+        let canonical = spotify_client
+            .canonical_track_id_for(track_id, &playlist_id)
+            .await;
+        if let Some(source) = sources.get(track_id.as_str()).copied() {
+            sources.insert(canonical.clone(), source);
+        }
+        *track_id = canonical;
+    }
+
+    if state.config.warn_on_readd {
+        let (readds, rest): (Vec<String>, Vec<String>) = track_ids.into_iter().partition(|id| {
+            state
+                .recently_removed
+                .get(id)
+                .is_some_and(|removed_at| removed_at.elapsed() < READD_WARN_WINDOW)
+        });
+        track_ids = rest;
+        if !readds.is_empty() {
+            // Forget the removal so confirming with the retry reaction goes through
+            for id in &readds {
+                state.recently_removed.remove(id);
+            }
+            state
+                .slack
+                .reactions_add(channel, thread_ts, &state.emojis().readd)
+                .await
+                .map_err(|e| format!("Failed to add reaction: {}", e))?;
+            let message = messages::render(
+                &state.config.messages.readd_warning,
+                &[
+                    ("count", &readds.len().to_string()),
+                    ("emoji", &state.emojis().retry),
+                ],
+            );
+            state
+                .slack
+                .chat_post_message(channel, reply_thread, &message)
+                .await
+                .map_err(|e| format!("Failed to post message: {}", e))?;
+            if track_ids.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    if state.config.curate_mode {
+        let count = track_ids.len();
+        state.pending.insert(
+            format!("{}:{}", channel, thread_ts),
+            PendingAdd {
+                channel: channel.to_string(),
+                ts: thread_ts.to_string(),
+                user: user.map(str::to_string),
+                playlist_id: playlist_id.to_string(),
+                track_ids,
+                sources,
+                note: messages::extract_note(text),
+                queued_at: Instant::now(),
+            },
+        );
+        info!("Queued {} track(s) from {} for approval", count, thread_ts);
+        // Queued for approval counts as adding, or curation would sidestep the cooldown
+        start_add_cooldown(&state, user);
+
         state
             .slack
-            .reactions_add(channel, thread_ts, "grey_question")
+            .reactions_add(channel, thread_ts, &state.emojis().pending)
             .await
             .map_err(|e| format!("Failed to add reaction: {}", e))?;
 
+        let message = messages::render(
+            &state.config.messages.queued,
+            &[("count", &count.to_string())],
+        );
         state
             .slack
-            .chat_post_message(
+            .chat_post_message(channel, reply_thread, &message)
+            .await
+            .map_err(|e| format!("Failed to post message: {}", e))?;
+
+        return Ok(());
+    }
+
+    // Tracks past MAX_URLS_PER_MESSAGE go to the spillover playlist instead
+    let spillover_ids = match (
+        state.config.max_urls_per_message,
+        &state.config.spillover_playlist_id,
+    ) {
+        // A quarantined user's extra tracks stay in quarantine too
+        (Some(max), Some(_)) if track_ids.len() > max && quarantine_playlist_id.is_none() => {
+            track_ids.split_off(max)
+        }
+        _ => Vec::new(),
+    };
+
+    let AddOutcome {
+        mut added_ids,
+        failed: mut failed_count,
+        forbidden: mut forbidden_count,
+        not_found: mut not_found_count,
+        mirrored: mut mirrored_count,
+    } = add_resolved_tracks(&state, spotify_client, channel, &playlist_id, track_ids).await;
+    let note = messages::extract_note(text);
+    if !added_ids.is_empty() {
+        record_added_tracks(
+            &state,
+            channel,
+            thread_ts,
+            user,
+            &playlist_id,
+            &added_ids,
+            &sources,
+        );
+        notify_tracks_added(
+            &state,
+            &TracksAdded {
+                channel,
+                ts: thread_ts,
+                user,
+                playlist_id: &playlist_id,
+                track_ids: &added_ids,
+                note: note.as_deref(),
+            },
+        );
+    }
+
+    let mut spilled_count = 0;
+    if let Some(spillover_playlist_id) = state
+        .config
+        .spillover_playlist_id
+        .as_ref()
+        .filter(|_| !spillover_ids.is_empty())
+    {
+        let spilled = add_resolved_tracks(
+            &state,
+            spotify_client,
+            channel,
+            spillover_playlist_id,
+            spillover_ids,
+        )
+        .await;
+        info!(
+            "Added {} overflow track(s) to spillover playlist {}",
+            spilled.added_ids.len(),
+            spillover_playlist_id
+        );
+        if !spilled.added_ids.is_empty() {
+            record_added_tracks(
+                &state,
+                channel,
+                thread_ts,
+                user,
+                spillover_playlist_id,
+                &spilled.added_ids,
+                &sources,
+            );
+        }
+        notify_tracks_added(
+            &state,
+            &TracksAdded {
                 channel,
-                Some(thread_ts),
-                "Couldn't add track(s) to the playlist—Spotify returned an error. If this keeps happening, try running the bot locally (Spotify may block cloud servers).",
+                ts: thread_ts,
+                user,
+                playlist_id: spillover_playlist_id,
+                track_ids: &spilled.added_ids,
+                note: note.as_deref(),
+            },
+        );
+        spilled_count = spilled.added_ids.len();
+        added_ids.extend(spilled.added_ids);
+        failed_count += spilled.failed;
+        forbidden_count += spilled.forbidden;
+        not_found_count += spilled.not_found;
+        mirrored_count += spilled.mirrored;
+    }
+    let added_count = added_ids.len();
+
+    if added_count > 0 {
+        // Success
+        start_add_cooldown(&state, user);
+        let reaction = success_reaction(&state, spotify_client, &added_ids).await;
+        state
+            .slack
+            .reactions_add(channel, thread_ts, &reaction)
+            .await
+            .map_err(|e| format!("Failed to add reaction: {}", e))?;
+        // Only when the mirror add went through; failures are just logged
+        if state.config.show_mirror_status && mirrored_count > 0 {
+            state
+                .slack
+                .reactions_add(channel, thread_ts, &state.emojis().mirrored)
+                .await
+                .map_err(|e| format!("Failed to add reaction: {}", e))?;
+        }
+
+        let texts = &state.config.messages;
+        let max = state
+            .config
+            .max_urls_per_message
+            .unwrap_or_default()
+            .to_string();
+        let mut lines = vec![success_message(&state, spotify_client, &added_ids, user).await];
+        if let Some(route) = route {
+            lines.push(messages::render(&texts.routed, &[("tag", &route.tag)]));
+        }
+        if spilled_count > 0 {
+            lines.push(messages::render(
+                &texts.spilled,
+                &[("count", &spilled_count.to_string()), ("max", &max)],
+            ));
+        }
+        if dropped_urls > 0 {
+            lines.push(messages::render(
+                &texts.dropped,
+                &[("count", &dropped_urls.to_string()), ("max", &max)],
+            ));
+        }
+        for (_, name) in added_ids
+            .iter()
+            .filter_map(|id| state.latest_episodes.remove(id))
+        {
+            lines.push(messages::render(&texts.latest_episode, &[("name", &name)]));
+        }
+        let message = lines.join("\n");
+        // Shadow mode added nothing, so there'd be nothing to undo
+        let blocks = (state.config.undo_button && !state.config.shadow_mode).then(|| {
+            interactions::undo_blocks(
+                &message,
+                &texts.undo_button,
+                &format!("{}:{}", channel, thread_ts),
             )
+        });
+        let confirmation_ts = state
+            .slack
+            .chat_post_blocks(channel, reply_thread, &message, blocks)
             .await
             .map_err(|e| format!("Failed to post message: {}", e))?;
-    } else {
-        // All tracks were duplicates
+        // Votes remove what went to the message's playlist; spilled tracks are listed last
+        if let Some(confirmation_ts) = confirmation_ts.filter(|_| state.config.reaction_voting) {
+            state.vote_cards.insert(
+                format!("{}:{}", channel, confirmation_ts),
+                voting::VoteCard::new(
+                    format!("{}:{}", channel, thread_ts),
+                    playlist_id.clone(),
+                    added_ids[..added_count - spilled_count].to_vec(),
+                ),
+            );
+        }
+    } else if failed_count > 0 {
+        // Add attempts failed; only 403s get the cloud-host hint, tracks Spotify dropped say so
         state
             .slack
-            .reactions_add(channel, thread_ts, "grey_question")
+            .reactions_add(channel, thread_ts, &state.emojis().unresolved)
             .await
             .map_err(|e| format!("Failed to add reaction: {}", e))?;
 
+        let message = if forbidden_count == failed_count {
+            state.config.spotify_403_message.as_str()
+        } else if not_found_count == failed_count {
+            state.config.messages.not_found.as_str()
+        } else {
+            state.config.messages.failure.as_str()
+        };
         state
             .slack
-            .chat_post_message(
-                channel,
-                Some(thread_ts),
-                "All tracks are already in the playlist.",
-            )
+            .chat_post_message(channel, reply_thread, message)
             .await
             .map_err(|e| format!("Failed to post message: {}", e))?;
+    } else {
+        // All tracks were duplicates
+        match state.config.on_all_duplicates {
+            DuplicateBehavior::Notify => {
+                state
+                    .slack
+                    .reactions_add(channel, thread_ts, &state.emojis().unresolved)
+                    .await
+                    .map_err(|e| format!("Failed to add reaction: {}", e))?;
+
+                state
+                    .slack
+                    .chat_post_message(channel, reply_thread, &state.config.messages.duplicate)
+                    .await
+                    .map_err(|e| format!("Failed to post message: {}", e))?;
+            }
+            DuplicateBehavior::ReactOnly => {
+                state
+                    .slack
+                    .reactions_add(channel, thread_ts, &state.emojis().duplicate)
+                    .await
+                    .map_err(|e| format!("Failed to add reaction: {}", e))?;
+            }
+            DuplicateBehavior::Silent => {}
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use config::parse_playlist_routes;
+    use wiremock::matchers::{body_string_contains, method, path, path_regex};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn message_target_skips_automated_subtypes() {
+        let event = |subtype: &str| -> types::SlackEvent {
+            serde_json::from_value(json!({
+                "type": "message",
+                "subtype": subtype,
+                "channel": "C123",
+                "user": "U42",
+                "ts": "1700000000.000100",
+                "text": "friday drop https://open.spotify.com/track/abc"
+            }))
+            .unwrap()
+        };
+        assert!(message_target(event("scheduled_message")).is_some());
+        assert!(message_target(event("file_share")).is_some_and(|t| t.file_share));
+        assert_eq!(message_target(event("channel_join")), None);
+    }
+
+    #[tokio::test]
+    async fn message_target_uses_original_ts_for_edits() {
+        let envelope = SlackEnvelope::parse(
+            json!({
+                "type": "event_callback",
+                "event": {
+                    "type": "message",
+                    "subtype": "message_changed",
+                    "channel": "C123",
+                    "ts": "1700000100.000200",
+                    "message": {
+                        "type": "message",
+                        "user": "U42",
+                        "text": "forgot the link https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+                        "ts": "1700000000.000100"
+                    },
+                    "previous_message": { "text": "forgot the link", "ts": "1700000000.000100" }
+                }
+            })
+            .to_string()
+            .as_bytes(),
+        )
+        .unwrap();
+
+        let SlackEnvelope::Event(event) = envelope else {
+            panic!("expected an event");
+        };
+        let target = message_target(*event).unwrap();
+        assert_eq!(target.ts, "1700000000.000100");
+        assert_eq!(target.user.as_deref(), Some("U42"));
+        assert_eq!(target.channel, "C123");
+
+        // The reaction goes on the edited message, not on the edit event
+        let (slack, _spotify, state) = mock_state(&[]).await;
+        process_message(
+            state.clone(),
+            &target.channel,
+            &target.ts,
+            target.user.as_deref(),
+            &target.text,
+            target.file_share,
+            false,
+        )
+        .await
+        .unwrap();
+        let reactions = bodies(&slack, "POST", "/reactions.add").await;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0]["channel"], "C123");
+        assert_eq!(reactions[0]["timestamp"], "1700000000.000100");
+        assert_eq!(reactions[0]["name"], state.emojis().success.as_str());
+
+        let emptied = SlackEnvelope::parse(
+            json!({
+                "type": "event_callback",
+                "event": {
+                    "type": "message",
+                    "subtype": "message_changed",
+                    "channel": "C123",
+                    "ts": "1700000100.000200",
+                    "message": { "text": "", "ts": "1700000000.000100" }
+                }
+            })
+            .to_string()
+            .as_bytes(),
+        )
+        .unwrap();
+        let SlackEnvelope::Event(emptied) = emptied else {
+            panic!("expected an event");
+        };
+        assert_eq!(message_target(*emptied), None);
+    }
+
+    #[test]
+    fn playlist_changes_reports_both_directions() {
+        let set = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<HashSet<_>>();
+        let previous = set(&["a", "b", "c"]);
+        let current = set(&["b", "c", "e", "d"]);
+
+        let (added, removed) = playlist_changes(&previous, &current);
+        assert_eq!(added, ["d", "e"]);
+        assert_eq!(removed, ["a"]);
+    }
+
+    #[test]
+    fn route_for_message_matches_whole_tags() {
+        let routes = parse_playlist_routes("#chill:pl_chill, rock:pl_rock").unwrap();
+        let route = |text| route_for_message(&routes, text).map(|r| r.playlist_id.as_str());
+
+        assert_eq!(route("#Chill vibes https://x"), Some("pl_chill"));
+        assert_eq!(route("https://x for the #rock."), Some("pl_rock"));
+        assert_eq!(route("#rockabilly https://x"), None);
+        assert_eq!(route("https://x"), None);
+    }
+
+    #[tokio::test]
+    async fn resolve_in_order_keeps_message_order() {
+        let urls = vec![
+            "https://example.com/slow".to_string(),
+            "https://example.com/medium".to_string(),
+            "https://example.com/fast".to_string(),
+        ];
+
+        // Later URLs finish first
+        let resolved = resolve_in_order(&urls, |url| async move {
+            let delay = match url.rsplit('/').next() {
+                Some("slow") => 60,
+                Some("medium") => 30,
+                _ => 0,
+            };
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Some(url.rsplit('/').next().unwrap_or_default().to_string())
+        })
+        .await;
+
+        assert_eq!(
+            resolved,
+            vec![
+                Some("slow".to_string()),
+                Some("medium".to_string()),
+                Some("fast".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resolve_in_order_caps_concurrency_and_keeps_going_past_failures() {
+        use std::sync::atomic::AtomicUsize;
+
+        let urls: Vec<String> = (0..12)
+            .map(|i| format!("https://example.com/{}", i))
+            .collect();
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let resolved = resolve_in_order(&urls, |url| {
+            let (in_flight, peak) = (&in_flight, &peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                // Every third link fails to resolve
+                let n: usize = url.rsplit('/').next()?.parse().ok()?;
+                (!n.is_multiple_of(3)).then(|| n.to_string())
+            }
+        })
+        .await;
+
+        assert_eq!(peak.load(Ordering::SeqCst), RESOLVE_CONCURRENCY);
+        assert_eq!(resolved.len(), 12);
+        assert_eq!(resolved[0], None);
+        assert_eq!(resolved[1].as_deref(), Some("1"));
+        assert_eq!(resolved[11].as_deref(), Some("11"));
+        assert_eq!(resolved.iter().flatten().count(), 8);
+    }
+
+    /// Slack and Spotify mocks, and state wired to them. The playlist `pl` holds `existing`.
+    pub(crate) async fn mock_state(existing: &[&str]) -> (MockServer, MockServer, AppState) {
+        let slack = MockServer::start().await;
+        let spotify = MockServer::start().await;
+        let ok = ResponseTemplate::new(200).set_body_json(json!({ "ok": true }));
+        Mock::given(method("POST"))
+            .respond_with(ok)
+            .mount(&slack)
+            .await;
+
+        let items: Vec<Value> = existing
+            .iter()
+            .map(|id| json!({ "item": { "uri": format!("spotify:track:{}", id) } }))
+            .collect();
+        Mock::given(method("GET"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "items": items, "total": existing.len() })),
+            )
+            .mount(&spotify)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+        // Not relinked, so every track is its own canonical ID
+        Mock::given(method("GET"))
+            .and(path_regex("^/tracks/"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "track" })))
+            .mount(&spotify)
+            .await;
+
+        let vars: HashMap<&str, &str> = HashMap::from([
+            ("SLACK_BOT_TOKEN", "xoxb-test"),
+            ("SLACK_SIGNING_SECRET", "secret"),
+            ("SPOTIFY_CLIENT_ID", "id"),
+            ("SPOTIFY_CLIENT_SECRET", "secret"),
+            ("SPOTIFY_REFRESH_TOKEN", "refresh"),
+            ("SPOTIFY_PLAYLIST_ID", "pl"),
+            ("RESOLVE_MAX_RETRIES", "0"),
+        ]);
+        let config = Config::from_lookup(|name| vars.get(name).map(|v| v.to_string())).unwrap();
+        let slack_client = SlackWebClient::new("xoxb-test".to_string()).with_api_base(&slack.uri());
+        let spotify_client = SpotifyClient::new(
+            "id".to_string(),
+            "secret".to_string(),
+            "refresh".to_string(),
+            "pl".to_string(),
+        )
+        .with_api_base(&spotify.uri())
+        .with_access_token("token");
+        let mut state = AppState::new(
+            config,
+            Arc::new(slack_client),
+            Some(Arc::new(spotify_client)),
+            Some("UBOT".to_string()),
+        );
+        // Only Spotify links resolve, so nothing leaves the mocks
+        state.resolvers = Arc::new(Vec::new());
+        (slack, spotify, state)
+    }
+
+    /// Bodies of the requests `server` received for `method` and `path`.
+    pub(crate) async fn bodies(server: &MockServer, method: &str, path: &str) -> Vec<Value> {
+        server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.method.as_str() == method && r.url.path() == path)
+            .map(|r| serde_json::from_slice(&r.body).unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn process_message_adds_a_new_track() {
+        let (slack, spotify, state) = mock_state(&["other"]).await;
+        let text = "tune https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"] })]
+        );
+        let reactions = bodies(&slack, "POST", "/reactions.add").await;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0]["name"], state.emojis().success.as_str());
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["text"], "Added 1 track(s) to the playlist ✅");
+        assert_eq!(messages[0]["thread_ts"], "1.1");
+    }
+
+    #[tokio::test]
+    async fn shadow_mode_posts_to_the_shadow_channel_instead_of_adding() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.shadow_mode = true;
+        state.config.shadow_channel = Some("CSHADOW".to_string());
+        Mock::given(method("GET"))
+            .and(path("/tracks/4uLU6hMCjMI75M1A2tKUQC"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                json!({ "name": "Helicopter", "artists": [{ "name": "Bloc Party" }] }),
+            ))
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(spotify
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .all(|r| r.method.as_str() != "POST"));
+        let shadow: Vec<Value> = bodies(&slack, "POST", "/chat.postMessage")
+            .await
+            .into_iter()
+            .filter(|m| m["channel"] == "CSHADOW")
+            .collect();
+        assert_eq!(shadow.len(), 1);
+        assert_eq!(
+            shadow[0]["text"],
+            "would add: Bloc Party – Helicopter (https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC)"
+        );
+    }
+
+    #[tokio::test]
+    async fn only_a_403_gets_the_spotify_403_message() {
+        for (status, reply) in [
+            (403, "Spotify blocked this host."),
+            (400, messages::Messages::default().failure.as_str()),
+        ] {
+            let (slack, spotify, mut state) = mock_state(&[]).await;
+            state.config.spotify_403_message = "Spotify blocked this host.".to_string();
+            Mock::given(method("POST"))
+                .and(path("/playlists/pl/items"))
+                .respond_with(ResponseTemplate::new(status))
+                .with_priority(1)
+                .mount(&spotify)
+                .await;
+            let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+            process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+                .await
+                .unwrap();
+
+            let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0]["text"], reply, "for a {}", status);
+        }
+
+        // One 403 among other failures is no sign of a blocked host
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.spotify_403_message = "Spotify blocked this host.".to_string();
+        let (blocked, broken) = ("4uLU6hMCjMI75M1A2tKUQC", "7ouMYWpwJ422jRcDASZB7P");
+        Mock::given(method("POST"))
+            .and(path("/playlists/pl/items"))
+            .and(body_string_contains(blocked))
+            .and(body_string_contains(broken))
+            .respond_with(ResponseTemplate::new(400))
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        for (id, status) in [(blocked, 403), (broken, 400)] {
+            Mock::given(method("POST"))
+                .and(path("/playlists/pl/items"))
+                .and(body_string_contains(id))
+                .respond_with(ResponseTemplate::new(status))
+                .with_priority(2)
+                .mount(&spotify)
+                .await;
+        }
+        let text = format!(
+            "https://open.spotify.com/track/{} https://open.spotify.com/track/{}",
+            blocked, broken
+        );
+        process_message(state.clone(), "C1", "1.2", Some("U1"), &text, false, false)
+            .await
+            .unwrap();
+
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(
+            messages[0]["text"],
+            messages::Messages::default().failure.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_slack_request_rejects_a_replayed_signature() {
+        use hmac::{Hmac, Mac};
+        let (_slack, _spotify, state) = mock_state(&[]).await;
+        let body = b"token=x&command=%2Fjamcraft&text=recent";
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(format!("v0:{}:", timestamp).as_bytes());
+        mac.update(body);
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Slack-Request-Timestamp", timestamp.parse().unwrap());
+        headers.insert("X-Slack-Signature", signature.parse().unwrap());
+        assert_eq!(verify_slack_request(&state, &headers, body), Ok(()));
+        assert_eq!(
+            verify_slack_request(&state, &headers, body),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[tokio::test]
+    async fn socket_mode_rejects_unsigned_slack_requests() {
+        use hmac::{Hmac, Mac};
+        let (_slack, _spotify, mut state) = mock_state(&[]).await;
+        state.config.socket_mode = true;
+        state.config.signing_secret = String::new();
+
+        // Signed with the empty key, which is what an attacker could compute
+        let body = b"command=%2Fjamcraft&text=dryrun&user_id=UADMIN";
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string();
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"").unwrap();
+        mac.update(format!("v0:{}:", timestamp).as_bytes());
+        mac.update(body);
+        let signature = format!("v0={}", hex::encode(mac.finalize().into_bytes()));
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Slack-Request-Timestamp", timestamp.parse().unwrap());
+        headers.insert("X-Slack-Signature", signature.parse().unwrap());
+        assert_eq!(
+            verify_slack_request(&state, &headers, body),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = build_router(state);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let client = reqwest::Client::new();
+        let response = client
+            .post(format!("http://{}/slack/commands", addr))
+            .header("X-Slack-Request-Timestamp", timestamp.as_str())
+            .header("X-Slack-Signature", signature.as_str())
+            .body(body.to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+        let health = client
+            .get(format!("http://{}/health", addr))
+            .send()
+            .await
+            .unwrap();
+        assert!(health.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn claim_event_drops_redeliveries_of_an_event() {
+        let (_slack, _spotify, state) = mock_state(&[]).await;
+        assert!(claim_event(&state, "Ev1"));
+        assert!(!claim_event(&state, "Ev1"));
+        assert!(claim_event(&state, "Ev2"));
+
+        // Past the window, the ID is claimable again
+        state
+            .seen_events
+            .insert("Ev1".into(), Instant::now() - SEEN_EVENT_WINDOW);
+        assert!(claim_event(&state, "Ev1"));
+    }
+
+    #[tokio::test]
+    async fn deep_health_reports_the_failing_dependency() {
+        let (slack, spotify, state) = mock_state(&[]).await;
+        Mock::given(method("POST"))
+            .and(path("/auth.test"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                json!({ "ok": true, "user_id": "UBOT", "user": "jamcraft", "team": "T" }),
+            ))
+            .with_priority(1)
+            .mount(&slack)
+            .await;
+
+        let (status, Json(body)) = deep_health_handler(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["slack"]["ok"], true);
+        assert_eq!(body["spotify"]["ok"], false);
+
+        Mock::given(method("GET"))
+            .and(path("/me"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": "bot" })))
+            .mount(&spotify)
+            .await;
+        let (status, Json(body)) = deep_health_handler(State(state)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn process_message_adds_pasted_spotify_uris_alongside_links() {
+        let (_slack, spotify, state) = mock_state(&[]).await;
+        let text = "spotify:track:1301WleyT98MSxVHPZCA6M and \
+                    https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state, "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": [
+                "spotify:track:1301WleyT98MSxVHPZCA6M",
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+            ] })]
+        );
+    }
+
+    #[tokio::test]
+    async fn process_message_asks_for_a_track_instead_of_an_album() {
+        let (slack, spotify, state) = mock_state(&[]).await;
+        let text = "spotify:album:1DFixLWuPkv3KT3TnV35m3";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(
+            messages[0]["text"],
+            state.config.messages.not_a_track.as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn existing_playlist_tracks_are_fetched_once_and_skipped() {
+        let (_slack, spotify, state) = mock_state(&["4uLU6hMCjMI75M1A2tKUQC"]).await;
+        for (ts, id) in [
+            ("1.1", "1301WleyT98MSxVHPZCA6M"),
+            ("2.2", "4uLU6hMCjMI75M1A2tKUQC"),
+        ] {
+            let text = format!("https://open.spotify.com/track/{}", id);
+            process_message(state.clone(), "C1", ts, Some("U1"), &text, false, false)
+                .await
+                .unwrap();
+        }
+
+        let fetches = spotify
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.method.as_str() == "GET" && r.url.path() == "/playlists/pl/items")
+            .count();
+        assert_eq!(fetches, 1);
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": ["spotify:track:1301WleyT98MSxVHPZCA6M"] })]
+        );
+        assert!(state
+            .live_playlists
+            .get("pl")
+            .unwrap()
+            .tracks
+            .contains("1301WleyT98MSxVHPZCA6M"));
+    }
+
+    #[tokio::test]
+    async fn refreshing_a_playlist_forgets_tracks_removed_outside_the_bot() {
+        let (_slack, _spotify, state) = mock_state(&["kept"]).await;
+        let spotify = state.spotify.clone().unwrap();
+        state.live_playlists.insert(
+            "pl".into(),
+            PlaylistSnapshot {
+                fetched_at: Instant::now() - PLAYLIST_TRACKS_REFRESH,
+                tracks: Arc::new(HashSet::from(["kept".into(), "gone".into()])),
+                titles: Arc::default(),
+            },
+        );
+        state.dedupe.insert("gone".into(), Instant::now());
+
+        let snapshot = state.existing_playlist(&spotify, "pl").await.unwrap();
+        assert_eq!(*snapshot.tracks, HashSet::from(["kept".to_string()]));
+        assert!(!state.dedupe.contains_key("gone"));
+    }
+
+    #[tokio::test]
+    async fn title_dedupe_reads_the_playlist_once_for_ids_and_titles() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.dedupe_by_title = true;
+        let helicopter = json!({ "name": "Helicopter", "artists": [{ "name": "Bloc Party" }] });
+        let mut item = helicopter.clone();
+        item["type"] = json!("track");
+        item["uri"] = json!("spotify:track:0ldVersion0000000000000");
+        Mock::given(method("GET"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "items": [{ "item": item }], "total": 1 })),
+            )
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/tracks/1301WleyT98MSxVHPZCA6M"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(helicopter))
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+
+        let text = "https://open.spotify.com/track/1301WleyT98MSxVHPZCA6M";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        let fetches = spotify
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|r| r.method.as_str() == "GET" && r.url.path() == "/playlists/pl/items")
+            .count();
+        assert_eq!(fetches, 1);
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+    }
+
+    /// A resolver that always answers the same, counting how often it was asked.
+    struct FixedResolver {
+        name: &'static str,
+        resolution: Resolution,
+        calls: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl UrlResolver for FixedResolver {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        async fn resolve(&self, _url: &str, _playlist_id: &str) -> Resolution {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            self.resolution.clone()
+        }
+    }
+
+    fn fixed_resolver(
+        name: &'static str,
+        resolution: Resolution,
+    ) -> (Box<dyn UrlResolver>, Arc<std::sync::atomic::AtomicUsize>) {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let resolver = FixedResolver {
+            name,
+            resolution,
+            calls: calls.clone(),
+        };
+        (Box::new(resolver), calls)
+    }
+
+    #[tokio::test]
+    async fn resolvers_run_in_resolvers_order_until_one_resolves() {
+        let (_slack, _spotify, mut state) = mock_state(&[]).await;
+        let names = ["qobuz", "odesli", "deezer"].map(String::from);
+        let built = build_resolvers(&names, state.spotify.clone(), 0.8);
+        assert_eq!(
+            built.iter().map(|r| r.name()).collect::<Vec<_>>(),
+            ["qobuz", "odesli", "deezer"]
+        );
+
+        let (first, first_calls) =
+            fixed_resolver("qobuz", Resolution::Missed(UnresolvedReason::Unknown));
+        let (second, second_calls) = fixed_resolver("odesli", Resolution::Resolved("b".into()));
+        let (third, third_calls) = fixed_resolver("deezer", Resolution::Resolved("c".into()));
+        state.resolvers = Arc::new(vec![first, second, third]);
+        assert_eq!(
+            resolve_url_once(&state, "https://www.deezer.com/track/1", None).await,
+            Resolution::Resolved("b".into())
+        );
+        let calls = [first_calls, second_calls, third_calls]
+            .map(|calls| calls.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(calls, [1, 1, 0]);
+    }
+
+    #[tokio::test]
+    async fn resolution_is_only_retried_after_a_transient_failure() {
+        let (_slack, _spotify, mut state) = mock_state(&[]).await;
+        state.config.resolve_max_retries = 1;
+        let url = "https://www.deezer.com/track/1";
+
+        let missed = Resolution::Missed(UnresolvedReason::Unknown);
+        let (resolver, calls) = fixed_resolver("deezer", missed.clone());
+        state.resolvers = Arc::new(vec![resolver]);
+        assert_eq!(resolve_url(&state, url.to_string(), None).await, missed);
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(state.resolve_cache.get(url), Some(None));
+
+        state.resolve_cache.remove(url);
+        let (failed, calls) = fixed_resolver("deezer", Resolution::Failed);
+        state.resolvers = Arc::new(vec![failed]);
+        assert_eq!(
+            resolve_url(&state, url.to_string(), None).await,
+            Resolution::Failed
+        );
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        // Not cached, so the next message with the link tries again
+        assert_eq!(state.resolve_cache.get(url), None);
+    }
+
+    #[tokio::test]
+    async fn unresolved_reply_explains_the_miss_without_looking_the_link_up_again() {
+        let (slack, _spotify, mut state) = mock_state(&[]).await;
+        let not_on_spotify = Resolution::Missed(UnresolvedReason::NotOnSpotify {
+            artist: "Radiohead".into(),
+            title: "Creep".into(),
+        });
+        let (first, first_calls) = fixed_resolver("odesli", not_on_spotify);
+        let (second, second_calls) =
+            fixed_resolver("deezer", Resolution::Missed(UnresolvedReason::Unknown));
+        state.resolvers = Arc::new(vec![first, second]);
+
+        let text = "https://www.deezer.com/track/1";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(
+            messages[0]["text"],
+            "Found \"Radiohead – Creep\", but it doesn't seem to be on Spotify."
+        );
+        assert_eq!(first_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(second_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn add_cooldown_starts_only_once_something_is_added() {
+        let (_slack, spotify, mut state) = mock_state(&["4uLU6hMCjMI75M1A2tKUQC"]).await;
+        state.config.user_add_cooldown = Duration::from_secs(60);
+        let post = |ts: &'static str, id: &'static str, retry: bool| {
+            let state = state.clone();
+            async move {
+                let text = format!("https://open.spotify.com/track/{}", id);
+                process_message(state, "C1", ts, Some("U1"), &text, false, retry)
+                    .await
+                    .unwrap();
+            }
+        };
+
+        // A duplicate adds nothing, so doesn't hold up the next message
+        post("1.1", "4uLU6hMCjMI75M1A2tKUQC", false).await;
+        post("1.2", "1301WleyT98MSxVHPZCA6M", false).await;
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await.len(),
+            1
+        );
+
+        post("1.3", "7ouMYWpwJ422jRcDASZB7P", false).await;
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await.len(),
+            1
+        );
+        // A retry isn't held to it
+        state.processed_messages.remove("C1:1.3");
+        post("1.3", "7ouMYWpwJ422jRcDASZB7P", true).await;
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await.len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn spilled_tracks_are_recorded_and_undone_with_the_message() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        for verb in ["GET", "POST", "DELETE"] {
+            Mock::given(method(verb))
+                .and(path("/playlists/pl_spill/items"))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(json!({ "items": [], "total": 0, "snapshot_id": "s" })),
+                )
+                .mount(&spotify)
+                .await;
+        }
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+        state.config.max_urls_per_message = Some(1);
+        state.config.spillover_playlist_id = Some("pl_spill".into());
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC \
+                    https://open.spotify.com/track/1301WleyT98MSxVHPZCA6M";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl_spill/items").await,
+            vec![json!({ "uris": ["spotify:track:1301WleyT98MSxVHPZCA6M"] })]
+        );
+        let playlists: Vec<String> = state
+            .history
+            .latest(10)
+            .into_iter()
+            .map(|r| r.playlist_id)
+            .collect();
+        assert_eq!(playlists, vec!["pl_spill", "pl"]);
+
+        assert_eq!(
+            undo_added_tracks(&state, "C1:1.1", "U1").await,
+            UndoOutcome::Removed(2)
+        );
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl_spill/items").await,
+            vec![json!({ "tracks": [{ "uri": "spotify:track:1301WleyT98MSxVHPZCA6M" }] })]
+        );
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl/items").await,
+            vec![json!({ "tracks": [{ "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC" }] })]
+        );
+    }
+
+    #[tokio::test]
+    async fn process_message_adds_to_the_channels_own_playlist() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        for verb in ["GET", "POST"] {
+            Mock::given(method(verb))
+                .and(path("/playlists/pl_rock/items"))
+                .respond_with(
+                    ResponseTemplate::new(201)
+                        .set_body_json(json!({ "items": [], "total": 0, "snapshot_id": "s" })),
+                )
+                .mount(&spotify)
+                .await;
+        }
+        state.config.music_channels = vec![config::MusicChannel {
+            name: "rock".into(),
+            playlist_id: Some("pl_rock".into()),
+            id: "C2".into(),
+        }];
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C2", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl_rock/items").await,
+            vec![json!({ "uris": ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"] })]
+        );
+    }
+
+    #[tokio::test]
+    async fn undo_button_removes_the_messages_tracks_for_its_poster_only() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+        state.config.undo_button = true;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        let posted = bodies(&slack, "POST", "/chat.postMessage").await;
+        let button = &posted[0]["blocks"][1]["elements"][0];
+        assert_eq!(button["action_id"], interactions::UNDO_ACTION_ID);
+        assert_eq!(button["value"], "C1:1.1");
+
+        let response_url = format!("{}/response", slack.uri());
+        interactions::undo_button(&state, "C1:1.1", "U2", &response_url).await;
+        assert!(bodies(&spotify, "DELETE", "/playlists/pl/items")
+            .await
+            .is_empty());
+        interactions::undo_button(&state, "C1:1.1", "U1", &response_url).await;
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl/items").await,
+            vec![json!({ "tracks": [{ "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC" }] })]
+        );
+        let answers = bodies(&slack, "POST", "/response").await;
+        assert_eq!(answers[0]["response_type"], "ephemeral");
+        assert_eq!(answers[1]["replace_original"], true);
+        assert_eq!(answers[1]["text"], state.config.messages.undone.as_str());
+    }
+
+    #[tokio::test]
+    async fn undo_reaction_posts_the_localized_undone_message() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+        state.config.messages.undone_by_reaction =
+            "{count} nummer(s) verwijderd door {user}".into();
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        undo_message(state, "C1".to_string(), "1.1".to_string(), "U1".to_string()).await;
+        let posted = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(posted[1]["text"], "1 nummer(s) verwijderd door <@U1>");
+        assert_eq!(posted[1]["thread_ts"], "1.1");
+    }
+
+    #[tokio::test]
+    async fn undone_track_can_be_reposted_under_title_dedupe() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.dedupe_by_title = true;
+        Mock::given(method("GET"))
+            .and(path("/tracks/1301WleyT98MSxVHPZCA6M"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(
+                json!({ "name": "Helicopter", "artists": [{ "name": "Bloc Party" }] }),
+            ))
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+        let text = "https://open.spotify.com/track/1301WleyT98MSxVHPZCA6M";
+        process_message(state.clone(), "C1", "1.1", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+        // As a reconcile would see it once the add has landed
+        let title = matching::title_key(&["Bloc Party".to_string()], "Helicopter");
+        state.update_live_playlist("pl", |snapshot| {
+            Arc::make_mut(&mut snapshot.titles).insert(title.clone());
+        });
+
+        undo_message(
+            state.clone(),
+            "C1".to_string(),
+            "1.1".to_string(),
+            "U1".to_string(),
+        )
+        .await;
+        assert!(!state
+            .live_playlists
+            .get("pl")
+            .unwrap()
+            .titles
+            .contains(&title));
+        process_message(state.clone(), "C1", "1.2", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await.len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn process_message_reacts_to_a_duplicate() {
+        let (slack, spotify, state) = mock_state(&["4uLU6hMCjMI75M1A2tKUQC"]).await;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.2", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        let reactions = bodies(&slack, "POST", "/reactions.add").await;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0]["name"], state.emojis().duplicate.as_str());
+        assert!(bodies(&slack, "POST", "/chat.postMessage").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn backfill_and_a_live_message_add_a_shared_track_once() {
+        let (_slack, spotify, state) = mock_state(&[]).await;
+        let spotify_client = state.spotify.clone().unwrap();
+        let mut adder = BulkAdder::new(&spotify_client, "C1", "pl").await;
+        let url = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+
+        let (_, live) = tokio::join!(
+            async {
+                adder.add_url(&state, url.to_string()).await;
+                adder.flush(&state).await;
+            },
+            process_message(state.clone(), "C1", "1.1", Some("U1"), url, false, false),
+        );
+        live.unwrap();
+
+        let adds = bodies(&spotify, "POST", "/playlists/pl/items").await;
+        assert_eq!(adds.len(), 1);
+        assert_eq!(
+            adds[0]["uris"],
+            json!(["spotify:track:4uLU6hMCjMI75M1A2tKUQC"])
+        );
+    }
+
+    #[tokio::test]
+    async fn bulk_adds_go_to_spotify_in_one_request() {
+        let (_slack, spotify, state) = mock_state(&["other"]).await;
+        let mut adder = BulkAdder::new(state.spotify.as_ref().unwrap(), "C1", "pl").await;
+        for id in ["4uLU6hMCjMI75M1A2tKUQC", "other", "7ouMYWpwJ422jRcDASZB7P"] {
+            adder
+                .add_url(&state, format!("https://open.spotify.com/track/{}", id))
+                .await;
+        }
+        adder.flush(&state).await;
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": [
+                "spotify:track:4uLU6hMCjMI75M1A2tKUQC",
+                "spotify:track:7ouMYWpwJ422jRcDASZB7P",
+            ] })]
+        );
+        assert_eq!((adder.added, adder.already_present), (2, 1));
+        assert_eq!(state.history.take_latest(5).len(), 2);
+        let metrics = state.metrics.render(&state.resolve_cache.stats());
+        assert!(metrics.contains("\njamcraft_tracks_deduped_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn curate_mode_backfill_queues_messages_for_approval() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.curate_mode = true;
+        Mock::given(method("GET"))
+            .and(path("/conversations.history"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "ok": true,
+                "messages": [{
+                    "ts": "1.1",
+                    "user": "U1",
+                    "text": "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC",
+                }],
+            })))
+            .mount(&slack)
+            .await;
+
+        let spotify_client = state.spotify.clone().unwrap();
+        let summary = backfill_channel(&state, &spotify_client, "C1", "pl", false)
+            .await
+            .unwrap();
+
+        assert_eq!(summary.added, 0);
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        let item = state.pending.get("C1:1.1").unwrap().clone();
+        assert_eq!(item.user.as_deref(), Some("U1"));
+        assert_eq!(item.track_ids, vec!["4uLU6hMCjMI75M1A2tKUQC"]);
+    }
+
+    #[tokio::test]
+    async fn process_message_counts_resolved_added_and_deduped_tracks() {
+        let (_slack, _spotify, state) = mock_state(&[]).await;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        for ts in ["1.1", "1.2"] {
+            process_message(state.clone(), "C1", ts, Some("U1"), text, false, false)
+                .await
+                .unwrap();
+        }
+
+        let metrics = state.metrics.render(&state.resolve_cache.stats());
+        assert!(metrics.contains("\njamcraft_tracks_resolved_total 2\n"));
+        assert!(metrics.contains("\njamcraft_tracks_added_total 1\n"));
+        assert!(metrics.contains("\njamcraft_tracks_deduped_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn process_message_replies_in_the_configured_locale() {
+        let (slack, _spotify, mut state) = mock_state(&["4uLU6hMCjMI75M1A2tKUQC"]).await;
+        state.config.on_all_duplicates = DuplicateBehavior::Notify;
+        state.config.messages = messages::Messages::load(
+            "nl",
+            Some(r#"{"nl": {"duplicate": "Die staan er allemaal al in."}}"#),
+        )
+        .unwrap();
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.2", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0]["text"], "Die staan er allemaal al in.");
+    }
+
+    #[tokio::test]
+    async fn process_message_adds_only_the_first_link_under_first_link_only() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.first_link_only = true;
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC https://open.spotify.com/track/7ouMYWpwJ422jRcDASZB7P";
+        process_message(state.clone(), "C1", "1.4", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await,
+            vec![json!({ "uris": ["spotify:track:4uLU6hMCjMI75M1A2tKUQC"] })]
+        );
+        let notes = bodies(&slack, "POST", "/chat.postEphemeral").await;
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0]["user"], "U1");
+    }
+
+    #[tokio::test]
+    async fn per_channel_dedupe_only_skips_adds_to_the_same_playlist() {
+        let (_slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.dedupe_scope = DedupeScope::PerChannel;
+        let track = "4uLU6hMCjMI75M1A2tKUQC";
+        // Just added to a routed playlist from the same channel
+        state
+            .dedupe
+            .insert(state.dedupe_key("C1", "rock", track), Instant::now());
+
+        let text = format!("https://open.spotify.com/track/{}", track);
+        process_message(state.clone(), "C1", "1.5", Some("U1"), &text, false, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            bodies(&spotify, "POST", "/playlists/pl/items").await.len(),
+            1
+        );
+        assert!(state.dedupe.contains_key(&format!("C1/pl/{}", track)));
+
+        state.forget_dedupe("pl", track);
+        assert!(!state.dedupe.contains_key(&format!("C1/pl/{}", track)));
+        assert!(state.dedupe.contains_key(&format!("C1/rock/{}", track)));
+
+        state
+            .dedupe
+            .insert(format!("C2/pl/title:{}", track), Instant::now());
+        state.forget_playlist_dedupe("pl");
+        assert!(!state.dedupe.contains_key(&format!("C2/pl/title:{}", track)));
+        assert!(state.dedupe.contains_key(&format!("C1/rock/{}", track)));
+    }
+
+    #[tokio::test]
+    async fn remove_votes_on_a_confirmation_take_its_tracks_out() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.reaction_voting = true;
+        state.config.vote_remove_threshold = 2;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "ts": "9.9" })),
+            )
+            .with_priority(1)
+            .mount(&slack)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.6", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+        assert!(state.vote_cards.contains_key("C1:9.9"));
+
+        for voter in ["U2", "U3"] {
+            voting::on_reaction(
+                state.clone(),
+                "C1".into(),
+                "9.9".into(),
+                voter.into(),
+                voting::REMOVE_VOTE_EMOJI.into(),
+                true,
+            )
+            .await;
+        }
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl/items").await,
+            vec![json!({ "tracks": [{ "uri": "spotify:track:4uLU6hMCjMI75M1A2tKUQC" }] })]
+        );
+        assert!(!state.vote_cards.contains_key("C1:9.9"));
+    }
+
+    #[tokio::test]
+    async fn undo_closes_the_messages_vote_card() {
+        let (slack, spotify, mut state) = mock_state(&[]).await;
+        state.config.reaction_voting = true;
+        state.config.vote_remove_threshold = 1;
+        Mock::given(method("POST"))
+            .and(path("/chat.postMessage"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({ "ok": true, "ts": "9.9" })),
+            )
+            .with_priority(1)
+            .mount(&slack)
+            .await;
+        // The first removal fails, so the card and its votes must survive it
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(400))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&spotify)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/pl/items"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "snapshot_id": "s" })))
+            .mount(&spotify)
+            .await;
+
+        let text = "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC";
+        process_message(state.clone(), "C1", "1.6", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+        let vote = |voter: &str| {
+            voting::on_reaction(
+                state.clone(),
+                "C1".into(),
+                "9.9".into(),
+                voter.into(),
+                voting::REMOVE_VOTE_EMOJI.into(),
+                true,
+            )
+        };
+        vote("U2").await;
+        assert!(state.vote_cards.contains_key("C1:9.9"));
+
+        assert_eq!(
+            undo_added_tracks(&state, "C1:1.6", "U1").await,
+            UndoOutcome::Removed(1)
+        );
+        assert!(!state.vote_cards.contains_key("C1:9.9"));
+        // A late vote on the undone message deletes nothing
+        vote("U3").await;
+        assert_eq!(
+            bodies(&spotify, "DELETE", "/playlists/pl/items")
+                .await
+                .len(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn process_message_explains_unresolved_links() {
+        let (slack, spotify, state) = mock_state(&[]).await;
+        // Two links, so there's no single-link diagnosis to fetch
+        let text = "https://tidal.com/browse/track/1 https://tidal.com/browse/track/2";
+        process_message(state.clone(), "C1", "1.3", Some("U1"), text, false, false)
+            .await
+            .unwrap();
+
+        assert!(bodies(&spotify, "POST", "/playlists/pl/items")
+            .await
+            .is_empty());
+        let reactions = bodies(&slack, "POST", "/reactions.add").await;
+        assert_eq!(reactions.len(), 1);
+        assert_eq!(reactions[0]["name"], state.emojis().unresolved.as_str());
+        let messages = bodies(&slack, "POST", "/chat.postMessage").await;
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("Couldn't resolve that link"));
+    }
+}
@@ -0,0 +1,167 @@
+/// Where an inline featured-artist credit starts, e.g. "Get Lucky feat. Pharrell".
+const FEATURING_MARKERS: [&str; 5] = [" feat. ", " feat ", " ft. ", " ft ", " featuring "];
+
+/// Lowercases and strips punctuation and common decorations ("feat. X", "(Remastered)",
+/// "- Radio Edit") so titles/artists from different catalogs compare on their core words.
+pub fn normalize(s: &str) -> String {
+    let lower = s.to_lowercase();
+
+    // Drop bracketed suffixes: "(feat. X)", "[Live]", "(2011 Remaster)"
+    let mut without_brackets = String::with_capacity(lower.len());
+    let mut depth = 0usize;
+    for c in lower.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            _ if depth == 0 => without_brackets.push(c),
+            _ => {}
+        }
+    }
+
+    // Drop trailing " - Remastered 2009" style suffixes and inline featured artists
+    let mut core = without_brackets.split(" - ").next().unwrap_or_default();
+    for marker in FEATURING_MARKERS {
+        core = core.split(marker).next().unwrap_or_default();
+    }
+
+    core.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Word-overlap similarity (Dice coefficient) between two strings after normalization.
+/// Returns a value in [0, 1].
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = normalize(a);
+    let b = normalize(b);
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+
+    let a_words: std::collections::HashSet<&str> = a.split(' ').collect();
+    let b_words: std::collections::HashSet<&str> = b.split(' ').collect();
+    let shared = a_words.intersection(&b_words).count();
+    (2 * shared) as f64 / (a_words.len() + b_words.len()) as f64
+}
+
+/// Scores a search candidate against the artist/title we were looking for. The title
+/// weighs more than the artist since scraped artist names are the least reliable part
+/// (collaborations, "feat." credits, "The" prefixes).
+pub fn match_score(
+    want_artist: &str,
+    want_title: &str,
+    candidate_artists: &[String],
+    candidate_title: &str,
+) -> f64 {
+    let title_score = similarity(want_title, candidate_title);
+    let artist_score = candidate_artists
+        .iter()
+        .map(|a| similarity(want_artist, a))
+        .fold(0.0, f64::max);
+    0.7 * title_score + 0.3 * artist_score
+}
+
+/// A version-independent key for a song, `artist|title` from the primary artist and the
+/// normalized title, so a remaster or single edit matches the album version (`DEDUPE_BY_TITLE`).
+pub fn title_key(artists: &[String], title: &str) -> String {
+    let artist = artists.first().map(|a| normalize(a)).unwrap_or_default();
+    format!("{}|{}", artist, normalize(title))
+}
+
+/// Whether `needle`'s words appear together in `haystack`, after normalization.
+pub fn mentions(haystack: &str, needle: &str) -> bool {
+    let needle = normalize(needle);
+    !needle.is_empty() && format!(" {} ", normalize(haystack)).contains(&format!(" {} ", needle))
+}
+
+/// Artist and title from free text like "Radiohead - Creep" or "Creep by Radiohead", e.g. the
+/// caption of an uploaded audio file. Only the first line is considered.
+pub fn parse_artist_title(text: &str) -> Option<(String, String)> {
+    let line = text.lines().next()?.trim();
+    let clean = |s: &str| {
+        s.trim()
+            .trim_matches(|c: char| c == '"' || c == '\'' || c == '“' || c == '”')
+            .trim()
+            .to_string()
+    };
+    let (artist, title) = [" - ", " – ", " — "]
+        .iter()
+        .find_map(|sep| line.split_once(sep))
+        .or_else(|| {
+            line.rsplit_once(" by ")
+                .map(|(title, artist)| (artist, title))
+        })?;
+    let (artist, title) = (clean(artist), clean(title));
+    (!artist.is_empty() && !title.is_empty()).then_some((artist, title))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_strips_decorations() {
+        assert_eq!(normalize("Helicopter (2011 Remaster)"), "helicopter");
+        assert_eq!(normalize("Creep - Radio Edit"), "creep");
+        assert_eq!(normalize("Get Lucky feat. Pharrell"), "get lucky");
+        assert_eq!(normalize("Don't Stop Me Now!"), "don t stop me now");
+        assert_eq!(normalize("Get Lucky featuring Pharrell"), "get lucky");
+        assert_eq!(normalize("Daft Punk ft Pharrell"), "daft punk");
+    }
+
+    #[test]
+    fn match_score_accepts_collaborations_and_rejects_garbage() {
+        let artists = vec!["Daft Punk".to_string(), "Pharrell Williams".to_string()];
+        assert!(
+            match_score(
+                "Daft Punk feat. Pharrell",
+                "Get Lucky",
+                &artists,
+                "Get Lucky"
+            ) > 0.9
+        );
+        assert!(match_score("Daft Punk", "Get Lucky", &artists, "Around the World") < 0.5);
+    }
+
+    #[test]
+    fn title_key_ignores_versions() {
+        let artists = vec!["Queen".to_string(), "David Bowie".to_string()];
+        assert_eq!(
+            title_key(&artists, "Under Pressure - Remastered 2011"),
+            title_key(&artists[..1], "Under Pressure (Single Version)")
+        );
+        assert_eq!(
+            title_key(&artists, "Under Pressure"),
+            "queen|under pressure"
+        );
+    }
+
+    #[test]
+    fn mentions_matches_whole_words() {
+        assert!(mentions(
+            "Queen Bohemian Rhapsody live",
+            "Bohemian Rhapsody"
+        ));
+        assert!(mentions(
+            "bohemian rhapsody",
+            "Bohemian Rhapsody (Remastered 2011)"
+        ));
+        assert!(!mentions("Bohemian Rhapsodyy", "Bohemian Rhapsody"));
+        assert!(!mentions("anything", ""));
+    }
+
+    #[test]
+    fn parse_artist_title_reads_captions() {
+        let creep = Some(("Radiohead".to_string(), "Creep".to_string()));
+        assert_eq!(parse_artist_title("Radiohead - Creep"), creep);
+        assert_eq!(parse_artist_title("\"Creep\" by Radiohead\nso good"), creep);
+        assert_eq!(parse_artist_title("listen to this"), None);
+        assert_eq!(parse_artist_title(" - Creep"), None);
+    }
+}
@@ -0,0 +1,325 @@
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// Slack-formatted links (`<https://…>`, `<https://…|label>`) and bare URLs
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<https?://[^>]*>|https?://\S+").expect("Invalid link regex"));
+
+/// Placeholders available in `SUCCESS_MESSAGE_TEMPLATE`.
+pub const SUCCESS_PLACEHOLDERS: &[&str] =
+    &["count", "artist", "title", "album", "duration", "user"];
+
+/// A track length as `{duration}` shows it, e.g. "3:40".
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// Names of the `{placeholder}`s used in a template, in order of appearance.
+pub fn placeholders(template: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) => {
+                names.push(&after[..end]);
+                rest = &after[end + 1..];
+            }
+            None => break,
+        }
+    }
+    names
+}
+
+/// Checks that every placeholder in the template is one we know how to fill.
+pub fn validate_template(template: &str, allowed: &[&str]) -> Result<(), String> {
+    let unknown: Vec<&str> = placeholders(template)
+        .into_iter()
+        .filter(|name| !allowed.contains(name))
+        .collect();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "unknown placeholder(s) {} (allowed: {})",
+            unknown
+                .iter()
+                .map(|n| format!("{{{}}}", n))
+                .collect::<Vec<_>>()
+                .join(", "),
+            allowed.join(", ")
+        ))
+    }
+}
+
+/// Substitutes `{name}` placeholders with their values in one pass over the template, so a
+/// value that itself contains `{name}` (a song title, a user's note) is left as it is.
+/// Placeholders without a value stay in the text.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let name = &after[..end];
+        // A stray `{` before a placeholder is just text
+        if name.contains('{') {
+            text.push('{');
+            rest = after;
+            continue;
+        }
+        match values.iter().find(|(n, _)| *n == name) {
+            Some((_, value)) => text.push_str(value),
+            None => text.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Locale whose bundle is built in; every other one comes from `MESSAGES_FILE`.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Everything the bot says in reply to a shared link, in one language (`LOCALE`). Strings
+/// missing from a `MESSAGES_FILE` bundle keep their English wording.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Messages {
+    pub success: String,
+    /// Success under `SHADOW_MODE`, where moderators review the adds
+    pub shadow_success: String,
+    pub queued: String,
+    /// Appended to the success message for `#tag`-routed messages
+    pub routed: String,
+    pub spilled: String,
+    pub dropped: String,
+    pub latest_episode: String,
+    pub duplicate: String,
+    pub unresolved: String,
+    /// An upload whose caption named a song Spotify doesn't have
+    pub unresolved_caption: String,
+    pub unavailable: String,
+    pub not_on_spotify: String,
+    /// A Spotify album or playlist link, which isn't added whole
+    pub not_a_track: String,
+    pub not_configured: String,
+    pub failure: String,
+    /// Spotify answered 403; `SPOTIFY_403_MESSAGE` takes precedence
+    pub forbidden: String,
+    pub not_found: String,
+    pub not_allowed: String,
+    pub cooldown: String,
+    pub first_link_only: String,
+    pub readd_warning: String,
+    /// Label of the success message's undo button (`UNDO_BUTTON`)
+    pub undo_button: String,
+    /// Replaces the success message once its undo button removed the tracks
+    pub undone: String,
+    /// Posted in thread when the undo reaction removed a message's tracks
+    pub undone_by_reaction: String,
+    /// Shown only to someone who pressed another member's undo button
+    pub undo_not_allowed: String,
+    /// Undo pressed after the tracks were already removed or the hour to undo them ran out
+    pub undo_expired: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            success: "Added {count} track(s) to the playlist ✅".into(),
+            shadow_success: "Sent {count} track(s) to the moderators for review 👀".into(),
+            queued: "Queued {count} track(s) for moderator approval ⏳".into(),
+            routed: "Added to the #{tag} playlist".into(),
+            spilled: "{count} of them went to the spillover playlist (more than {max} in one message)".into(),
+            dropped: "Only the first {max} links in a message are added; {count} skipped".into(),
+            latest_episode: "Latest episode: {name}".into(),
+            duplicate: "All tracks are already in the playlist.".into(),
+            unresolved: "Couldn't resolve that link—try a Spotify link or include artist + title.".into(),
+            unresolved_caption: "Couldn't find that song on Spotify—try posting a link to it.".into(),
+            unavailable: "That link points to something private, removed or unavailable—check it opens for you, or share a public link.".into(),
+            not_on_spotify: "Found \"{artist} – {title}\", but it doesn't seem to be on Spotify.".into(),
+            not_a_track: "That's a whole album or playlist, and only single tracks are added—share a link to the song you want in the playlist.".into(),
+            not_configured: "Spotify is not configured. Please set SPOTIFY_CLIENT_ID, SPOTIFY_CLIENT_SECRET, SPOTIFY_REFRESH_TOKEN, and SPOTIFY_PLAYLIST_ID in your .env file.".into(),
+            failure: "Couldn't add track(s) to the playlist—Spotify returned an error. Try again in a bit.".into(),
+            forbidden: "Couldn't add track(s) to the playlist—Spotify returned 403 Forbidden. If this keeps happening, try running the bot locally (Spotify may block cloud servers).".into(),
+            not_found: "That track no longer exists on Spotify.".into(),
+            not_allowed: "Thanks for the share! Only a few members can add to the playlist in this channel, so ask one of them to post it.".into(),
+            cooldown: "Slow down a little: wait {secs}s between messages with links. That one wasn't added.".into(),
+            first_link_only: "This channel is one song per message, so only your first link was added; {count} other link(s) ignored.".into(),
+            readd_warning: "{count} track(s) here were just removed from the playlist — adding it back? React with :{emoji}: to add it anyway.".into(),
+            undo_button: "Undo".into(),
+            undone: "Removed ✅".into(),
+            undone_by_reaction: "Removed {count} track(s) from the playlist (undone by {user})".into(),
+            undo_not_allowed: "Only whoever posted the link or a moderator can undo this.".into(),
+            undo_expired: "There's nothing left to undo here: the tracks were already removed, or it's been more than an hour.".into(),
+        }
+    }
+}
+
+impl Messages {
+    /// The `locale` bundle from a `MESSAGES_FILE`, a JSON object of locale → key → string.
+    /// `en` needn't be in the file; if it is, it overrides the built-in wording.
+    pub fn load(locale: &str, file: Option<&str>) -> Result<Self, String> {
+        let mut bundles: HashMap<String, Messages> = match file {
+            Some(file) => serde_json::from_str(file).map_err(|e| format!("invalid JSON: {}", e))?,
+            None => HashMap::new(),
+        };
+        let messages = match bundles.remove(locale) {
+            Some(messages) => messages,
+            None if locale == DEFAULT_LOCALE => Messages::default(),
+            None => {
+                let mut known: Vec<String> = bundles.into_keys().collect();
+                known.push(DEFAULT_LOCALE.to_string());
+                known.sort();
+                return Err(format!(
+                    "no {:?} bundle (available: {})",
+                    locale,
+                    known.join(", ")
+                ));
+            }
+        };
+        messages.validate()?;
+        Ok(messages)
+    }
+
+    /// Checks each string only uses the placeholders filled in for it.
+    fn validate(&self) -> Result<(), String> {
+        let strings: [(&str, &str, &[&str]); 26] = [
+            ("success", &self.success, SUCCESS_PLACEHOLDERS),
+            ("shadow_success", &self.shadow_success, &["count"]),
+            ("queued", &self.queued, &["count"]),
+            ("routed", &self.routed, &["tag"]),
+            ("spilled", &self.spilled, &["count", "max"]),
+            ("dropped", &self.dropped, &["count", "max"]),
+            ("latest_episode", &self.latest_episode, &["name"]),
+            ("duplicate", &self.duplicate, &[]),
+            ("unresolved", &self.unresolved, &[]),
+            ("unresolved_caption", &self.unresolved_caption, &[]),
+            ("unavailable", &self.unavailable, &[]),
+            ("not_on_spotify", &self.not_on_spotify, &["artist", "title"]),
+            ("not_a_track", &self.not_a_track, &[]),
+            ("not_configured", &self.not_configured, &[]),
+            ("failure", &self.failure, &[]),
+            ("forbidden", &self.forbidden, &[]),
+            ("not_found", &self.not_found, &[]),
+            ("not_allowed", &self.not_allowed, &[]),
+            ("cooldown", &self.cooldown, &["secs"]),
+            ("first_link_only", &self.first_link_only, &["count"]),
+            ("readd_warning", &self.readd_warning, &["count", "emoji"]),
+            ("undo_button", &self.undo_button, &[]),
+            ("undone", &self.undone, &[]),
+            (
+                "undone_by_reaction",
+                &self.undone_by_reaction,
+                &["count", "user"],
+            ),
+            ("undo_not_allowed", &self.undo_not_allowed, &[]),
+            ("undo_expired", &self.undo_expired, &[]),
+        ];
+        for (key, text, allowed) in strings {
+            validate_template(text, allowed).map_err(|e| format!("{}: {}", key, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The prose around the link(s) in a message, e.g. "for the friday mix", with whitespace
+/// collapsed. `None` if the message is only links.
+pub fn extract_note(text: &str) -> Option<String> {
+    let without_links = LINK_REGEX.replace_all(text, " ");
+    let note = without_links
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if note.chars().any(char::is_alphanumeric) {
+        Some(note)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_falls_back_to_english_per_string() {
+        assert_eq!(Messages::load("en", None).unwrap(), Messages::default());
+
+        let file = r#"{"nl": {"success": "{count} nummer(s) toegevoegd ✅", "duplicate": "Staat er al in."}}"#;
+        let nl = Messages::load("nl", Some(file)).unwrap();
+        assert_eq!(nl.success, "{count} nummer(s) toegevoegd ✅");
+        assert_eq!(nl.duplicate, "Staat er al in.");
+        assert_eq!(nl.failure, Messages::default().failure);
+
+        assert_eq!(
+            Messages::load("de", Some(file)).unwrap_err(),
+            "no \"de\" bundle (available: en, nl)"
+        );
+        assert!(Messages::load("nl", Some(r#"{"nl": {"sucess": "x"}}"#)).is_err());
+        assert!(
+            Messages::load("nl", Some(r#"{"nl": {"queued": "{user}"}}"#))
+                .unwrap_err()
+                .starts_with("queued: unknown placeholder(s) {user}")
+        );
+    }
+
+    #[test]
+    fn extract_note_drops_links() {
+        assert_eq!(
+            extract_note("for the friday mix <https://open.spotify.com/track/abc|open.spotify.com/track/abc> 🔥").as_deref(),
+            Some("for the friday mix 🔥")
+        );
+        assert_eq!(
+            extract_note("https://a.example/1\n  https://b.example/2 ").as_deref(),
+            None
+        );
+        assert_eq!(extract_note("<https://a.example/1> - ").as_deref(), None);
+    }
+
+    #[test]
+    fn render_fills_placeholders() {
+        let text = render(
+            "🎶 {user} added {title} by {artist} ({count})",
+            &[
+                ("count", "1"),
+                ("artist", "Bloc Party"),
+                ("title", "Helicopter"),
+                ("user", "<@U123>"),
+            ],
+        );
+        assert_eq!(text, "🎶 <@U123> added Helicopter by Bloc Party (1)");
+
+        let text = render(
+            "{user}: {note} ({count}, {missing})",
+            &[
+                ("note", "shout {user} and {count}"),
+                ("user", "<@U1>"),
+                ("count", "2"),
+            ],
+        );
+        assert_eq!(text, "<@U1>: shout {user} and {count} (2, {missing})");
+        assert_eq!(render("{ {count} }", &[("count", "3")]), "{ 3 }");
+    }
+
+    #[test]
+    fn format_duration_pads_seconds() {
+        use std::time::Duration;
+        assert_eq!(format_duration(Duration::from_millis(220_560)), "3:40");
+        assert_eq!(format_duration(Duration::from_secs(65)), "1:05");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "62:05");
+    }
+
+    #[test]
+    fn validate_template_rejects_unknown_placeholders() {
+        assert!(validate_template("Added {count} to the vibes", SUCCESS_PLACEHOLDERS).is_ok());
+        assert!(validate_template("Added {cnt}", SUCCESS_PLACEHOLDERS).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use crate::cache::CacheStats;
+use crate::spotify::SpotifyError;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// `SpotifyError` variants, as labelled in `jamcraft_spotify_add_errors_total`.
+const SPOTIFY_ERROR_VARIANTS: [&str; 8] = [
+    "Network",
+    "Auth",
+    "RateLimit",
+    "Forbidden",
+    "NotFound",
+    "PlaylistNotFound",
+    "Api",
+    "Other",
+];
+
+/// Counters served at `GET /metrics`, in the Prometheus text format. They start at zero with
+/// each process, as Prometheus counters may.
+#[derive(Default)]
+pub struct Metrics {
+    tracks_resolved: AtomicU64,
+    resolution_failures: AtomicU64,
+    tracks_added: AtomicU64,
+    tracks_deduped: AtomicU64,
+    /// Failed adds, indexed like `SPOTIFY_ERROR_VARIANTS`
+    spotify_add_errors: [AtomicU64; SPOTIFY_ERROR_VARIANTS.len()],
+}
+
+impl Metrics {
+    pub fn track_resolved(&self) {
+        self.tracks_resolved.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn resolution_failed(&self) {
+        self.resolution_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn tracks_added(&self, count: usize) {
+        self.tracks_added.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn track_deduped(&self) {
+        self.tracks_deduped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn spotify_add_error(&self, error: &SpotifyError) {
+        let index = SPOTIFY_ERROR_VARIANTS
+            .iter()
+            .position(|&variant| variant == error.variant())
+            .unwrap_or(SPOTIFY_ERROR_VARIANTS.len() - 1);
+        self.spotify_add_errors[index].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// All counters, plus the resolution cache's, in the Prometheus text exposition format
+    /// (version 0.0.4).
+    pub fn render(&self, cache: &CacheStats) -> String {
+        let mut out = String::new();
+        let counters = [
+            (
+                "jamcraft_tracks_resolved_total",
+                "Links resolved to a Spotify track",
+                &self.tracks_resolved,
+            ),
+            (
+                "jamcraft_resolution_failures_total",
+                "Links no resolver could match to Spotify",
+                &self.resolution_failures,
+            ),
+            (
+                "jamcraft_tracks_added_total",
+                "Tracks added to a playlist",
+                &self.tracks_added,
+            ),
+            (
+                "jamcraft_tracks_deduped_total",
+                "Tracks skipped as recently added or already in the playlist",
+                &self.tracks_deduped,
+            ),
+        ];
+        let cache_counters = [
+            (
+                "jamcraft_resolve_cache_hits_total",
+                "Links answered from the resolution cache",
+                cache.hits,
+            ),
+            (
+                "jamcraft_resolve_cache_misses_total",
+                "Links the resolution cache had no fresh entry for",
+                cache.misses,
+            ),
+        ];
+        let counters = counters
+            .into_iter()
+            .map(|(name, help, value)| (name, help, value.load(Ordering::Relaxed)))
+            .chain(cache_counters);
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+
+        let name = "jamcraft_spotify_add_errors_total";
+        let _ = writeln!(
+            out,
+            "# HELP {} Tracks Spotify refused to add, by error",
+            name
+        );
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        for (variant, value) in SPOTIFY_ERROR_VARIANTS.iter().zip(&self.spotify_add_errors) {
+            let _ = writeln!(
+                out,
+                "{}{{error=\"{}\"}} {}",
+                name,
+                variant,
+                value.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_lists_every_counter_and_error_variant() {
+        let metrics = Metrics::default();
+        metrics.track_resolved();
+        metrics.tracks_added(3);
+        metrics.spotify_add_error(&SpotifyError::RateLimit(5));
+        metrics.spotify_add_error(&SpotifyError::RateLimit(1));
+
+        let cache = CacheStats {
+            hits: 4,
+            ..CacheStats::default()
+        };
+        let text = metrics.render(&cache);
+        assert!(text.contains("# TYPE jamcraft_tracks_added_total counter\n"));
+        assert!(text.contains("\njamcraft_tracks_resolved_total 1\n"));
+        assert!(text.contains("\njamcraft_tracks_added_total 3\n"));
+        assert!(text.contains("\njamcraft_tracks_deduped_total 0\n"));
+        assert!(text.contains("\njamcraft_resolve_cache_hits_total 4\n"));
+        assert!(text.contains("\njamcraft_spotify_add_errors_total{error=\"RateLimit\"} 2\n"));
+        assert!(text.contains("\njamcraft_spotify_add_errors_total{error=\"Network\"} 0\n"));
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+use std::time::Duration;
+
+/// Posts a JSON event to `NOTIFY_WEBHOOK_URL` whenever tracks are added, so an external app
+/// can show what was added, by whom and why.
+pub struct WebhookNotifier {
+    url: String,
+    client: reqwest::Client,
+}
+
+/// Payload sent to the webhook.
+#[derive(Debug, Serialize)]
+pub struct TracksAdded<'a> {
+    pub channel: &'a str,
+    pub ts: &'a str,
+    pub user: Option<&'a str>,
+    pub playlist_id: &'a str,
+    pub track_ids: &'a [String],
+    /// Text the poster wrote around the link(s), e.g. "for the friday mix"
+    pub note: Option<&'a str>,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Posts an already-serialized event (see [`TracksAdded`]).
+    pub async fn post(&self, payload: &serde_json::Value) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Webhook returned {}", response.status()));
+        }
+
+        Ok(())
+    }
+}
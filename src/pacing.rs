@@ -0,0 +1,115 @@
+use std::time::{Duration, Instant};
+
+/// AIMD delay between bulk requests: shrinks by a fixed step after each request that went
+/// through and doubles when the API throttles us, so a healthy run stays fast and a
+/// throttled one slows down on its own.
+#[derive(Debug, Clone)]
+pub struct AdaptiveDelay {
+    current: Duration,
+    min: Duration,
+    max: Duration,
+    step: Duration,
+}
+
+impl AdaptiveDelay {
+    /// Starts at `min`.
+    pub fn new(min: Duration, max: Duration, step: Duration) -> Self {
+        Self {
+            current: min,
+            min,
+            max,
+            step,
+        }
+    }
+
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    pub fn on_success(&mut self) {
+        self.current = self.current.saturating_sub(self.step).max(self.min);
+    }
+
+    pub fn on_throttled(&mut self) {
+        self.current = (self.current * 2).max(self.step).min(self.max);
+    }
+}
+
+/// Token bucket: allows bursts of up to `burst` requests, then `rate` per second on average.
+/// Callers reserve a slot and sleep for the returned wait, so no lock is held while waiting
+/// and waiters go out in the order they asked.
+#[derive(Debug)]
+pub struct TokenBucket {
+    burst: f64,
+    rate: f64,
+    /// Goes negative while reservations are queued up
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full.
+    pub fn new(burst: u32, rate: f64) -> Self {
+        Self {
+            burst: burst.max(1).into(),
+            rate,
+            tokens: burst.max(1).into(),
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Takes a slot and returns how long to wait before using it.
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.refilled_at = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adaptive_delay_backs_off_and_recovers() {
+        let ms = Duration::from_millis;
+        let mut delay = AdaptiveDelay::new(ms(10), ms(100), ms(20));
+        assert_eq!(delay.current(), ms(10));
+
+        delay.on_throttled();
+        assert_eq!(delay.current(), ms(20));
+        delay.on_throttled();
+        delay.on_throttled();
+        delay.on_throttled();
+        assert_eq!(delay.current(), ms(100));
+
+        delay.on_success();
+        assert_eq!(delay.current(), ms(80));
+        for _ in 0..10 {
+            delay.on_success();
+        }
+        assert_eq!(delay.current(), ms(10));
+    }
+
+    #[test]
+    fn token_bucket_allows_a_burst_then_paces() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(2, 2.0);
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        assert_eq!(bucket.reserve(start), Duration::ZERO);
+        assert_eq!(bucket.reserve(start), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(start), Duration::from_secs(1));
+
+        // After the queue drains and a second passes, the burst is back
+        let later = start + Duration::from_secs(2);
+        assert_eq!(bucket.reserve(later), Duration::ZERO);
+    }
+}
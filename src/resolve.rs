@@ -1,30 +1,209 @@
 use regex::Regex;
 use std::sync::LazyLock;
 
-// Match URLs - will include trailing punctuation which is fine for most cases
-static URL_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"https?://[^\s]+").expect("Invalid URL regex"));
+// Slack's link markup (`<url>` or `<url|label>`, URL in group 1), a bare URL, which will
+// include trailing punctuation, or a Spotify URI as the desktop app copies it (kind and ID in
+// groups 2 and 3)
+static URL_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"<(https?://[^|>\s]+)(?:\|[^>]*)?>|https?://[^\s<>]+|\bspotify:(track|album|playlist|show):([a-zA-Z0-9]+)",
+    )
+    .expect("Invalid URL regex")
+});
 
+// Localized links carry the app's language first, e.g. open.spotify.com/intl-pt-BR/track/ID
 static SPOTIFY_TRACK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"open\.spotify\.com/track/([a-zA-Z0-9]+)").expect("Invalid Spotify regex")
+    Regex::new(r"open\.spotify\.com/(?:intl-[a-zA-Z-]+/)?track/([a-zA-Z0-9]+)")
+        .expect("Invalid Spotify regex")
+});
+
+static SPOTIFY_SHOW_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"open\.spotify\.com/(?:intl-[a-zA-Z-]+/)?show/([a-zA-Z0-9]+)")
+        .expect("Invalid Spotify show regex")
+});
+
+static SPOTIFY_COLLECTION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"open\.spotify\.com/(?:intl-[a-zA-Z-]+/)?(?:album|playlist)/[a-zA-Z0-9]+")
+        .expect("Invalid Spotify collection regex")
 });
 
 static QOBUZ_TRACK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"open\.qobuz\.com/track/([a-zA-Z0-9]+)").expect("Invalid Qobuz regex")
 });
 
+// Apple Music song links: /album/slug/ID?i=SONG_ID (a song within an album) or /song/slug/SONG_ID
+static APPLE_MUSIC_TRACK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"music\.apple\.com/([a-z]{2})/(?:album/[^?#]*\?(?:[^#]*&)?i=(\d+)|song/(?:[^/?#]+/)?(\d+))",
+    )
+    .expect("Invalid Apple Music regex")
+});
+
+static DEEZER_TRACK_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"deezer\.com/(?:[a-z]{2}/)?track/(\d+)").expect("Invalid Deezer regex")
+});
+
+/// Bracketed video-title decorations that aren't part of the song title, e.g. "(Official
+/// Video)", "[Official Audio]", "(Lyrics)", "(HD)".
+static VIDEO_TITLE_SUFFIX_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\s*[(\[](?:official\s+)?(?:music\s+|lyric\s+|hd\s+|4k\s+)?(?:video|audio|visuali[sz]er|lyrics?|hd|hq|4k|m/?v)(?:\s+video)?[)\]]",
+    )
+    .expect("Invalid video title regex")
+});
+
+// Canonical Shazam song pages: shazam.com/song/ID/slug (newer) or shazam.com/track/ID/slug
+static SHAZAM_SONG_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"shazam\.com/(?:[a-z]{2}(?:-[a-z]{2})?/)?(song|track)/(\d+)")
+        .expect("Invalid Shazam regex")
+});
+
+/// Hosts whose links only redirect to the real page; followed before asking Odesli.
+const SHORT_LINK_HOSTS: &[&str] = &["link.deezer.com", "link.spotify.com", "shz.am"];
+
+/// Domains that host music (or redirect to it). Links elsewhere are treated as general chat.
+/// Subdomains match too, e.g. `music.youtube.com` via `youtube.com`.
+const MUSIC_HOSTS: &[&str] = &[
+    "spotify.com",
+    "spotify.link",
+    "youtube.com",
+    "youtu.be",
+    "deezer.com",
+    "deezer.page.link",
+    "qobuz.com",
+    "shazam.com",
+    "shz.am",
+    "apple.com",
+    "tidal.com",
+    "soundcloud.com",
+    "bandcamp.com",
+    "song.link",
+    "album.link",
+    "odesli.co",
+    "pandora.com",
+    "music.amazon.com",
+    "music.amazon.co.uk",
+    "music.amazon.de",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrlKind {
+    Music,
+    Other,
+}
+
+/// Whether a URL is on a known music host. Apple is limited to `music.apple.com`.
+pub fn classify_url(url: &str) -> UrlKind {
+    let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+    else {
+        return UrlKind::Other;
+    };
+    let on = |domain: &str| host == domain || host.ends_with(&format!(".{}", domain));
+    let is_music = MUSIC_HOSTS
+        .iter()
+        .any(|domain| on(domain) && (*domain != "apple.com" || on("music.apple.com")));
+    if is_music {
+        UrlKind::Music
+    } else {
+        UrlKind::Other
+    }
+}
+
+/// Hosts grouped into the platform names `/jamcraft sources` reports, matched like `MUSIC_HOSTS`.
+const PLATFORMS: &[(&str, &str)] = &[
+    ("spotify.com", "Spotify"),
+    ("spotify.link", "Spotify"),
+    ("youtube.com", "YouTube"),
+    ("youtu.be", "YouTube"),
+    ("music.apple.com", "Apple Music"),
+    ("deezer.com", "Deezer"),
+    ("deezer.page.link", "Deezer"),
+    ("qobuz.com", "Qobuz"),
+    ("shazam.com", "Shazam"),
+    ("shz.am", "Shazam"),
+    ("tidal.com", "Tidal"),
+    ("soundcloud.com", "SoundCloud"),
+    ("bandcamp.com", "Bandcamp"),
+    ("song.link", "song.link"),
+    ("album.link", "song.link"),
+    ("odesli.co", "song.link"),
+    ("pandora.com", "Pandora"),
+    ("music.amazon.com", "Amazon Music"),
+    ("music.amazon.co.uk", "Amazon Music"),
+    ("music.amazon.de", "Amazon Music"),
+];
+
+/// Which platform a music link is from, e.g. "YouTube" for `music.youtube.com` or `youtu.be`.
+pub fn platform(url: &str) -> &'static str {
+    let Some(host) = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+    else {
+        return "Other";
+    };
+    PLATFORMS
+        .iter()
+        .find(|(domain, _)| host == *domain || host.ends_with(&format!(".{}", domain)))
+        .map_or("Other", |(_, name)| name)
+}
+
+/// Extracts URLs from message text, unwrapping Slack's `<url|label>` markup (and its `&amp;`
+/// escaping). `spotify:track:`, `spotify:album:`, `spotify:playlist:` and `spotify:show:` URIs
+/// come back as the matching open.spotify.com link, so they resolve like one. A link pasted
+/// twice (or once with and once without a trailing slash) is only returned once, at its first
+/// position.
 pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
     URL_REGEX
-        .find_iter(text)
-        .map(|m| {
+        .captures_iter(text)
+        .map(|caps| match (caps.get(1), caps.get(2), caps.get(3)) {
+            (Some(url), _, _) => url.as_str().replace("&amp;", "&"),
+            (None, Some(kind), Some(id)) => {
+                format!("https://open.spotify.com/{}/{}", kind.as_str(), id.as_str())
+            }
             // Clean up URL - remove trailing punctuation that might have been captured
-            m.as_str()
+            _ => caps[0]
                 .trim_end_matches(|c: char| ".,;:!?)]>".contains(c))
-                .to_string()
+                .to_string(),
         })
+        .filter(|url| seen.insert(url.trim_end_matches('/').to_string()))
         .collect()
 }
 
+/// Query params that only carry share/tracking context and never affect which track a link
+/// points to. `utm_*` params are matched by prefix.
+const TRACKING_PARAMS: &[&str] = &["si", "nd", "context", "go", "igshid"];
+
+fn is_tracking_param(key: &str) -> bool {
+    TRACKING_PARAMS.contains(&key) || key.starts_with("utm_")
+}
+
+/// Removes tracking query params (`si`, `nd`, `utm_*`, `context`, `go`, `igshid`) so identical
+/// shares resolve, cache and log the same way. Meaningful params (e.g. YouTube's `v`, Apple
+/// Music's `i`) are kept.
+pub fn strip_tracking_params(url: &str) -> String {
+    let Ok(mut parsed) = reqwest::Url::parse(url) else {
+        return url.to_string();
+    };
+    if !parsed.query_pairs().any(|(k, _)| is_tracking_param(&k)) {
+        return url.to_string();
+    }
+
+    let kept: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(k, _)| !is_tracking_param(k))
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if kept.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(kept);
+    }
+    parsed.to_string()
+}
+
 pub fn parse_spotify_track_id(url: &str) -> Option<String> {
     SPOTIFY_TRACK_REGEX
         .captures(url)
@@ -32,6 +211,40 @@ pub fn parse_spotify_track_id(url: &str) -> Option<String> {
         .map(|m| m.as_str().to_string())
 }
 
+/// Song ID from a canonical Shazam URL. Odesli resolves these directly.
+pub fn parse_shazam_song_id(url: &str) -> Option<String> {
+    SHAZAM_SONG_REGEX
+        .captures(url)
+        .and_then(|caps| caps.get(2))
+        .map(|m| m.as_str().to_string())
+}
+
+/// A Shazam song URL cut down to its ID, without the locale prefix, slug or share params.
+fn canonical_shazam_url(url: &str) -> Option<String> {
+    let kind = SHAZAM_SONG_REGEX.captures(url)?.get(1)?.as_str();
+    let id = parse_shazam_song_id(url)?;
+    Some(format!("https://www.shazam.com/{}/{}", kind, id))
+}
+
+pub(crate) fn is_short_link(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_lowercase))
+        .is_some_and(|host| SHORT_LINK_HOSTS.contains(&host.as_str()))
+}
+
+pub fn parse_spotify_show_id(url: &str) -> Option<String> {
+    SPOTIFY_SHOW_REGEX
+        .captures(url)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+/// Whether a link is a whole Spotify album or playlist rather than one track.
+pub fn is_spotify_collection(url: &str) -> bool {
+    SPOTIFY_COLLECTION_REGEX.is_match(url)
+}
+
 pub fn parse_qobuz_track_id(url: &str) -> Option<String> {
     QOBUZ_TRACK_REGEX
         .captures(url)
@@ -39,9 +252,185 @@ pub fn parse_qobuz_track_id(url: &str) -> Option<String> {
         .map(|m| m.as_str().to_string())
 }
 
+/// Storefront (country code) and song ID of an Apple Music song link. Album links without an
+/// `i=` song are not songs, so they give `None`.
+pub fn parse_apple_music_track_id(url: &str) -> Option<(String, String)> {
+    let caps = APPLE_MUSIC_TRACK_REGEX.captures(url)?;
+    let song_id = caps.get(2).or_else(|| caps.get(3))?;
+    Some((caps[1].to_string(), song_id.as_str().to_string()))
+}
+
+pub fn parse_deezer_track_id(url: &str) -> Option<String> {
+    DEEZER_TRACK_REGEX
+        .captures(url)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+/// Why a lookup against another service came back empty-handed, when it's worth knowing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LookupError {
+    /// Another attempt might work: the request didn't complete, or the service answered with a
+    /// 5xx or 429
+    Transient,
+    /// The service says the track or video is gone or private
+    Unavailable,
+}
+
+/// What a lookup against another service came to; `Ok(None)` if it has nothing to go on.
+pub type Lookup<T> = Result<Option<T>, LookupError>;
+
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Sends a metadata lookup and reads its JSON, telling a transient failure or content that's
+/// gone from a plain miss. `youtube` as for `is_unavailable_status`.
+async fn lookup_json(
+    request: reqwest::RequestBuilder,
+    service: &str,
+    youtube: bool,
+) -> Lookup<serde_json::Value> {
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("{} request failed: {}", service, e);
+            return Err(LookupError::Transient);
+        }
+    };
+    let status = response.status();
+    if !status.is_success() {
+        tracing::warn!("{} returned {}", service, status);
+        return if is_transient_status(status) {
+            Err(LookupError::Transient)
+        } else if is_unavailable_status(youtube, status.as_u16()) {
+            Err(LookupError::Unavailable)
+        } else {
+            Ok(None)
+        };
+    }
+    Ok(response.json().await.ok())
+}
+
+/// Fetch track metadata (artist, title) from Deezer's public API, which needs no key.
+#[tracing::instrument]
+pub async fn fetch_deezer_track_metadata(track_id: &str) -> Lookup<(String, String)> {
+    let url = format!("https://api.deezer.com/track/{}", track_id);
+    let request = reqwest::Client::new().get(&url);
+    let Some(json) = lookup_json(request, "Deezer API", false).await? else {
+        return Ok(None);
+    };
+
+    // Errors come back as 200 with an `error` object, which has no title. Code 800 ("no
+    // data") is a track Deezer doesn't have (any more).
+    if json["error"]["code"].as_u64() == Some(800) {
+        return Err(LookupError::Unavailable);
+    }
+    let Some(title) = json.get("title").and_then(|t| t.as_str()) else {
+        return Ok(None);
+    };
+    let Some(artist) = json
+        .get("artist")
+        .and_then(|a| a.get("name"))
+        .and_then(|n| n.as_str())
+    else {
+        return Ok(None);
+    };
+
+    tracing::info!("Deezer metadata: artist={} title={}", artist, title);
+    Ok(Some((artist.to_string(), title.to_string())))
+}
+
+/// Fetch track metadata (artist, title) for an Apple Music song from the iTunes lookup API,
+/// which is public. The storefront matters: a song may only be listed in some countries.
+#[tracing::instrument]
+pub async fn fetch_apple_music_track_metadata(
+    storefront: &str,
+    song_id: &str,
+) -> Lookup<(String, String)> {
+    let request = reqwest::Client::new()
+        .get("https://itunes.apple.com/lookup")
+        .query(&[("id", song_id), ("country", storefront), ("entity", "song")]);
+    let Some(json) = lookup_json(request, "iTunes lookup", false).await? else {
+        return Ok(None);
+    };
+
+    // Unknown IDs come back as 200 with an empty `results`
+    let song = json
+        .get("results")
+        .and_then(|r| r.as_array())
+        .and_then(|results| {
+            results
+                .iter()
+                .find(|r| r.get("kind").and_then(|k| k.as_str()) == Some("song"))
+        });
+    let Some((title, artist)) = song.and_then(|song| {
+        Some((
+            song.get("trackName")?.as_str()?,
+            song.get("artistName")?.as_str()?,
+        ))
+    }) else {
+        return Ok(None);
+    };
+
+    tracing::info!("Apple Music metadata: artist={} title={}", artist, title);
+    Ok(Some((artist.to_string(), title.to_string())))
+}
+
+/// A YouTube video's title and channel name from its oEmbed data, which needs no API key.
+#[tracing::instrument]
+pub async fn fetch_youtube_oembed_title(url: &str) -> Lookup<(String, String)> {
+    let request = reqwest::Client::new()
+        .get("https://www.youtube.com/oembed")
+        .query(&[("url", url), ("format", "json")]);
+    let Some(json) = lookup_json(request, "YouTube oEmbed", true).await? else {
+        return Ok(None);
+    };
+
+    let Some(title) = json.get("title").and_then(|t| t.as_str()) else {
+        return Ok(None);
+    };
+    let author = json
+        .get("author_name")
+        .and_then(|a| a.as_str())
+        .unwrap_or_default();
+    tracing::info!("YouTube oEmbed: title={} channel={}", title, author);
+    Ok(Some((title.to_string(), author.to_string())))
+}
+
+/// A video title without "(Official Video)"-style decorations.
+pub fn clean_video_title(title: &str) -> String {
+    VIDEO_TITLE_SUFFIX_REGEX
+        .replace_all(title, "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Artist and title from a cleaned "Artist - Song" video title. Only dashes count: "by" shows
+/// up in too many song titles ("Stand by Me").
+pub fn split_video_title(title: &str) -> Option<(String, String)> {
+    let (artist, song) = [" - ", " – ", " — "]
+        .iter()
+        .find_map(|sep| title.split_once(sep))?;
+    let (artist, song) = (artist.trim(), song.trim().trim_matches('"').trim());
+    (!artist.is_empty() && !song.is_empty()).then(|| (artist.to_string(), song.to_string()))
+}
+
+/// The artist behind an auto-generated "Artist - Topic" channel or an "ArtistVEVO" channel.
+/// Other channels are often fans or labels, so they don't name the artist.
+pub fn channel_artist(channel: &str) -> Option<String> {
+    let artist = channel
+        .strip_suffix(" - Topic")
+        .or_else(|| channel.strip_suffix("VEVO"))?
+        .trim();
+    (!artist.is_empty()).then(|| artist.to_string())
+}
+
 /// Fetch track metadata (artist, title) from Qobuz API. Uses open.qobuz.com's public
 /// app_id which allows simple GET without signing - same as their smart-link pages.
-pub async fn fetch_qobuz_track_metadata(track_id: &str) -> Option<(String, String)> {
+#[tracing::instrument]
+pub async fn fetch_qobuz_track_metadata(track_id: &str) -> Lookup<(String, String)> {
     const QOBUZ_OPEN_APP_ID: &str = "712109809";
 
     let client = reqwest::Client::new();
@@ -56,13 +445,19 @@ pub async fn fetch_qobuz_track_metadata(track_id: &str) -> Option<(String, Strin
         QOBUZ_OPEN_APP_ID
     );
 
-    let response = client
+    let response = match client
         .get(&url)
         .header("Origin", "https://open.qobuz.com")
         .header("Referer", "https://open.qobuz.com/")
         .send()
         .await
-        .ok()?;
+    {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("Qobuz API request failed: {}", e);
+            return Err(LookupError::Transient);
+        }
+    };
 
     if !response.status().is_success() {
         let status = response.status();
@@ -73,11 +468,21 @@ pub async fn fetch_qobuz_track_metadata(track_id: &str) -> Option<(String, Strin
             body
         };
         tracing::warn!("Qobuz API returned {} - {}", status, preview);
-        return None;
+        return if is_transient_status(status) {
+            Err(LookupError::Transient)
+        } else if is_unavailable_status(false, status.as_u16()) {
+            Err(LookupError::Unavailable)
+        } else {
+            Ok(None)
+        };
     }
 
-    let json: serde_json::Value = response.json().await.ok()?;
-    let title = json.get("title").and_then(|t| t.as_str())?.to_string();
+    let Ok(json) = response.json::<serde_json::Value>().await else {
+        return Ok(None);
+    };
+    let Some(title) = json.get("title").and_then(|t| t.as_str()) else {
+        return Ok(None);
+    };
 
     // Artist: try performer.name, performers[0].name, album.artist.name, composer.name
     let artist = json
@@ -101,14 +506,35 @@ pub async fn fetch_qobuz_track_metadata(track_id: &str) -> Option<(String, Strin
             json.get("composer")
                 .and_then(|c| c.get("name"))
                 .and_then(|n| n.as_str())
-        })
-        .map(|s| s.to_string())?;
+        });
+    let Some(artist) = artist else {
+        return Ok(None);
+    };
 
     tracing::info!("Qobuz metadata: artist={} title={}", artist, title);
-    Some((artist, title))
+    Ok(Some((artist.to_string(), title.to_string())))
 }
 
-pub async fn resolve_via_odesli(url: &str) -> Option<String> {
+/// What Odesli could tell us about a link.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OdesliMatch {
+    /// Odesli linked it to this Spotify track
+    Spotify(String),
+    /// No Spotify link, but the source platform's artist and title, to search Spotify with
+    Entity { artist: String, title: String },
+}
+
+/// Artist and title of the entity Odesli looked up (`entityUniqueId` in `entitiesByUniqueId`).
+fn odesli_entity(json: &serde_json::Value) -> Option<(String, String)> {
+    let id = json.get("entityUniqueId")?.as_str()?;
+    let entity = json.get("entitiesByUniqueId")?.get(id)?;
+    let title = entity.get("title")?.as_str()?.trim();
+    let artist = entity.get("artistName")?.as_str()?.trim();
+    (!title.is_empty() && !artist.is_empty()).then(|| (artist.to_string(), title.to_string()))
+}
+
+#[tracing::instrument]
+pub async fn resolve_via_odesli(url: &str) -> Lookup<OdesliMatch> {
     // Create a client that follows redirects (important for short links like link.deezer.com)
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::limited(5))
@@ -120,6 +546,7 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
 
     tracing::debug!("Calling Odesli API for URL: {}", url);
 
+    let mut entity = None;
     match client.get(&api_url).send().await {
         Ok(response) => {
             let status = response.status();
@@ -127,7 +554,11 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
 
             if !status.is_success() {
                 tracing::warn!("Odesli API returned non-success status: {}", status);
-                return None;
+                return if is_transient_status(status) {
+                    Err(LookupError::Transient)
+                } else {
+                    Ok(None)
+                };
             }
 
             // Read response as text first (can be used for both JSON and text search)
@@ -137,9 +568,14 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
                 // Try parsing as JSON first (preferred method)
                 if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
                     tracing::debug!("Successfully parsed Odesli JSON response");
+                    entity = odesli_entity(&json);
 
                     // Odesli returns linksByPlatform with platform keys
                     if let Some(links) = json.get("linksByPlatform") {
+                        tracing::debug!(
+                            "Odesli knows the song on: {:?}",
+                            links.as_object().map(|o| o.keys().collect::<Vec<_>>())
+                        );
                         if let Some(spotify) = links.get("spotify") {
                             // Try "url" field first
                             if let Some(spotify_url) = spotify.get("url").and_then(|u| u.as_str()) {
@@ -153,7 +589,7 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
                                         url,
                                         track_id
                                     );
-                                    return Some(track_id);
+                                    return Ok(Some(OdesliMatch::Spotify(track_id)));
                                 } else {
                                     tracing::warn!(
                                         "Could not parse track ID from Spotify URL: {}",
@@ -172,7 +608,7 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
                                         "Using entityUniqueId as track ID: {}",
                                         entity_id
                                     );
-                                    return Some(entity_id.to_string());
+                                    return Ok(Some(OdesliMatch::Spotify(entity_id.to_string())));
                                 }
                             }
                             tracing::debug!(
@@ -200,7 +636,7 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
                         "Found Spotify track ID in Odesli response text: {}",
                         track_id
                     );
-                    return Some(track_id);
+                    return Ok(Some(OdesliMatch::Spotify(track_id)));
                 }
             } else {
                 tracing::warn!("Failed to read Odesli response body");
@@ -208,34 +644,72 @@ pub async fn resolve_via_odesli(url: &str) -> Option<String> {
         }
         Err(e) => {
             tracing::warn!("Odesli API request failed: {}", e);
+            return Err(LookupError::Transient);
         }
     }
 
+    if let Some((artist, title)) = entity {
+        tracing::debug!(
+            "No Spotify link for {} via Odesli, but it's {} - {}",
+            url,
+            artist,
+            title
+        );
+        return Ok(Some(OdesliMatch::Entity { artist, title }));
+    }
+
     tracing::debug!("Could not resolve {} via Odesli", url);
-    None
+    Ok(None)
 }
 
-async fn resolve_short_link(url: &str) -> Option<String> {
-    // For short links like link.deezer.com, resolve to the full URL first
-    if url.contains("link.deezer.com") || url.contains("link.spotify.com") {
-        let client = reqwest::Client::builder()
-            .redirect(reqwest::redirect::Policy::limited(5))
-            .build()
-            .unwrap_or_else(|_| reqwest::Client::new());
-
-        match client.get(url).send().await {
-            Ok(response) => {
-                // Get the final URL after redirects
-                let final_url = response.url().to_string();
-                if final_url != url {
-                    tracing::debug!("Resolved short link {} to {}", url, final_url);
-                    return Some(final_url);
-                }
-            }
-            Err(e) => {
-                tracing::warn!("Failed to resolve short link {}: {}", url, e);
+pub(crate) async fn resolve_short_link(url: &str) -> Option<String> {
+    // For short links like link.deezer.com or shz.am, resolve to the full URL first
+    if is_short_link(url) {
+        follow_redirects(url).await
+    } else {
+        None
+    }
+}
+
+/// Why a link didn't resolve, as far as the resolvers could tell, so the reply can say more
+/// than "couldn't resolve that".
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnresolvedReason {
+    /// The platform says the track is gone, or a YouTube video is private or removed
+    Unavailable,
+    /// Odesli knows the song, but not on Spotify
+    NotOnSpotify { artist: String, title: String },
+    /// A Spotify album or playlist, which the bot doesn't add whole
+    NotATrack,
+    /// Nothing more specific is known
+    Unknown,
+}
+
+/// Statuses that mean the content is gone or private. YouTube's oEmbed answers 401/403 for
+/// private videos; elsewhere those are too often bot blocking to read anything into.
+fn is_unavailable_status(youtube: bool, status: u16) -> bool {
+    matches!(status, 404 | 410) || (youtube && matches!(status, 401 | 403))
+}
+
+/// Final URL after following redirects, or `None` if the request failed or didn't redirect.
+async fn follow_redirects(url: &str) -> Option<String> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::limited(5))
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new());
+
+    match client.get(url).send().await {
+        Ok(response) => {
+            // Get the final URL after redirects
+            let final_url = response.url().to_string();
+            if final_url != url {
+                tracing::debug!("Resolved short link {} to {}", url, final_url);
+                return Some(final_url);
             }
         }
+        Err(e) => {
+            tracing::warn!("Failed to resolve short link {}: {}", url, e);
+        }
     }
     None
 }
@@ -248,23 +722,34 @@ fn normalize_for_odesli(url: &str) -> String {
     url.to_string()
 }
 
-pub async fn resolve_to_spotify_track_id(url: &str) -> Option<String> {
+/// Spotify track ID for a link, if it's a Spotify link or Odesli knows the Spotify equivalent.
+pub async fn resolve_to_spotify_track_id(url: &str) -> Lookup<String> {
+    Ok(match resolve_link(url).await? {
+        Some(OdesliMatch::Spotify(track_id)) => Some(track_id),
+        Some(OdesliMatch::Entity { .. }) | None => None,
+    })
+}
+
+/// Like [`resolve_to_spotify_track_id`], but also returns Odesli's artist/title for links it
+/// couldn't match to Spotify, so the caller can search for them.
+#[tracing::instrument]
+pub async fn resolve_link(url: &str) -> Lookup<OdesliMatch> {
     // Try direct Spotify parse first
     if let Some(track_id) = parse_spotify_track_id(url) {
-        return Some(track_id);
+        return Ok(Some(OdesliMatch::Spotify(track_id)));
     }
 
     // Odesli doesn't support Qobuz - skip the call, let caller use Qobuz fallback
     if parse_qobuz_track_id(url).is_some() {
         tracing::debug!("Qobuz URL detected, skipping Odesli");
-        return None;
+        return Ok(None);
     }
 
     let url = normalize_for_odesli(url);
 
-    // For short links (link.deezer.com, link.spotify.com), resolve them first
+    // For short links (link.deezer.com, link.spotify.com, shz.am), resolve them first
     // Odesli works better with full URLs
-    let url_to_use = if url.contains("link.deezer.com") || url.contains("link.spotify.com") {
+    let url_to_use = if is_short_link(&url) {
         tracing::info!("Detected short link, resolving: {}", url);
         if let Some(resolved) = resolve_short_link(&url).await {
             tracing::info!("Resolved short link {} to {}", url, resolved);
@@ -279,6 +764,14 @@ pub async fn resolve_to_spotify_track_id(url: &str) -> Option<String> {
     } else {
         url.to_string()
     };
+    // Shazam songs, including where a shz.am link led, go to Odesli by ID alone
+    let url_to_use = match canonical_shazam_url(&url_to_use) {
+        Some(canonical) => {
+            tracing::debug!("Shazam song URL detected, passing {} to Odesli", canonical);
+            canonical
+        }
+        None => url_to_use,
+    };
 
     // Fall back to Odesli with the (possibly resolved) URL
     tracing::debug!("Calling Odesli with URL: {}", url_to_use);
@@ -289,22 +782,369 @@ pub async fn resolve_to_spotify_track_id(url: &str) -> Option<String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn odesli_entity_reads_source_title_and_artist() {
+        let json = serde_json::json!({
+            "entityUniqueId": "YOUTUBE_VIDEO::abc",
+            "entitiesByUniqueId": {
+                "YOUTUBE_VIDEO::abc": { "title": "Creep", "artistName": "Radiohead" }
+            },
+            "linksByPlatform": { "youtube": { "url": "https://www.youtube.com/watch?v=abc" } }
+        });
+        assert_eq!(
+            odesli_entity(&json),
+            Some(("Radiohead".to_string(), "Creep".to_string()))
+        );
+        assert_eq!(odesli_entity(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn parse_deezer_track_id_extracts_id() {
+        assert_eq!(
+            parse_deezer_track_id("https://www.deezer.com/fr/track/3135556?utm_source=x"),
+            Some("3135556".to_string())
+        );
+        assert_eq!(
+            parse_deezer_track_id("https://www.deezer.com/track/3135556"),
+            Some("3135556".to_string())
+        );
+        assert_eq!(
+            parse_deezer_track_id("https://www.deezer.com/album/302127"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_apple_music_track_id_handles_album_and_song_links() {
+        let parsed = |storefront: &str, id: &str| Some((storefront.to_string(), id.to_string()));
+        assert_eq!(
+            parse_apple_music_track_id("https://music.apple.com/us/album/song-name/1234?i=5678"),
+            parsed("us", "5678")
+        );
+        assert_eq!(
+            parse_apple_music_track_id(
+                "https://music.apple.com/nl/album/song-name/1234?l=en&i=5678"
+            ),
+            parsed("nl", "5678")
+        );
+        assert_eq!(
+            parse_apple_music_track_id("https://music.apple.com/gb/song/song-name/5678"),
+            parsed("gb", "5678")
+        );
+        assert_eq!(
+            parse_apple_music_track_id("https://music.apple.com/us/song/5678"),
+            parsed("us", "5678")
+        );
+        // A whole album isn't one track
+        assert_eq!(
+            parse_apple_music_track_id("https://music.apple.com/us/album/album-name/1234"),
+            None
+        );
+    }
+
     #[test]
     fn parse_qobuz_track_id_extracts_id() {
         assert_eq!(
             parse_qobuz_track_id("https://open.qobuz.com/track/23847392"),
             Some("23847392".to_string())
         );
-        assert_eq!(parse_qobuz_track_id("https://open.spotify.com/track/abc"), None);
+        assert_eq!(
+            parse_qobuz_track_id("https://open.spotify.com/track/abc"),
+            None
+        );
+    }
+
+    #[test]
+    fn extract_urls_dedupes_repeated_links() {
+        let text = "https://open.spotify.com/track/abc https://open.spotify.com/track/abc \
+                    https://www.deezer.com/track/123/ and again https://www.deezer.com/track/123";
+        assert_eq!(
+            extract_urls(text),
+            vec![
+                "https://open.spotify.com/track/abc".to_string(),
+                "https://www.deezer.com/track/123/".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_urls_unwraps_slack_link_markup() {
+        let url = |u: &str| vec![u.to_string()];
+        assert_eq!(
+            extract_urls("listen: https://open.spotify.com/track/abc."),
+            url("https://open.spotify.com/track/abc")
+        );
+        assert_eq!(
+            extract_urls("<https://open.spotify.com/track/abc>"),
+            url("https://open.spotify.com/track/abc")
+        );
+        assert_eq!(
+            extract_urls("listen: <https://open.spotify.com/track/abc|Track Name> 🔥"),
+            url("https://open.spotify.com/track/abc")
+        );
+        assert_eq!(
+            extract_urls("<https://www.youtube.com/watch?v=x&amp;t=42|youtube.com/watch>"),
+            url("https://www.youtube.com/watch?v=x&t=42")
+        );
+        assert_eq!(
+            extract_urls(
+                "two: <https://open.spotify.com/track/abc|open.spotify.com/track/abc> and \
+                 <https://www.deezer.com/track/123>, plus https://youtu.be/xyz"
+            ),
+            vec![
+                "https://open.spotify.com/track/abc".to_string(),
+                "https://www.deezer.com/track/123".to_string(),
+                "https://youtu.be/xyz".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_urls_turns_spotify_uris_into_links() {
+        assert_eq!(
+            extract_urls(
+                "spotify:track:3n3Ppam7vgaVa1iaRUc9Lp and https://www.deezer.com/track/123, \
+                 then <spotify:album:1DFixLWuPkv3KT3TnV35m3>, spotify:playlist:37i9dQZF1DX4WYpdgoIcn6 \
+                 and spotify:show:5CfCWKI5pZ28U0uOzXkDHe."
+            ),
+            vec![
+                "https://open.spotify.com/track/3n3Ppam7vgaVa1iaRUc9Lp".to_string(),
+                "https://www.deezer.com/track/123".to_string(),
+                "https://open.spotify.com/album/1DFixLWuPkv3KT3TnV35m3".to_string(),
+                "https://open.spotify.com/playlist/37i9dQZF1DX4WYpdgoIcn6".to_string(),
+                "https://open.spotify.com/show/5CfCWKI5pZ28U0uOzXkDHe".to_string(),
+            ]
+        );
+        // The same track as a URI and a link is one track
+        assert_eq!(
+            extract_urls(
+                "spotify:track:3n3Ppam7vgaVa1iaRUc9Lp https://open.spotify.com/track/3n3Ppam7vgaVa1iaRUc9Lp"
+            ),
+            vec!["https://open.spotify.com/track/3n3Ppam7vgaVa1iaRUc9Lp".to_string()]
+        );
+        assert!(extract_urls("spotify:artist:0OdUWJ0sBjDrqHygGUXeCF").is_empty());
+        assert!(is_spotify_collection(
+            "https://open.spotify.com/intl-de/album/1DFixLWuPkv3KT3TnV35m3"
+        ));
+        assert!(!is_spotify_collection(
+            "https://open.spotify.com/track/3n3Ppam7vgaVa1iaRUc9Lp"
+        ));
+    }
+
+    #[test]
+    fn parse_spotify_track_id_ignores_suffixes() {
+        let links = [
+            "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT",
+            "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT?si=a1b2c3d4&nd=1",
+            "https://open.spotify.com/intl-de/track/4cOdK2wGLETKBW3PvgPWqT?nd=1",
+            "https://open.spotify.com/intl-pt/track/4cOdK2wGLETKBW3PvgPWqT",
+            "https://open.spotify.com/intl-pt-BR/track/4cOdK2wGLETKBW3PvgPWqT?si=x",
+        ];
+        for link in links {
+            assert_eq!(
+                parse_spotify_track_id(link).as_deref(),
+                Some("4cOdK2wGLETKBW3PvgPWqT"),
+                "{}",
+                link
+            );
+        }
+        assert_eq!(
+            strip_tracking_params(links[1]),
+            strip_tracking_params(links[0])
+        );
+    }
+
+    #[test]
+    fn parse_spotify_show_id_extracts_id() {
+        assert_eq!(
+            parse_spotify_show_id("https://open.spotify.com/show/4rOoJ6Egrf8K2IrywzwOMk?si=x"),
+            Some("4rOoJ6Egrf8K2IrywzwOMk".to_string())
+        );
+        assert_eq!(
+            parse_spotify_show_id("https://open.spotify.com/intl-fr/show/4rOoJ6Egrf8K2IrywzwOMk"),
+            Some("4rOoJ6Egrf8K2IrywzwOMk".to_string())
+        );
+        assert_eq!(
+            parse_spotify_show_id("https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT"),
+            None
+        );
+    }
+
+    #[test]
+    fn strip_tracking_params_removes_spotify_share_token() {
+        assert_eq!(
+            strip_tracking_params(
+                "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT?si=a1b2c3d4"
+            ),
+            "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT"
+        );
+    }
+
+    #[test]
+    fn links_differing_only_in_tracking_params_normalize_identically() {
+        assert_eq!(
+            strip_tracking_params("https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT?si=1"),
+            strip_tracking_params(
+                "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT?si=2&go=1&context=x"
+            )
+        );
+        // Apple Music's `i` picks the song out of an album, so it stays
+        assert_eq!(
+            strip_tracking_params(
+                "https://music.apple.com/us/album/x/1440857781?i=1440858012&go=1&utm_source=slack"
+            ),
+            "https://music.apple.com/us/album/x/1440857781?i=1440858012"
+        );
+    }
+
+    #[test]
+    fn strip_tracking_params_removes_utm_params_and_keeps_others() {
+        assert_eq!(
+            strip_tracking_params(
+                "https://www.youtube.com/watch?utm_source=slack&v=dQw4w9WgXcQ&utm_medium=share&utm_campaign=x"
+            ),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(
+            strip_tracking_params("https://www.deezer.com/track/123456"),
+            "https://www.deezer.com/track/123456"
+        );
+    }
+
+    #[test]
+    fn video_titles_are_cleaned_and_split() {
+        let clean = clean_video_title("Daft Punk - Get Lucky (Official Video) [HD]");
+        assert_eq!(clean, "Daft Punk - Get Lucky");
+        assert_eq!(
+            split_video_title(&clean),
+            Some(("Daft Punk".to_string(), "Get Lucky".to_string()))
+        );
+        assert_eq!(
+            clean_video_title("Adele – Hello [Official Audio] (Lyrics)"),
+            "Adele – Hello"
+        );
+        assert_eq!(clean_video_title("Song (Official Music Video)"), "Song");
+        // Brackets that are part of the title stay
+        assert_eq!(
+            clean_video_title("Song (Live at Wembley)"),
+            "Song (Live at Wembley)"
+        );
+        assert_eq!(split_video_title("Stand by Me"), None);
+
+        assert_eq!(
+            channel_artist("Ben E. King - Topic").as_deref(),
+            Some("Ben E. King")
+        );
+        assert_eq!(channel_artist("AdeleVEVO").as_deref(), Some("Adele"));
+        assert_eq!(channel_artist("Some Fan Channel"), None);
+    }
+
+    #[test]
+    fn unavailable_statuses_depend_on_platform() {
+        assert!(is_unavailable_status(false, 404));
+        assert!(is_unavailable_status(false, 410));
+        assert!(!is_unavailable_status(false, 403));
+        assert!(is_unavailable_status(true, 401));
+        assert!(!is_unavailable_status(true, 200));
+    }
+
+    #[test]
+    fn platform_groups_hosts() {
+        assert_eq!(platform("https://music.youtube.com/watch?v=x"), "YouTube");
+        assert_eq!(platform("https://youtu.be/x"), "YouTube");
+        assert_eq!(
+            platform("https://music.apple.com/us/album/x"),
+            "Apple Music"
+        );
+        assert_eq!(platform("https://link.deezer.com/s/x"), "Deezer");
+        assert_eq!(platform("https://open.spotify.com/track/x"), "Spotify");
+        assert_eq!(platform("https://example.com/x"), "Other");
+    }
+
+    #[test]
+    fn classify_url_recognizes_music_hosts() {
+        for url in [
+            "https://open.spotify.com/track/4cOdK2wGLETKBW3PvgPWqT",
+            "https://music.youtube.com/watch?v=abc",
+            "https://youtu.be/dQw4w9WgXcQ",
+            "https://link.deezer.com/s/abc",
+            "https://music.apple.com/us/album/x/123?i=456",
+            "https://artist.bandcamp.com/track/song",
+        ] {
+            assert_eq!(classify_url(url), UrlKind::Music, "{}", url);
+        }
+        for url in [
+            "https://github.com/tvanboxtel/jamcraft",
+            "https://www.apple.com/iphone/",
+            "https://notspotify.com/track/abc",
+            "not a url",
+        ] {
+            assert_eq!(classify_url(url), UrlKind::Other, "{}", url);
+        }
+    }
+
+    #[test]
+    fn parse_shazam_song_id_detects_canonical_urls() {
+        assert_eq!(
+            parse_shazam_song_id("https://www.shazam.com/song/1440818844/mr-brightside"),
+            Some("1440818844".to_string())
+        );
+        assert_eq!(
+            parse_shazam_song_id("https://www.shazam.com/track/20066955/mr-brightside"),
+            Some("20066955".to_string())
+        );
+        assert_eq!(
+            parse_shazam_song_id("https://www.shazam.com/en-us/song/1440818844/mr-brightside"),
+            Some("1440818844".to_string())
+        );
+        assert_eq!(parse_shazam_song_id("https://www.shazam.com/charts"), None);
+        assert_eq!(
+            canonical_shazam_url(
+                "https://www.shazam.com/en-us/song/1440818844/mr-brightside?referrer=share"
+            )
+            .as_deref(),
+            Some("https://www.shazam.com/song/1440818844")
+        );
+        assert_eq!(
+            canonical_shazam_url("https://www.shazam.com/track/20066955/mr-brightside").as_deref(),
+            Some("https://www.shazam.com/track/20066955")
+        );
+        assert_eq!(canonical_shazam_url("https://www.shazam.com/charts"), None);
+        assert!(is_short_link("https://shz.am/t20066955"));
+        assert!(!is_short_link("https://www.shazam.com/song/1440818844"));
+    }
+
+    #[tokio::test]
+    async fn follow_redirects_returns_final_url() {
+        use axum::{response::Redirect, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route(
+                "/t20066955",
+                get(|| async { Redirect::permanent("/song/20066955/mr-brightside") }),
+            )
+            .route("/song/20066955/mr-brightside", get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let resolved = follow_redirects(&format!("{}/t20066955", base)).await;
+        assert_eq!(
+            resolved,
+            Some(format!("{}/song/20066955/mr-brightside", base))
+        );
+        assert_eq!(
+            follow_redirects(&format!("{}/song/20066955/mr-brightside", base)).await,
+            None
+        );
     }
 
     #[tokio::test]
     async fn fetch_qobuz_metadata_returns_artist_and_title() {
-        let meta = fetch_qobuz_track_metadata("23847392").await;
+        let meta = fetch_qobuz_track_metadata("23847392").await.unwrap();
         assert!(meta.is_some(), "Qobuz API should return metadata");
         let (artist, title) = meta.unwrap();
         assert_eq!(artist, "Bloc Party");
         assert_eq!(title, "Helicopter");
     }
-
 }
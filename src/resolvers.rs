@@ -0,0 +1,282 @@
+use crate::resolve::{
+    channel_artist, clean_video_title, fetch_apple_music_track_metadata,
+    fetch_deezer_track_metadata, fetch_qobuz_track_metadata, fetch_youtube_oembed_title,
+    is_short_link, parse_apple_music_track_id, parse_deezer_track_id, parse_qobuz_track_id,
+    platform, resolve_link, resolve_short_link, split_video_title, Lookup, LookupError,
+    OdesliMatch, UnresolvedReason,
+};
+use crate::spotify::SpotifyClient;
+use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Resolver names accepted in `RESOLVERS`, in the default order.
+pub const RESOLVER_NAMES: &[&str] = &["apple_music", "odesli", "deezer", "qobuz", "youtube"];
+
+/// What resolving a link came to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    Resolved(String),
+    /// Not handled, or looked up with no Spotify match; another attempt won't change that.
+    /// Says why when the lookups could tell.
+    Missed(UnresolvedReason),
+    /// A lookup failed along the way (network error, 5xx, 429), so another attempt might not
+    Failed,
+}
+
+impl Resolution {
+    pub fn track_id(self) -> Option<String> {
+        match self {
+            Resolution::Resolved(track_id) => Some(track_id),
+            Resolution::Missed(_) | Resolution::Failed => None,
+        }
+    }
+}
+
+/// A metadata lookup that came back without anything to search for.
+fn unresolved<T>(lookup: Lookup<T>) -> Resolution {
+    match lookup {
+        Err(LookupError::Transient) => Resolution::Failed,
+        Err(LookupError::Unavailable) => Resolution::Missed(UnresolvedReason::Unavailable),
+        Ok(_) => Resolution::Missed(UnresolvedReason::Unknown),
+    }
+}
+
+/// One way of turning a music link into a Spotify track ID. The bot tries each configured
+/// resolver in turn (`RESOLVERS`) until one succeeds, so losing one backend (e.g. Odesli
+/// being down) doesn't stop resolution.
+#[async_trait]
+pub trait UrlResolver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// `Missed` if this resolver doesn't handle the link or couldn't find it on Spotify.
+    /// Searches look in the market of `playlist_id`, where the track is going.
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution;
+}
+
+/// Builds the resolvers named in `names`, in that order. Names are validated by the config.
+pub fn build_resolvers(
+    names: &[String],
+    spotify: Option<Arc<SpotifyClient>>,
+    entity_search_threshold: f64,
+) -> Vec<Box<dyn UrlResolver>> {
+    names
+        .iter()
+        .filter_map(|name| -> Option<Box<dyn UrlResolver>> {
+            match name.as_str() {
+                "apple_music" => Some(Box::new(AppleMusicResolver {
+                    spotify: spotify.clone()?,
+                })),
+                "odesli" => Some(Box::new(OdesliResolver {
+                    spotify: spotify.clone(),
+                    entity_search_threshold,
+                })),
+                "deezer" => Some(Box::new(DeezerResolver {
+                    spotify: spotify.clone()?,
+                })),
+                "qobuz" => Some(Box::new(QobuzResolver {
+                    spotify: spotify.clone()?,
+                })),
+                "youtube" => Some(Box::new(YoutubeResolver {
+                    spotify: spotify.clone()?,
+                })),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Odesli (song.link), searching Spotify for Odesli's title/artist when it knows the song but
+/// has no Spotify link for it.
+pub struct OdesliResolver {
+    spotify: Option<Arc<SpotifyClient>>,
+    entity_search_threshold: f64,
+}
+
+#[async_trait]
+impl UrlResolver for OdesliResolver {
+    fn name(&self) -> &'static str {
+        "odesli"
+    }
+
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution {
+        let (artist, title) = match resolve_link(url).await {
+            Ok(Some(OdesliMatch::Spotify(id))) => return Resolution::Resolved(id),
+            Ok(Some(OdesliMatch::Entity { artist, title })) => (artist, title),
+            lookup => return unresolved(lookup),
+        };
+        // Odesli knows the song, so a miss from here on is the song not being on Spotify
+        let Some(spotify) = &self.spotify else {
+            return Resolution::Missed(UnresolvedReason::NotOnSpotify { artist, title });
+        };
+        match spotify
+            .search_track_with_threshold(&artist, &title, self.entity_search_threshold, playlist_id)
+            .await
+        {
+            Ok(Some(id)) => {
+                info!("Resolved {} to Spotify via entity search: {}", url, id);
+                Resolution::Resolved(id)
+            }
+            Ok(None) => {
+                warn!(
+                    "Unresolved for review: {} is \"{} - {}\" per Odesli, but no Spotify match reached ENTITY_SEARCH_THRESHOLD {}",
+                    url, artist, title, self.entity_search_threshold
+                );
+                Resolution::Missed(UnresolvedReason::NotOnSpotify { artist, title })
+            }
+            Err(e) => {
+                warn!("Entity search for {} failed: {}", url, e);
+                Resolution::Failed
+            }
+        }
+    }
+}
+
+/// iTunes lookup → Spotify search for Apple Music song links, which Odesli sometimes
+/// rate-limits. Ahead of Odesli by default; if the lookup fails, Odesli still gets the link.
+pub struct AppleMusicResolver {
+    spotify: Arc<SpotifyClient>,
+}
+
+#[async_trait]
+impl UrlResolver for AppleMusicResolver {
+    fn name(&self) -> &'static str {
+        "apple_music"
+    }
+
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution {
+        let Some((storefront, song_id)) = parse_apple_music_track_id(url) else {
+            return Resolution::Missed(UnresolvedReason::Unknown);
+        };
+        match fetch_apple_music_track_metadata(&storefront, &song_id).await {
+            Ok(Some((artist, title))) => {
+                search(
+                    &self.spotify,
+                    "Apple Music",
+                    url,
+                    playlist_id,
+                    &artist,
+                    &title,
+                )
+                .await
+            }
+            lookup => unresolved(lookup),
+        }
+    }
+}
+
+/// Deezer's public track API → Spotify search. Doesn't depend on Odesli at all.
+pub struct DeezerResolver {
+    spotify: Arc<SpotifyClient>,
+}
+
+#[async_trait]
+impl UrlResolver for DeezerResolver {
+    fn name(&self) -> &'static str {
+        "deezer"
+    }
+
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution {
+        let track_id = match parse_deezer_track_id(url) {
+            Some(id) => Some(id),
+            None if is_short_link(url) => resolve_short_link(url)
+                .await
+                .and_then(|full| parse_deezer_track_id(&full)),
+            None => None,
+        };
+        let Some(track_id) = track_id else {
+            return Resolution::Missed(UnresolvedReason::Unknown);
+        };
+        match fetch_deezer_track_metadata(&track_id).await {
+            Ok(Some((artist, title))) => {
+                search(&self.spotify, "Deezer", url, playlist_id, &artist, &title).await
+            }
+            lookup => unresolved(lookup),
+        }
+    }
+}
+
+/// Qobuz track metadata → Spotify search. Odesli doesn't support Qobuz, so this is the only
+/// way Qobuz links resolve.
+pub struct QobuzResolver {
+    spotify: Arc<SpotifyClient>,
+}
+
+#[async_trait]
+impl UrlResolver for QobuzResolver {
+    fn name(&self) -> &'static str {
+        "qobuz"
+    }
+
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution {
+        let Some(qobuz_id) = parse_qobuz_track_id(url) else {
+            return Resolution::Missed(UnresolvedReason::Unknown);
+        };
+        match fetch_qobuz_track_metadata(&qobuz_id).await {
+            Ok(Some((artist, title))) => {
+                search(&self.spotify, "Qobuz", url, playlist_id, &artist, &title).await
+            }
+            lookup => unresolved(lookup),
+        }
+    }
+}
+
+/// YouTube's oEmbed video title → Spotify search, for videos Odesli can't map. "Artist - Song"
+/// titles are split; otherwise a music channel's name stands in for the artist, and failing
+/// that the whole title is searched.
+pub struct YoutubeResolver {
+    spotify: Arc<SpotifyClient>,
+}
+
+#[async_trait]
+impl UrlResolver for YoutubeResolver {
+    fn name(&self) -> &'static str {
+        "youtube"
+    }
+
+    async fn resolve(&self, url: &str, playlist_id: &str) -> Resolution {
+        if platform(url) != "YouTube" {
+            return Resolution::Missed(UnresolvedReason::Unknown);
+        }
+        let (title, channel) = match fetch_youtube_oembed_title(url).await {
+            Ok(Some(found)) => found,
+            lookup => return unresolved(lookup),
+        };
+        let title = clean_video_title(&title);
+        if let Some((artist, song)) = split_video_title(&title) {
+            return search(&self.spotify, "YouTube", url, playlist_id, &artist, &song).await;
+        }
+        if let Some(artist) = channel_artist(&channel) {
+            return search(&self.spotify, "YouTube", url, playlist_id, &artist, &title).await;
+        }
+        match self.spotify.search_track_by_text(&title, playlist_id).await {
+            Ok(Some(id)) => Resolution::Resolved(id),
+            Ok(None) => Resolution::Missed(UnresolvedReason::Unknown),
+            Err(e) => {
+                warn!("Spotify search for YouTube {} failed: {}", url, e);
+                Resolution::Failed
+            }
+        }
+    }
+}
+
+async fn search(
+    spotify: &SpotifyClient,
+    source: &str,
+    url: &str,
+    playlist_id: &str,
+    artist: &str,
+    title: &str,
+) -> Resolution {
+    match spotify.search_track_for(artist, title, playlist_id).await {
+        Ok(Some(id)) => {
+            info!("Resolved {} {} to Spotify via search: {}", source, url, id);
+            Resolution::Resolved(id)
+        }
+        Ok(None) => Resolution::Missed(UnresolvedReason::Unknown),
+        Err(e) => {
+            warn!("Spotify search for {} {} failed: {}", source, url, e);
+            Resolution::Failed
+        }
+    }
+}
@@ -1,17 +1,46 @@
+use crate::pacing::{AdaptiveDelay, TokenBucket};
+use crate::types::is_user_message;
 use axum::http::StatusCode;
+use futures::{stream, StreamExt};
 use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha2::Sha256;
 use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// How far a signed request's timestamp may be from our clock, either way.
+pub const SIGNATURE_MAX_SKEW: Duration = Duration::from_secs(300);
+
 pub struct SlackWebClient {
     bot_token: String,
     client: reqwest::Client,
+    /// Web API root, overridable so tests can point the client at a mock server
+    api_base: String,
+    /// Earliest time the next paginated history/replies request may go out
+    next_page_at: tokio::sync::Mutex<Instant>,
+    /// Spacing between paginated requests, widened when Slack answers 429
+    page_delay: std::sync::Mutex<AdaptiveDelay>,
+    /// Shared pacing for reactions and posted messages (`SLACK_WRITES_PER_MINUTE`); `None` is
+    /// unpaced
+    write_budget: Option<std::sync::Mutex<TokenBucket>>,
 }
 
+const API_BASE: &str = "https://slack.com/api";
+
+/// Spacing between paginated `conversations.*` requests across all concurrent fetches starts
+/// at the minimum and grows up to the maximum while Slack rate-limits us.
+const MIN_PAGE_REQUEST_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_PAGE_REQUEST_INTERVAL: Duration = Duration::from_secs(5);
+const PAGE_REQUEST_INTERVAL_STEP: Duration = Duration::from_millis(50);
+
+/// Writes that may go out back to back before `SLACK_WRITES_PER_MINUTE` pacing kicks in.
+const WRITE_BURST: u32 = 5;
+
+/// Retries of a request Slack answered with 429, after waiting its `Retry-After`.
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+
 #[derive(Debug, Deserialize)]
 struct SlackApiResponse<T> {
     ok: bool,
@@ -41,9 +70,8 @@ struct ResponseMetadata {
 #[derive(Debug, Deserialize)]
 pub struct SlackMessage {
     pub ts: Option<String>,
-    #[serde(rename = "thread_ts")]
-    pub thread_ts: Option<String>,
     pub text: Option<String>,
+    pub user: Option<String>,
     #[serde(rename = "reply_count")]
     pub reply_count: Option<u32>,
     #[serde(rename = "bot_id")]
@@ -53,7 +81,6 @@ pub struct SlackMessage {
 
 #[derive(Debug, Deserialize)]
 struct ConversationsHistoryResponse {
-    ok: bool,
     messages: Option<Vec<SlackMessage>>,
     #[serde(rename = "response_metadata")]
     response_metadata: Option<ResponseMetadata>,
@@ -61,6 +88,14 @@ struct ConversationsHistoryResponse {
     has_more: Option<bool>,
 }
 
+/// Identity of the bot token, from `auth.test`.
+#[derive(Debug, Deserialize)]
+pub struct AuthTest {
+    pub user_id: String,
+    pub user: String,
+    pub team: String,
+}
+
 #[derive(Debug, Serialize)]
 struct ReactionsAddRequest {
     channel: String,
@@ -74,6 +109,16 @@ struct ChatPostMessageRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     thread_ts: Option<String>,
     text: String,
+    /// Block Kit layout; `text` is then the notification fallback
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blocks: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatPostEphemeralRequest {
+    channel: String,
+    user: String,
+    text: String,
 }
 
 impl SlackWebClient {
@@ -81,9 +126,47 @@ impl SlackWebClient {
         Self {
             bot_token,
             client: reqwest::Client::new(),
+            api_base: API_BASE.to_string(),
+            next_page_at: tokio::sync::Mutex::new(Instant::now()),
+            page_delay: std::sync::Mutex::new(AdaptiveDelay::new(
+                MIN_PAGE_REQUEST_INTERVAL,
+                MAX_PAGE_REQUEST_INTERVAL,
+                PAGE_REQUEST_INTERVAL_STEP,
+            )),
+            write_budget: None,
         }
     }
 
+    /// Paces reactions and posted messages to at most `per_minute` on average, after a short
+    /// burst, so a busy channel or bulk run stays under Slack's write limits.
+    pub fn with_write_budget(mut self, per_minute: Option<u32>) -> Self {
+        self.write_budget = per_minute
+            .map(|n| std::sync::Mutex::new(TokenBucket::new(WRITE_BURST, f64::from(n) / 60.0)));
+        self
+    }
+
+    /// Waits for a slot in the write budget, if there is one.
+    async fn pace_write(&self) {
+        let Some(budget) = &self.write_budget else {
+            return;
+        };
+        let wait = budget.lock().unwrap().reserve(Instant::now());
+        if !wait.is_zero() {
+            tracing::debug!("Slack write budget used up, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.to_string();
+        self
+    }
+
+    fn api_url(&self, method: &str) -> String {
+        format!("{}/{}", self.api_base, method)
+    }
+
     pub fn verify_signature(
         signing_secret: &str,
         timestamp: &str,
@@ -99,7 +182,7 @@ impl SlackWebClient {
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
             .as_secs();
 
-        if now.abs_diff(ts) > 300 {
+        if now.abs_diff(ts) > SIGNATURE_MAX_SKEW.as_secs() {
             return Err(StatusCode::UNAUTHORIZED);
         }
 
@@ -122,13 +205,15 @@ impl SlackWebClient {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self))]
     pub async fn reactions_add(
         &self,
         channel: &str,
         timestamp: &str,
         name: &str,
     ) -> Result<(), String> {
-        let url = "https://slack.com/api/reactions.add";
+        self.pace_write().await;
+        let url = self.api_url("reactions.add");
         let payload = ReactionsAddRequest {
             channel: channel.to_string(),
             timestamp: timestamp.to_string(),
@@ -155,17 +240,32 @@ impl SlackWebClient {
         Ok(())
     }
 
+    #[tracing::instrument(skip(self, text))]
     pub async fn chat_post_message(
         &self,
         channel: &str,
         thread_ts: Option<&str>,
         text: &str,
-    ) -> Result<(), String> {
-        let url = "https://slack.com/api/chat.postMessage";
+    ) -> Result<Option<String>, String> {
+        self.chat_post_blocks(channel, thread_ts, text, None).await
+    }
+
+    /// Like `chat_post_message`, laid out with Block Kit `blocks` when given.
+    #[tracing::instrument(skip(self, text, blocks))]
+    pub async fn chat_post_blocks(
+        &self,
+        channel: &str,
+        thread_ts: Option<&str>,
+        text: &str,
+        blocks: Option<serde_json::Value>,
+    ) -> Result<Option<String>, String> {
+        self.pace_write().await;
+        let url = self.api_url("chat.postMessage");
         let payload = ChatPostMessageRequest {
             channel: channel.to_string(),
             thread_ts: thread_ts.map(|s| s.to_string()),
             text: text.to_string(),
+            blocks,
         };
 
         let response: SlackApiResponse<HashMap<String, serde_json::Value>> = self
@@ -185,6 +285,117 @@ impl SlackWebClient {
             return Err(format!("Slack API error: {:?}", response.data));
         }
 
+        // The posted message's ts, e.g. to recognise reactions on it later
+        Ok(response
+            .data
+            .get("ts")
+            .and_then(|ts| ts.as_str())
+            .map(str::to_string))
+    }
+
+    /// Posts a message only `user` can see.
+    pub async fn chat_post_ephemeral(
+        &self,
+        channel: &str,
+        user: &str,
+        text: &str,
+    ) -> Result<(), String> {
+        self.pace_write().await;
+        let payload = ChatPostEphemeralRequest {
+            channel: channel.to_string(),
+            user: user.to_string(),
+            text: text.to_string(),
+        };
+
+        let response: SlackApiResponse<HashMap<String, serde_json::Value>> = self
+            .client
+            .post(self.api_url("chat.postEphemeral"))
+            .header("Authorization", format!("Bearer {}", self.bot_token))
+            .header("Content-Type", "application/json")
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Parse failed: {}", e))?;
+
+        if !response.ok {
+            return Err(format!("Slack API error: {:?}", response.data));
+        }
+
+        Ok(())
+    }
+
+    pub async fn auth_test(&self) -> Result<AuthTest, String> {
+        let raw: serde_json::Value = self
+            .client
+            .post(self.api_url("auth.test"))
+            .header("Authorization", format!("Bearer {}", self.bot_token))
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Parse failed: {}", e))?;
+
+        if !raw.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let err = raw
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("unknown");
+            return Err(format!("Slack API error: {}", err));
+        }
+
+        serde_json::from_value(raw).map_err(|e| format!("Parse failed: {}", e))
+    }
+
+    /// Opens a Socket Mode connection (`apps.connections.open`) and returns its WebSocket URL.
+    /// Needs the app-level token, not the bot token.
+    pub async fn apps_connections_open(&self, app_token: &str) -> Result<String, String> {
+        let raw: serde_json::Value = self
+            .client
+            .post(self.api_url("apps.connections.open"))
+            .header("Authorization", format!("Bearer {}", app_token))
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Parse failed: {}", e))?;
+
+        if !raw.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let err = raw
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("unknown");
+            return Err(format!("Slack API error: {}", err));
+        }
+
+        raw.get("url")
+            .and_then(|u| u.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| "Slack returned no Socket Mode URL".to_string())
+    }
+
+    /// Posts a delayed slash-command response to the `response_url` Slack handed us.
+    pub async fn post_response_url(
+        &self,
+        response_url: &str,
+        payload: &serde_json::Value,
+    ) -> Result<(), String> {
+        let response = self
+            .client
+            .post(response_url)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("response_url returned {}", response.status()));
+        }
+
         Ok(())
     }
 
@@ -192,7 +403,7 @@ impl SlackWebClient {
         &self,
         channel_name: &str,
     ) -> Result<Option<String>, String> {
-        let url = "https://slack.com/api/conversations.list";
+        let url = self.api_url("conversations.list");
         let mut cursor: Option<String> = None;
         let max_pages = 5;
         let mut page_count = 0;
@@ -212,7 +423,7 @@ impl SlackWebClient {
             // Parse response as raw JSON first to check 'ok' field
             let raw_response: serde_json::Value = self
                 .client
-                .get(url)
+                .get(&url)
                 .header("Authorization", format!("Bearer {}", self.bot_token))
                 .query(&params)
                 .send()
@@ -273,59 +484,142 @@ impl SlackWebClient {
         Ok(None)
     }
 
-    /// Fetches all messages from a channel (and thread replies) for backfill.
-    pub async fn fetch_channel_messages(&self, channel_id: &str) -> Result<Vec<String>, String> {
-        let mut all_texts = Vec::new();
-        let mut cursor: Option<String> = None;
+    /// Fetches a single message by timestamp, whether it's top-level or a thread reply.
+    pub async fn fetch_message(
+        &self,
+        channel_id: &str,
+        ts: &str,
+    ) -> Result<Option<SlackMessage>, String> {
+        let params = [
+            ("channel", channel_id),
+            ("latest", ts),
+            ("oldest", ts),
+            ("inclusive", "true"),
+            ("limit", "1"),
+        ];
+        let history: ConversationsHistoryResponse = self
+            .get_api(&self.api_url("conversations.history"), &params)
+            .await?;
+        if let Some(msg) = history
+            .messages
+            .unwrap_or_default()
+            .into_iter()
+            .find(|m| m.ts.as_deref() == Some(ts))
+        {
+            return Ok(Some(msg));
+        }
 
-        loop {
-            let mut params = vec![("channel", channel_id), ("limit", "200")];
-            if let Some(ref c) = cursor {
-                params.push(("cursor", c));
-            }
+        // Thread replies don't show up in history; conversations.replies returns them by ts
+        let params = [
+            ("channel", channel_id),
+            ("ts", ts),
+            ("inclusive", "true"),
+            ("limit", "1"),
+        ];
+        let replies: ConversationsHistoryResponse = self
+            .get_api(&self.api_url("conversations.replies"), &params)
+            .await?;
+        Ok(replies
+            .messages
+            .unwrap_or_default()
+            .into_iter()
+            .find(|m| m.ts.as_deref() == Some(ts)))
+    }
 
-            let raw: serde_json::Value = self
+    async fn get_api<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<T, String> {
+        let mut retries = 0;
+        let response = loop {
+            let response = self
                 .client
-                .get("https://slack.com/api/conversations.history")
+                .get(url)
                 .header("Authorization", format!("Bearer {}", self.bot_token))
-                .query(&params)
+                .query(params)
                 .send()
                 .await
-                .map_err(|e| format!("Request failed: {}", e))?
-                .json()
-                .await
-                .map_err(|e| format!("Parse failed: {}", e))?;
+                .map_err(|e| format!("Request failed: {}", e))?;
+            if response.status() != 429 {
+                self.page_delay.lock().unwrap().on_success();
+                break response;
+            }
 
-            if !raw.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
-                let err = raw
-                    .get("error")
-                    .and_then(|e| e.as_str())
-                    .unwrap_or("unknown");
-                return Err(format!("Slack API error: {}", err));
+            self.page_delay.lock().unwrap().on_throttled();
+            if retries == MAX_RATE_LIMIT_RETRIES {
+                return Err("Slack API error: ratelimited".to_string());
             }
+            retries += 1;
+            let retry_after = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(1);
+            tracing::warn!("Slack rate limited {}, waiting {}s", url, retry_after);
+            tokio::time::sleep(Duration::from_secs(retry_after)).await;
+        };
+        let raw: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Parse failed: {}", e))?;
 
-            let response: ConversationsHistoryResponse =
-                serde_json::from_value(raw).map_err(|e| format!("Parse failed: {}", e))?;
+        if !raw.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let err = raw
+                .get("error")
+                .and_then(|e| e.as_str())
+                .unwrap_or("unknown");
+            return Err(format!("Slack API error: {}", err));
+        }
 
-            let messages = response.messages.unwrap_or_default();
-            for msg in messages {
-                if msg.bot_id.is_some() || msg.subtype.is_some() {
-                    continue;
-                }
-                if let Some(ref text) = msg.text {
-                    if !text.is_empty() {
-                        all_texts.push(text.clone());
-                    }
-                }
-                if msg.reply_count.unwrap_or(0) > 0 {
-                    if let Some(ref ts) = msg.ts {
-                        if let Ok(replies) = self.fetch_thread_replies(channel_id, ts).await {
-                            all_texts.extend(replies);
-                        }
-                    }
-                }
+        serde_json::from_value(raw).map_err(|e| format!("Parse failed: {}", e))
+    }
+
+    /// Waits for this client's turn to make a paginated request.
+    async fn pace(&self) {
+        let mut next = self.next_page_at.lock().await;
+        let now = Instant::now();
+        if *next > now {
+            tokio::time::sleep(*next - now).await;
+        }
+        *next = Instant::now() + self.page_delay.lock().unwrap().current();
+    }
+
+    /// Fetches all messages with text from a channel (and thread replies) for backfill.
+    /// Up to `thread_concurrency` threads' replies are fetched at once. With `oldest` (a Slack
+    /// ts), only threads started after it are fetched.
+    pub async fn fetch_channel_messages(
+        &self,
+        channel_id: &str,
+        thread_concurrency: usize,
+        oldest: Option<&str>,
+    ) -> Result<Vec<SlackMessage>, String> {
+        let mut top_level = Vec::new();
+        let mut cursor: Option<String> = None;
+
+        loop {
+            let mut params = vec![("channel", channel_id), ("limit", "200")];
+            if let Some(oldest) = oldest {
+                params.push(("oldest", oldest));
+            }
+            if let Some(ref c) = cursor {
+                params.push(("cursor", c));
             }
 
+            self.pace().await;
+            let response: ConversationsHistoryResponse = self
+                .get_api(&self.api_url("conversations.history"), &params)
+                .await?;
+
+            top_level.extend(
+                response
+                    .messages
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|msg| msg.bot_id.is_none() && is_user_message(msg.subtype.as_deref())),
+            );
+
             cursor = response
                 .response_metadata
                 .and_then(|m| m.next_cursor)
@@ -333,18 +627,69 @@ impl SlackWebClient {
             if cursor.is_none() && !response.has_more.unwrap_or(false) {
                 break;
             }
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         }
 
-        Ok(all_texts)
+        // Fetch thread replies concurrently, then put each thread back after its parent
+        let threads: Vec<(usize, String)> = top_level
+            .iter()
+            .enumerate()
+            .filter_map(
+                |(i, msg)| match (msg.reply_count.unwrap_or(0) > 0, &msg.ts) {
+                    (true, Some(ts)) => Some((i, ts.clone())),
+                    _ => None,
+                },
+            )
+            .collect();
+        let mut replies: Vec<(usize, Vec<SlackMessage>)> = stream::iter(threads)
+            .map(|(i, ts)| self.fetch_thread_replies_at(i, channel_id, ts))
+            .buffer_unordered(thread_concurrency.max(1))
+            .collect()
+            .await;
+        replies.sort_by_key(|(i, _)| *i);
+
+        let mut replies = replies.into_iter().peekable();
+        let mut all_messages = Vec::new();
+        for (i, msg) in top_level.into_iter().enumerate() {
+            if msg.text.as_deref().is_some_and(|t| !t.is_empty()) {
+                all_messages.push(msg);
+            }
+            if let Some((_, thread)) = replies.next_if(|(j, _)| *j == i) {
+                all_messages.extend(thread);
+            }
+        }
+
+        Ok(all_messages)
+    }
+
+    /// A thread's replies, tagged with its parent's position. A thread whose replies can't be
+    /// fetched is logged and backfilled without them.
+    async fn fetch_thread_replies_at(
+        &self,
+        index: usize,
+        channel_id: &str,
+        thread_ts: String,
+    ) -> (usize, Vec<SlackMessage>) {
+        let replies = match self.fetch_thread_replies(channel_id, &thread_ts).await {
+            Ok(replies) => replies,
+            Err(e) => {
+                tracing::warn!(
+                    "Couldn't fetch replies to thread {} in {}, skipping them: {}",
+                    thread_ts,
+                    channel_id,
+                    e
+                );
+                Vec::new()
+            }
+        };
+        (index, replies)
     }
 
     async fn fetch_thread_replies(
         &self,
         channel_id: &str,
         thread_ts: &str,
-    ) -> Result<Vec<String>, String> {
-        let mut texts = Vec::new();
+    ) -> Result<Vec<SlackMessage>, String> {
+        let mut replies = Vec::new();
         let mut cursor: Option<String> = None;
 
         loop {
@@ -353,55 +698,30 @@ impl SlackWebClient {
                 params.push(("cursor", c));
             }
 
-            let raw: serde_json::Value = self
-                .client
-                .get("https://slack.com/api/conversations.replies")
-                .header("Authorization", format!("Bearer {}", self.bot_token))
-                .query(&params)
-                .send()
-                .await
-                .map_err(|e| format!("Request failed: {}", e))?
-                .json()
-                .await
-                .map_err(|e| format!("Parse failed: {}", e))?;
-
-            if !raw.get("ok").and_then(|v| v.as_bool()).unwrap_or(false) {
-                let err = raw
-                    .get("error")
-                    .and_then(|e| e.as_str())
-                    .unwrap_or("unknown");
-                return Err(format!("Slack API error: {}", err));
-            }
+            self.pace().await;
+            let response: ConversationsHistoryResponse = self
+                .get_api(&self.api_url("conversations.replies"), &params)
+                .await?;
 
-            let messages: Vec<SlackMessage> = raw
-                .get("messages")
-                .and_then(|m| serde_json::from_value(m.clone()).ok())
-                .unwrap_or_default();
-
-            for msg in messages {
-                if msg.bot_id.is_some() || msg.subtype.is_some() {
+            for msg in response.messages.unwrap_or_default() {
+                if msg.bot_id.is_some() || !is_user_message(msg.subtype.as_deref()) {
                     continue;
                 }
-                if let Some(ref text) = msg.text {
-                    if !text.is_empty() {
-                        texts.push(text.clone());
-                    }
+                if msg.text.as_deref().is_some_and(|t| !t.is_empty()) {
+                    replies.push(msg);
                 }
             }
 
-            let meta = raw.get("response_metadata");
-            cursor = meta
-                .and_then(|m| m.get("next_cursor"))
-                .and_then(|c| c.as_str())
-                .map(String::from)
+            cursor = response
+                .response_metadata
+                .and_then(|m| m.next_cursor)
                 .filter(|c| !c.is_empty());
             if cursor.is_none() {
                 break;
             }
-            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
         }
 
-        Ok(texts)
+        Ok(replies)
     }
 }
 
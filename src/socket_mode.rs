@@ -0,0 +1,185 @@
+use crate::commands::{run_command, SlashCommand};
+use crate::interactions::handle_interaction;
+use crate::types::SlackEnvelope;
+use crate::{handle_event, AppState};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+use tracing::{debug, info, warn};
+
+/// Wait before reconnecting after a connection attempt failed.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// One message from Slack over the Socket Mode WebSocket. `events_api`, `slash_commands` and
+/// `interactive` carry the same payload the HTTP endpoints would have received, and must be
+/// acknowledged by echoing `envelope_id`.
+#[derive(Debug, Deserialize)]
+struct SocketFrame {
+    #[serde(rename = "type")]
+    frame_type: String,
+    envelope_id: Option<String>,
+    #[serde(default)]
+    payload: Value,
+    reason: Option<String>,
+}
+
+/// Receives events and slash commands over Socket Mode (`SLACK_SOCKET_MODE`) for as long as
+/// the process runs, reconnecting whenever Slack closes the connection.
+pub async fn run(state: AppState, app_token: String) {
+    loop {
+        match connect(&state, &app_token).await {
+            Ok(()) => info!("Socket Mode connection closed, reconnecting"),
+            Err(e) => {
+                warn!("Socket Mode connection failed: {}", e);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Serves one WebSocket connection until Slack closes it or asks us to reconnect.
+async fn connect(state: &AppState, app_token: &str) -> Result<(), String> {
+    let url = state.slack.apps_connections_open(app_token).await?;
+    let (socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .map_err(|e| format!("WebSocket connect failed: {}", e))?;
+    info!("Connected to Slack over Socket Mode");
+
+    // Acks for slash commands are sent once the command replies, so writes go through a channel
+    let (mut sink, mut stream) = socket.split();
+    let (outgoing, mut queued) = mpsc::unbounded_channel::<Message>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            if let Err(e) = sink.send(message).await {
+                warn!("Failed to write to Socket Mode connection: {}", e);
+                break;
+            }
+        }
+    });
+
+    let result = loop {
+        let message = match stream.next().await {
+            Some(Ok(message)) => message,
+            Some(Err(e)) => break Err(format!("WebSocket read failed: {}", e)),
+            None => break Ok(()),
+        };
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Ping(data) => {
+                let _ = outgoing.send(Message::Pong(data));
+                continue;
+            }
+            Message::Close(_) => break Ok(()),
+            _ => continue,
+        };
+        let frame: SocketFrame = match serde_json::from_str(&text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Failed to parse Socket Mode message: {}", e);
+                continue;
+            }
+        };
+
+        match frame.frame_type.as_str() {
+            "hello" => debug!("Socket Mode hello received"),
+            "disconnect" => {
+                info!(
+                    "Slack asked to reconnect ({})",
+                    frame.reason.as_deref().unwrap_or("no reason given")
+                );
+                break Ok(());
+            }
+            "slash_commands" => {
+                let Some(envelope_id) = frame.envelope_id else {
+                    continue;
+                };
+                let command: SlashCommand = match serde_json::from_value(frame.payload) {
+                    Ok(command) => command,
+                    Err(e) => {
+                        warn!("Failed to parse slash command payload: {}", e);
+                        let _ = outgoing.send(ack(&envelope_id, None));
+                        continue;
+                    }
+                };
+                let state = state.clone();
+                let outgoing = outgoing.clone();
+                tokio::spawn(async move {
+                    let reply = run_command(state, command).await;
+                    let _ = outgoing.send(ack(&envelope_id, Some(reply)));
+                });
+            }
+            other => {
+                // Acknowledge first: Slack retries anything not acked within 3 seconds
+                if let Some(envelope_id) = &frame.envelope_id {
+                    let _ = outgoing.send(ack(envelope_id, None));
+                }
+                if other == "events_api" {
+                    if let SlackEnvelope::Event(event) = event_envelope(&frame.payload) {
+                        handle_event(state.clone(), *event);
+                    }
+                } else if other == "interactive" {
+                    handle_interaction(state.clone(), frame.payload);
+                } else {
+                    debug!("Ignoring Socket Mode {} message", other);
+                }
+            }
+        }
+    };
+
+    writer.abort();
+    result
+}
+
+/// The `events_api` payload is an HTTP event body, so it goes through the same parser.
+fn event_envelope(payload: &Value) -> SlackEnvelope {
+    serde_json::to_vec(payload)
+        .ok()
+        .and_then(|body| SlackEnvelope::parse(&body).ok())
+        .unwrap_or(SlackEnvelope::Ignored)
+}
+
+fn ack(envelope_id: &str, payload: Option<Value>) -> Message {
+    let mut ack = json!({ "envelope_id": envelope_id });
+    if let Some(payload) = payload {
+        ack["payload"] = payload;
+    }
+    Message::Text(ack.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_api_payload_parses_like_an_http_event() {
+        let frame: SocketFrame = serde_json::from_value(json!({
+            "type": "events_api",
+            "envelope_id": "env-1",
+            "accepts_response_payload": false,
+            "payload": {
+                "type": "event_callback",
+                "event": {
+                    "type": "message",
+                    "channel": "C123",
+                    "user": "U42",
+                    "ts": "1700000000.000100",
+                    "text": "https://open.spotify.com/track/abc"
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(frame.envelope_id.as_deref(), Some("env-1"));
+        let SlackEnvelope::Event(event) = event_envelope(&frame.payload) else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.channel.as_deref(), Some("C123"));
+        assert!(matches!(
+            event_envelope(&json!({ "type": "app_rate_limited" })),
+            SlackEnvelope::Ignored
+        ));
+    }
+}
@@ -1,29 +1,361 @@
+use crate::matching;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
 use base64::Engine;
+use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
-use tracing::warn;
+use tracing::{debug, warn};
 
 #[derive(Clone)]
 struct TokenCache {
     access_token: String,
     expires_at: Instant,
+    scope: Option<String>,
 }
 
 pub struct SpotifyClient {
     client_id: String,
     client_secret: String,
     refresh_token: String,
-    playlist_id: String,
+    /// The main playlist, switchable at runtime with `/jamcraft target`
+    playlist_id: std::sync::RwLock<String>,
     client: reqwest::Client,
+    /// Web API root, overridable so tests can point the client at a mock server
+    api_base: String,
     token_cache: Arc<Mutex<Option<TokenCache>>>,
+    rate_limit: Arc<Mutex<RateLimitTracker>>,
+    match_threshold: f64,
+    /// Country code for search and relinking; `None` uses the token's account country
+    market: Option<String>,
+    /// Playlist ID → market for tracks going there, overriding `market`
+    playlist_markets: HashMap<String, String>,
+    /// (market, track ID) → the ID Spotify serves in that market (see `canonical_track_id_for`)
+    canonical_ids: DashMap<(String, String), String>,
+    /// Extra attempts for a request that failed transiently (see `send_with_retries`)
+    max_retries: u32,
 }
 
+const API_BASE: &str = "https://api.spotify.com/v1";
+
+/// How long before Spotify's expiry a cached token counts as expired, so requests and the
+/// background refresher both renew it ahead of time.
+const TOKEN_REFRESH_AHEAD: Duration = Duration::from_secs(60);
+/// Pause after a failed background refresh before trying again.
+const TOKEN_REFRESH_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// How long the background refresher waits before renewing a token cached until `expires_at`:
+/// right away when nothing is cached. `expires_at` already leaves `TOKEN_REFRESH_AHEAD`.
+fn token_refresh_delay(expires_at: Option<Instant>, now: Instant) -> Duration {
+    expires_at.map_or(Duration::ZERO, |expires_at| {
+        expires_at.saturating_duration_since(now)
+    })
+}
+
+/// Extra attempts for a request that hit a network error, 5xx or 429, unless
+/// `SPOTIFY_MAX_RETRIES` says otherwise.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Backoff before the first retry; it doubles with each further one, up to `RETRY_MAX_DELAY`.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Backoff before retry number `attempt` (from 0), somewhere in the upper half of the doubled
+/// delay depending on `jitter` (0 to 1), so requests that failed together don't all retry
+/// together.
+fn backoff_delay(attempt: u32, jitter: f64) -> Duration {
+    let delay = RETRY_BASE_DELAY
+        .saturating_mul(1 << attempt.min(16))
+        .min(RETRY_MAX_DELAY);
+    delay / 2 + delay.mul_f64(jitter.clamp(0.0, 1.0) / 2.0)
+}
+
+/// A different number from 0 to 1 on each call, for `backoff_delay`. Each `RandomState` is
+/// seeded differently, which is all the randomness jitter needs.
+fn jitter() -> f64 {
+    use std::hash::{BuildHasher, Hasher};
+    let bits = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Which failures `send_with_retries` resends a request after.
+#[derive(Clone, Copy, PartialEq)]
+enum RetryOn {
+    /// Network errors, 5xx and 429, for requests that are safe to repeat
+    Transient,
+    /// Only 429s and failures to connect, where Spotify can't have acted on the request. Adds
+    /// use this: a request that timed out or got a 5xx may still have been applied, and sending
+    /// it again would insert the tracks a second time.
+    NotApplied,
+}
+
+/// Spotify caps how many items one add/remove request may carry.
+pub const MAX_ITEMS_PER_REQUEST: usize = 100;
+
+/// How far back `/jamcraft ratelimit` counts 429 responses.
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(3600);
+
+/// 429s seen from Spotify. While a `Retry-After` pause is running every request from this
+/// client waits it out, so concurrent adds don't keep hammering an endpoint that told us to stop.
+#[derive(Default)]
+struct RateLimitTracker {
+    hits: VecDeque<Instant>,
+    /// Every 429 since startup, so callers can tell whether a request they made was throttled
+    total_hits: u64,
+    last_retry_after: Option<(u64, Instant)>,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimitTracker {
+    fn record(&mut self, retry_after: u64, now: Instant) {
+        self.hits.push_back(now);
+        self.total_hits += 1;
+        self.last_retry_after = Some((retry_after, now));
+        let until = now + Duration::from_secs(retry_after);
+        self.paused_until = Some(self.paused_until.map_or(until, |p| p.max(until)));
+        self.prune(now);
+    }
+
+    fn prune(&mut self, now: Instant) {
+        while self
+            .hits
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RATE_LIMIT_WINDOW)
+        {
+            self.hits.pop_front();
+        }
+    }
+
+    fn remaining_pause(&self, now: Instant) -> Option<Duration> {
+        self.paused_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    fn status(&mut self, now: Instant) -> RateLimitStatus {
+        self.prune(now);
+        RateLimitStatus {
+            last_retry_after: self
+                .last_retry_after
+                .map(|(secs, at)| (secs, now.duration_since(at))),
+            hits_last_hour: self.hits.len(),
+            paused_for: self.remaining_pause(now),
+        }
+    }
+}
+
+/// Snapshot of the client's rate-limit state, for `/jamcraft ratelimit`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitStatus {
+    /// Last `Retry-After` value (seconds) and how long ago it was received.
+    pub last_retry_after: Option<(u64, Duration)>,
+    pub hits_last_hour: usize,
+    /// Time left in the current backoff pause, if any.
+    pub paused_for: Option<Duration>,
+}
+
+/// Identifies the bot to Spotify; override with `SPOTIFY_USER_AGENT`.
+pub const DEFAULT_USER_AGENT: &str = concat!("jamcraft/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the HTTP client used for all Spotify calls. Backfill and large playlist fetches make
+/// many sequential requests to the same hosts, so keep connections warm instead of reconnecting.
+fn build_http_client(user_agent: &str, proxy: Option<&str>) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = proxy {
+        match reqwest::Proxy::all(proxy) {
+            Ok(proxy) => builder = builder.proxy(proxy),
+            Err(e) => warn!("Ignoring invalid Spotify proxy: {}", e),
+        }
+    }
+    builder
+        .user_agent(user_agent)
+        .pool_max_idle_per_host(4)
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .http2_keep_alive_timeout(Duration::from_secs(10))
+        .http2_keep_alive_while_idle(true)
+        .build()
+        .unwrap_or_else(|_| reqwest::Client::new())
+}
+
+/// Minimum `matching::match_score` a loose (unqualified) search result needs to be accepted.
+pub const DEFAULT_MATCH_THRESHOLD: f64 = 0.6;
+
+/// Same, for searches built from Odesli entity data. Video titles and channel names are
+/// noisier than catalog metadata, so these need closer agreement by default.
+pub const DEFAULT_ENTITY_MATCH_THRESHOLD: f64 = 0.75;
+
 #[derive(Debug, Deserialize)]
 struct TokenResponse {
     access_token: String,
     expires_in: u64,
+    scope: Option<String>,
+}
+
+/// Who the token belongs to and who owns a playlist, for diagnosing 403s on add.
+#[derive(Debug)]
+pub struct PlaylistAccess {
+    pub user_id: String,
+    pub playlist_name: String,
+    pub owner_id: String,
+    pub collaborative: bool,
+    pub public: bool,
+}
+
+impl PlaylistAccess {
+    /// Spotify only lets the owner or collaborators (on collaborative playlists) add items.
+    pub fn can_modify(&self) -> bool {
+        self.owner_id == self.user_id || self.collaborative
+    }
+
+    /// The scope a token needs to add to this playlist, which depends on its visibility.
+    pub fn modify_scope(&self) -> &'static str {
+        if self.public {
+            "playlist-modify-public"
+        } else {
+            "playlist-modify-private"
+        }
+    }
+}
+
+/// What's in a playlist, as read by `get_playlist_contents`.
+#[derive(Debug, Default)]
+pub struct PlaylistContents {
+    pub track_ids: std::collections::HashSet<String>,
+    pub title_keys: std::collections::HashSet<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackMeta {
+    pub artists: Vec<String>,
+    pub title: String,
+    /// `None` for episodes
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+/// The parts of a track's audio features used to pick a mood reaction (both 0.0–1.0).
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct AudioFeatures {
+    pub valence: f64,
+    pub energy: f64,
+}
+
+impl AudioFeatures {
+    /// A reaction emoji for the track's feel (`REACT_WITH_MOOD`).
+    pub fn mood_emoji(&self) -> &'static str {
+        match (self.energy, self.valence) {
+            (e, _) if e >= 0.8 => "fire",
+            (e, v) if e < 0.4 && v < 0.35 => "rain_cloud",
+            (e, _) if e < 0.4 => "leaves",
+            (_, v) if v >= 0.65 => "sunny",
+            (_, v) if v < 0.3 => "new_moon",
+            _ => "notes",
+        }
+    }
+}
+
+impl TrackMeta {
+    /// From a track (or episode) object in an API response.
+    fn from_json(json: &serde_json::Value) -> Self {
+        let mut artists: Vec<String> = json["artists"]
+            .as_array()
+            .map(|a| {
+                a.iter()
+                    .filter_map(|ar| ar["name"].as_str())
+                    .map(|n| n.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Episodes have no artists; the show stands in for them
+        if let Some(show) = json["show"]["name"].as_str() {
+            artists.push(show.to_string());
+        }
+        Self {
+            artists,
+            title: json["name"].as_str().unwrap_or_default().to_string(),
+            album: json["album"]["name"].as_str().map(str::to_string),
+            duration: json["duration_ms"].as_u64().map(Duration::from_millis),
+        }
+    }
+}
+
+/// An item at a specific playlist position.
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub position: u64,
+    /// Bare track ID, or the full URI for non-tracks, matching what `add_tracks` takes
+    pub id: Option<String>,
+    pub meta: TrackMeta,
+    pub added_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Episode {
+    pub id: String,
+    pub name: String,
+}
+
+/// Playlist URI for an item: bare IDs are tracks, anything already in `spotify:` form
+/// (e.g. `spotify:episode:...`) is used as-is.
+pub fn item_uri(id: &str) -> String {
+    if id.starts_with("spotify:") {
+        id.to_string()
+    } else {
+        format!("spotify:track:{}", id)
+    }
+}
+
+/// The single item in a `limit=1` playlist items page.
+fn playlist_entry(page: &serde_json::Value, position: u64) -> Option<PlaylistEntry> {
+    let entry = page["items"].as_array()?.first()?;
+    let item = &entry["item"];
+    let id = match (item["type"].as_str(), item["id"].as_str()) {
+        (Some("track"), Some(id)) => Some(id.to_string()),
+        _ => item["uri"].as_str().map(str::to_string),
+    };
+    Some(PlaylistEntry {
+        position,
+        id,
+        meta: TrackMeta::from_json(item),
+        added_at: entry["added_at"].as_str().map(str::to_string),
+    })
+}
+
+/// The served track's ID from a `market`-qualified track response. When Spotify relinked the
+/// request, `linked_from` holds the ID that was asked for and `id` the one served instead;
+/// otherwise `id` is the requested ID.
+fn relinked_id(json: &serde_json::Value) -> Option<String> {
+    json["linked_from"]["id"].as_str()?;
+    json["id"].as_str().map(str::to_string)
+}
+
+/// `(uri, position)` of every repeat of an item that already appeared earlier in the playlist.
+pub fn duplicate_positions(uris: &[Option<String>]) -> Vec<(String, usize)> {
+    let mut seen = std::collections::HashSet::new();
+    uris.iter()
+        .enumerate()
+        .filter_map(|(position, uri)| {
+            let uri = uri.as_ref()?;
+            (!seen.insert(uri)).then(|| (uri.clone(), position))
+        })
+        .collect()
+}
+
+struct SearchCandidate {
+    id: String,
+    title: String,
+    artists: Vec<String>,
+}
+
+/// Which items `add_tracks_to` added and which Spotify rejected, with why.
+#[derive(Debug, Default)]
+pub struct BatchAddOutcome {
+    pub added: Vec<String>,
+    pub failed: Vec<(String, SpotifyError)>,
 }
 
 #[derive(Debug, Serialize)]
@@ -44,13 +376,18 @@ struct SpotifyErrorDetail {
     message: Option<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum SpotifyError {
     Network(String),
     Auth(String),
     RateLimit(u64),
+    /// HTTP 403, typically Spotify refusing requests from cloud hosting IPs
+    Forbidden(String),
+    /// HTTP 404, e.g. on an add of a resolved track Spotify has since taken down
+    NotFound(String),
+    /// HTTP 404 because the playlist itself is gone, not the track
+    PlaylistNotFound(String),
     Api(String),
-    #[allow(dead_code)]
     Other(String),
 }
 
@@ -60,6 +397,9 @@ impl std::fmt::Display for SpotifyError {
             SpotifyError::Network(msg) => write!(f, "Network error: {}", msg),
             SpotifyError::Auth(msg) => write!(f, "Auth error: {}", msg),
             SpotifyError::RateLimit(secs) => write!(f, "Rate limited, retry after {}s", secs),
+            SpotifyError::Forbidden(msg) => write!(f, "Forbidden: {}", msg),
+            SpotifyError::NotFound(msg) => write!(f, "Not found: {}", msg),
+            SpotifyError::PlaylistNotFound(msg) => write!(f, "Playlist not found: {}", msg),
             SpotifyError::Api(msg) => write!(f, "API error: {}", msg),
             SpotifyError::Other(msg) => write!(f, "Error: {}", msg),
         }
@@ -68,6 +408,22 @@ impl std::fmt::Display for SpotifyError {
 
 impl std::error::Error for SpotifyError {}
 
+impl SpotifyError {
+    /// The variant's name, e.g. `RateLimit`, for counting errors by kind.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            SpotifyError::Network(_) => "Network",
+            SpotifyError::Auth(_) => "Auth",
+            SpotifyError::RateLimit(_) => "RateLimit",
+            SpotifyError::Forbidden(_) => "Forbidden",
+            SpotifyError::NotFound(_) => "NotFound",
+            SpotifyError::PlaylistNotFound(_) => "PlaylistNotFound",
+            SpotifyError::Api(_) => "Api",
+            SpotifyError::Other(_) => "Other",
+        }
+    }
+}
+
 impl SpotifyClient {
     pub fn new(
         client_id: String,
@@ -79,12 +435,165 @@ impl SpotifyClient {
             client_id,
             client_secret,
             refresh_token,
-            playlist_id,
-            client: reqwest::Client::new(),
+            playlist_id: std::sync::RwLock::new(playlist_id),
+            client: build_http_client(DEFAULT_USER_AGENT, None),
+            api_base: API_BASE.to_string(),
             token_cache: Arc::new(Mutex::new(None)),
+            rate_limit: Arc::new(Mutex::new(RateLimitTracker::default())),
+            match_threshold: DEFAULT_MATCH_THRESHOLD,
+            market: None,
+            playlist_markets: HashMap::new(),
+            canonical_ids: DashMap::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
         }
     }
 
+    /// Sets the User-Agent and, if given, a proxy every Spotify request (token refreshes
+    /// included) goes through.
+    pub fn with_http_client(mut self, user_agent: &str, proxy: Option<&str>) -> Self {
+        self.client = build_http_client(user_agent, proxy);
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_api_base(mut self, api_base: &str) -> Self {
+        self.api_base = api_base.to_string();
+        self
+    }
+
+    /// Skips the token refresh, for tests against a mock API.
+    #[cfg(test)]
+    pub fn with_access_token(self, access_token: &str) -> Self {
+        *self.token_cache.lock().unwrap() = Some(TokenCache {
+            access_token: access_token.to_string(),
+            expires_at: Instant::now() + Duration::from_secs(3600),
+            scope: None,
+        });
+        self
+    }
+
+    /// How often a request that failed transiently is retried (`SPOTIFY_MAX_RETRIES`).
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_match_threshold(mut self, threshold: f64) -> Self {
+        self.match_threshold = threshold;
+        self
+    }
+
+    pub fn with_market(mut self, market: Option<&str>) -> Self {
+        self.market = market.map(str::to_string);
+        self
+    }
+
+    /// Per-playlist markets, e.g. from `PLAYLIST_ROUTING` routes owned by accounts elsewhere.
+    pub fn with_playlist_markets(mut self, markets: HashMap<String, String>) -> Self {
+        self.playlist_markets = markets;
+        self
+    }
+
+    /// The market for tracks going to `playlist_id`: its own if it has one, else the client's.
+    pub fn market_for(&self, playlist_id: &str) -> Option<&str> {
+        self.playlist_markets
+            .get(playlist_id)
+            .or(self.market.as_ref())
+            .map(String::as_str)
+    }
+
+    pub fn rate_limit_status(&self) -> RateLimitStatus {
+        self.rate_limit.lock().unwrap().status(Instant::now())
+    }
+
+    /// 429s received since startup. Compare before and after a request to see if it was throttled.
+    pub fn rate_limit_hits(&self) -> u64 {
+        self.rate_limit.lock().unwrap().total_hits
+    }
+
+    /// Sends an API request, first waiting out any shared `Retry-After` pause and recording
+    /// the response if it's a 429.
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, reqwest::Error> {
+        let pause = self
+            .rate_limit
+            .lock()
+            .unwrap()
+            .remaining_pause(Instant::now());
+        if let Some(pause) = pause {
+            warn!("Spotify backoff in effect, waiting {:?}", pause);
+            tokio::time::sleep(pause).await;
+        }
+
+        let response = request.send().await?;
+        if response.status() == 429 {
+            let retry_after = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(1);
+            self.rate_limit
+                .lock()
+                .unwrap()
+                .record(retry_after, Instant::now());
+        }
+        Ok(response)
+    }
+
+    /// Sends the request `request` builds, building and sending it again after the failures
+    /// `retry_on` allows, up to `max_retries` times. A 429 with `Retry-After` waits that out
+    /// (via `send`); anything else backs off exponentially with jitter. Once retries run out
+    /// the last response is returned as is.
+    async fn send_with_retries(
+        &self,
+        retry_on: RetryOn,
+        request: impl Fn() -> reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response, SpotifyError> {
+        let mut attempt = 0;
+        loop {
+            let result = self.send(request()).await;
+            let (retryable, has_retry_after) = match &result {
+                Ok(response) => (
+                    response.status() == 429
+                        || (retry_on == RetryOn::Transient && response.status().is_server_error()),
+                    response.headers().contains_key("Retry-After"),
+                ),
+                Err(e) => (retry_on == RetryOn::Transient || e.is_connect(), false),
+            };
+            if !retryable || attempt >= self.max_retries {
+                return result.map_err(|e| SpotifyError::Network(format!("Request failed: {}", e)));
+            }
+
+            let failure = match &result {
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string(),
+            };
+            if has_retry_after {
+                warn!(
+                    "Spotify request failed ({}), retrying after Retry-After ({}/{})",
+                    failure,
+                    attempt + 1,
+                    self.max_retries
+                );
+            } else {
+                let delay = backoff_delay(attempt, jitter());
+                warn!(
+                    "Spotify request failed ({}), retrying in {:?} ({}/{})",
+                    failure,
+                    delay,
+                    attempt + 1,
+                    self.max_retries
+                );
+                tokio::time::sleep(delay).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
     async fn get_access_token(&self) -> Result<String, SpotifyError> {
         // Check cache first
         {
@@ -96,7 +605,12 @@ impl SpotifyClient {
             }
         }
 
-        // Refresh token
+        self.refresh_access_token().await
+    }
+
+    /// Swaps the refresh token for a new access token and caches it, whether or not the cached
+    /// one is still valid.
+    async fn refresh_access_token(&self) -> Result<String, SpotifyError> {
         let auth = BASE64_STANDARD.encode(format!("{}:{}", self.client_id, self.client_secret));
         let url = "https://accounts.spotify.com/api/token";
 
@@ -106,14 +620,14 @@ impl SpotifyClient {
         ];
 
         let response = self
-            .client
-            .post(url)
-            .header("Authorization", format!("Basic {}", auth))
-            .header("Content-Type", "application/x-www-form-urlencoded")
-            .form(&params)
-            .send()
-            .await
-            .map_err(|e| SpotifyError::Network(format!("Request failed: {}", e)))?;
+            .send_with_retries(RetryOn::Transient, || {
+                self.client
+                    .post(url)
+                    .header("Authorization", format!("Basic {}", auth))
+                    .header("Content-Type", "application/x-www-form-urlencoded")
+                    .form(&params)
+            })
+            .await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -129,12 +643,13 @@ impl SpotifyClient {
             .await
             .map_err(|e| SpotifyError::Network(format!("Parse failed: {}", e)))?;
 
-        // Cache the token (subtract 60 seconds for safety margin)
-        let expires_at =
-            Instant::now() + Duration::from_secs(token_response.expires_in.saturating_sub(60));
+        // Cache the token, treating it as expired `TOKEN_REFRESH_AHEAD` early
+        let expires_at = Instant::now()
+            + Duration::from_secs(token_response.expires_in).saturating_sub(TOKEN_REFRESH_AHEAD);
         let cache = TokenCache {
             access_token: token_response.access_token.clone(),
             expires_at,
+            scope: token_response.scope,
         };
 
         {
@@ -145,28 +660,362 @@ impl SpotifyClient {
         Ok(token_response.access_token)
     }
 
-    /// Fetches all track IDs currently in the playlist. Requires playlist-read-private scope.
-    pub async fn get_playlist_track_ids(
+    /// Keeps the cached access token fresh in the background, refreshing it
+    /// `TOKEN_REFRESH_AHEAD` before Spotify expires it so no add waits on (or fails with) a
+    /// refresh.
+    /// A failed refresh is retried after `TOKEN_REFRESH_RETRY_DELAY`; requests still refresh
+    /// lazily meanwhile.
+    pub fn spawn_token_refresher(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                let expires_at = self
+                    .token_cache
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|c| c.expires_at);
+                tokio::time::sleep(token_refresh_delay(expires_at, Instant::now())).await;
+                match self.refresh_access_token().await {
+                    Ok(_) => debug!("Refreshed Spotify access token ahead of expiry"),
+                    Err(e) => {
+                        warn!(
+                            "Background Spotify token refresh failed, retrying in {:?}: {}",
+                            TOKEN_REFRESH_RETRY_DELAY, e
+                        );
+                        tokio::time::sleep(TOKEN_REFRESH_RETRY_DELAY).await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Scopes granted to the current access token (refreshing it if needed).
+    pub async fn granted_scopes(&self) -> Result<Option<String>, SpotifyError> {
+        self.get_access_token().await?;
+        let cache = self.token_cache.lock().unwrap();
+        Ok(cache.as_ref().and_then(|c| c.scope.clone()))
+    }
+
+    async fn get_json(&self, url: &str) -> Result<serde_json::Value, SpotifyError> {
+        let access_token = self.get_access_token().await?;
+        let response = self
+            .send_with_retries(RetryOn::Transient, || {
+                self.client
+                    .get(url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+            })
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            let message = format!("GET {} failed: {} - {}", url, status, text);
+            return Err(if status == 404 {
+                SpotifyError::NotFound(message)
+            } else {
+                SpotifyError::Api(message)
+            });
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| SpotifyError::Network(format!("Parse failed: {}", e)))
+    }
+
+    /// The Spotify user the token belongs to (`GET /me`), which proves the token refreshes and
+    /// the API answers.
+    pub async fn current_user_id(&self) -> Result<String, SpotifyError> {
+        let me = self.get_json(&format!("{}/me", self.api_base)).await?;
+        Ok(me["id"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Looks up the token's user and the playlist's owner, like `spotify_check` does.
+    pub async fn playlist_access(&self, playlist_id: &str) -> Result<PlaylistAccess, SpotifyError> {
+        let user_id = self.current_user_id().await?;
+        let playlist = self
+            .get_json(&format!(
+                "{}/playlists/{}?fields=name,owner.id,collaborative,public",
+                self.api_base, playlist_id
+            ))
+            .await
+            .map_err(|e| match e {
+                SpotifyError::NotFound(message) => SpotifyError::PlaylistNotFound(message),
+                e => e,
+            })?;
+
+        Ok(PlaylistAccess {
+            user_id,
+            playlist_name: playlist["name"].as_str().unwrap_or_default().to_string(),
+            owner_id: playlist["owner"]["id"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            collaborative: playlist["collaborative"].as_bool().unwrap_or(false),
+            public: playlist["public"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Checks the token's account can add to `playlist_id` (any playlist, not just the main
+    /// one), with a reason fit to show users if not.
+    pub async fn verify_can_modify(&self, playlist_id: &str) -> Result<PlaylistAccess, String> {
+        match self.playlist_access(playlist_id).await {
+            Ok(access) if access.can_modify() => Ok(access),
+            Ok(access) => Err(format!(
+                "\"{}\" is owned by {} and isn't collaborative, so the bot's account ({}) can't add to it",
+                access.playlist_name, access.owner_id, access.user_id
+            )),
+            Err(SpotifyError::PlaylistNotFound(_)) => {
+                Err(format!("Playlist {} doesn't exist or isn't visible to the bot's account", playlist_id))
+            }
+            Err(e) => Err(format!("Couldn't read playlist {}: {}", playlist_id, e)),
+        }
+    }
+
+    /// Most recent episode of a podcast show. Spotify lists show episodes newest first.
+    pub async fn latest_episode(&self, show_id: &str) -> Result<Option<Episode>, SpotifyError> {
+        let json = self
+            .get_json(&format!(
+                "{}/shows/{}/episodes?limit=1",
+                self.api_base, show_id
+            ))
+            .await?;
+
+        Ok(json
+            .get("items")
+            .and_then(|i| i.as_array())
+            .and_then(|a| a.first())
+            .and_then(|e| {
+                Some(Episode {
+                    id: e.get("id")?.as_str()?.to_string(),
+                    name: e.get("name")?.as_str()?.to_string(),
+                })
+            }))
+    }
+
+    pub async fn get_track_metadata(&self, track_id: &str) -> Result<TrackMeta, SpotifyError> {
+        let json = self
+            .get_json(&format!("{}/tracks/{}", self.api_base, track_id))
+            .await?;
+        Ok(TrackMeta::from_json(&json))
+    }
+
+    /// Valence and energy for a track. Spotify has no audio features for episodes.
+    pub async fn get_audio_features(&self, track_id: &str) -> Result<AudioFeatures, SpotifyError> {
+        let json = self
+            .get_json(&format!("{}/audio-features/{}", self.api_base, track_id))
+            .await?;
+        serde_json::from_value(json)
+            .map_err(|e| SpotifyError::Api(format!("Unexpected audio features: {}", e)))
+    }
+
+    /// The ID Spotify serves for a track in the market of the playlist it's going to (its
+    /// route's market, else `SPOTIFY_MARKET`, else the account's country). Spotify relinks
+    /// tracks per market, so the same song can be posted under several IDs; they all map to the
+    /// one that's playable here, which is what dedupe and the duplicate check compare. Cached
+    /// for the life of the process. Episodes and failed lookups come back unchanged.
+    pub async fn canonical_track_id_for(&self, track_id: &str, playlist_id: &str) -> String {
+        if track_id.starts_with("spotify:") {
+            return track_id.to_string();
+        }
+        let market = self
+            .market_for(playlist_id)
+            .unwrap_or("from_token")
+            .to_string();
+        let key = (market.clone(), track_id.to_string());
+        if let Some(canonical) = self.canonical_ids.get(&key) {
+            return canonical.clone();
+        }
+
+        let url = format!("{}/tracks/{}?market={}", self.api_base, track_id, market);
+        match self.get_json(&url).await {
+            Ok(json) => {
+                let canonical = relinked_id(&json).unwrap_or_else(|| track_id.to_string());
+                if canonical != track_id {
+                    tracing::info!(
+                        "Track {} is relinked to {} in market {}",
+                        track_id,
+                        canonical,
+                        market
+                    );
+                    // Looking the canonical ID up again needn't cost a request
+                    self.canonical_ids
+                        .insert((market.clone(), canonical.clone()), canonical.clone());
+                }
+                self.canonical_ids.insert(key, canonical.clone());
+                canonical
+            }
+            Err(e) => {
+                warn!("Couldn't look up relinking for {}: {}", track_id, e);
+                track_id.to_string()
+            }
+        }
+    }
+
+    /// First and last items of a playlist by position, or `None` if it's empty. Costs two
+    /// single-item requests rather than a full playlist fetch.
+    pub async fn playlist_ends(
         &self,
+        playlist_id: &str,
+    ) -> Result<Option<(PlaylistEntry, PlaylistEntry)>, SpotifyError> {
+        let first_page = self.playlist_page(playlist_id, 0).await?;
+        let total = first_page["total"].as_u64().unwrap_or(0);
+        let Some(first) = playlist_entry(&first_page, 0) else {
+            return Ok(None);
+        };
+        let last = if total > 1 {
+            let last_page = self.playlist_page(playlist_id, total - 1).await?;
+            playlist_entry(&last_page, total - 1)
+        } else {
+            None
+        };
+        let last = last.unwrap_or_else(|| first.clone());
+        Ok(Some((first, last)))
+    }
+
+    /// The item at `position`, or `None` past the end of the playlist.
+    pub async fn playlist_entry_at(
+        &self,
+        playlist_id: &str,
+        position: u64,
+    ) -> Result<Option<PlaylistEntry>, SpotifyError> {
+        let page = self.playlist_page(playlist_id, position).await?;
+        Ok(playlist_entry(&page, position))
+    }
+
+    async fn playlist_page(
+        &self,
+        playlist_id: &str,
+        offset: u64,
+    ) -> Result<serde_json::Value, SpotifyError> {
+        self.get_json(&format!(
+            "{}/playlists/{}/items?limit=1&offset={}",
+            self.api_base, playlist_id, offset
+        ))
+        .await
+    }
+
+    pub async fn episode_name(&self, episode_id: &str) -> Result<String, SpotifyError> {
+        let json = self
+            .get_json(&format!("{}/episodes/{}", self.api_base, episode_id))
+            .await?;
+        Ok(json["name"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub fn playlist_id(&self) -> String {
+        self.playlist_id.read().unwrap().clone()
+    }
+
+    /// Makes `playlist_id` the main playlist until the next restart. Check it with
+    /// `verify_can_modify` first.
+    pub fn set_playlist_id(&self, playlist_id: &str) {
+        *self.playlist_id.write().unwrap() = playlist_id.to_string();
+    }
+
+    /// Fetches all track IDs currently in a playlist the token can read. Requires
+    /// playlist-read-private scope for private playlists.
+    pub async fn get_playlist_track_ids_for(
+        &self,
+        playlist_id: &str,
     ) -> Result<std::collections::HashSet<String>, SpotifyError> {
-        let mut track_ids = std::collections::HashSet::new();
+        Ok(self.get_playlist_contents(playlist_id).await?.track_ids)
+    }
+
+    /// A playlist's track IDs and, for `DEDUPE_BY_TITLE`, their `matching::title_key`s, from a
+    /// single read of its items.
+    pub async fn get_playlist_contents(
+        &self,
+        playlist_id: &str,
+    ) -> Result<PlaylistContents, SpotifyError> {
+        let items = self.get_playlist_items(playlist_id).await?;
+        let track_ids = items
+            .iter()
+            .filter_map(|item| item.get("uri").and_then(|u| u.as_str()))
+            .filter_map(|uri| {
+                // Tracks are tracked by bare ID, episodes by full URI, matching what `item_uri` adds
+                if let Some(id) = uri.strip_prefix("spotify:track:") {
+                    Some(id.to_string())
+                } else if uri.starts_with("spotify:episode:") {
+                    Some(uri.to_string())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let title_keys = items
+            .iter()
+            .filter(|item| item["type"] == "track")
+            .map(|item| {
+                let meta = TrackMeta::from_json(item);
+                matching::title_key(&meta.artists, &meta.title)
+            })
+            .collect();
+        Ok(PlaylistContents {
+            track_ids,
+            title_keys,
+        })
+    }
+
+    /// Every item's URI in playlist order. `None` for entries without one (e.g. local files),
+    /// kept so indices match playlist positions.
+    pub async fn get_playlist_item_uris(
+        &self,
+        playlist_id: &str,
+    ) -> Result<Vec<Option<String>>, SpotifyError> {
+        let items = self.get_playlist_items(playlist_id).await?;
+        Ok(items
+            .iter()
+            .map(|item| {
+                item.get("uri")
+                    .and_then(|u| u.as_str())
+                    .filter(|u| !u.starts_with("spotify:local:"))
+                    .map(str::to_string)
+            })
+            .collect())
+    }
+
+    /// Like `get_playlist_item_uris`, with the snapshot ID the positions belong to. Paging
+    /// isn't atomic, so the snapshot is read before and after; if the playlist changed in
+    /// between, the positions can't be trusted and this fails instead.
+    pub async fn get_playlist_item_uris_at_snapshot(
+        &self,
+        playlist_id: &str,
+    ) -> Result<(String, Vec<Option<String>>), SpotifyError> {
+        let before = self.playlist_snapshot_id(playlist_id).await?;
+        let uris = self.get_playlist_item_uris(playlist_id).await?;
+        let after = self.playlist_snapshot_id(playlist_id).await?;
+        if before != after {
+            return Err(SpotifyError::Other(
+                "the playlist changed while it was being read, try again".to_string(),
+            ));
+        }
+        Ok((after, uris))
+    }
+
+    /// Every entry's `item` object (track or episode) in playlist order; null where missing.
+    #[tracing::instrument(skip(self))]
+    async fn get_playlist_items(
+        &self,
+        playlist_id: &str,
+    ) -> Result<Vec<serde_json::Value>, SpotifyError> {
+        let mut entries = Vec::new();
         let mut offset = 0;
         let limit = 50;
 
         loop {
             let access_token = self.get_access_token().await?;
             let url = format!(
-                "https://api.spotify.com/v1/playlists/{}/items?limit={}&offset={}",
-                self.playlist_id, limit, offset
+                "{}/playlists/{}/items?limit={}&offset={}",
+                self.api_base, playlist_id, limit, offset
             );
 
             let response = self
-                .client
-                .get(&url)
-                .header("Authorization", format!("Bearer {}", access_token))
-                .send()
-                .await
-                .map_err(|e| SpotifyError::Network(format!("Request failed: {}", e)))?;
+                .send_with_retries(RetryOn::Transient, || {
+                    self.client
+                        .get(&url)
+                        .header("Authorization", format!("Bearer {}", access_token))
+                })
+                .await?;
 
             if !response.status().is_success() {
                 let status = response.status();
@@ -187,15 +1036,7 @@ impl SpotifyClient {
                 .and_then(|i| i.as_array())
                 .map(|a| a.as_slice())
                 .unwrap_or(&[]);
-            for item in items {
-                if let Some(item_obj) = item.get("item") {
-                    if item_obj.get("type").and_then(|t| t.as_str()) == Some("track") {
-                        if let Some(id) = item_obj.get("id").and_then(|i| i.as_str()) {
-                            track_ids.insert(id.to_string());
-                        }
-                    }
-                }
-            }
+            entries.extend(items.iter().map(|entry| entry["item"].clone()));
 
             let total = json.get("total").and_then(|t| t.as_u64()).unwrap_or(0);
             offset += items.len() as u32;
@@ -206,34 +1047,250 @@ impl SpotifyClient {
             tokio::time::sleep(Duration::from_millis(100)).await;
         }
 
-        Ok(track_ids)
+        Ok(entries)
     }
 
-    pub async fn add_track(&self, track_id: &str) -> Result<(), SpotifyError> {
+    /// Current snapshot ID of a playlist, required for position-based removals.
+    pub async fn playlist_snapshot_id(&self, playlist_id: &str) -> Result<String, SpotifyError> {
+        let json = self
+            .get_json(&format!(
+                "{}/playlists/{}?fields=snapshot_id",
+                self.api_base, playlist_id
+            ))
+            .await?;
+        json["snapshot_id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| SpotifyError::Api("Playlist has no snapshot_id".to_string()))
+    }
+
+    /// Whether the playlist exists, to tell what an add's 404 was about: Spotify answers the
+    /// same way for a missing playlist as for a missing track.
+    async fn playlist_exists(&self, playlist_id: &str) -> Result<bool, SpotifyError> {
+        let access_token = self.get_access_token().await?;
+        let url = format!("{}/playlists/{}?fields=id", self.api_base, playlist_id);
+        let response = self
+            .send_with_retries(RetryOn::Transient, || {
+                self.client
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+            })
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            Ok(true)
+        } else if status == 404 {
+            Ok(false)
+        } else {
+            Err(SpotifyError::Api(format!("GET {} failed: {}", url, status)))
+        }
+    }
+
+    /// Removes every occurrence of the given tracks (bare IDs or full URIs) from a playlist.
+    #[tracing::instrument(skip(self))]
+    pub async fn remove_tracks_from(
+        &self,
+        playlist_id: &str,
+        track_ids: &[String],
+    ) -> Result<(), SpotifyError> {
+        for chunk in track_ids.chunks(MAX_ITEMS_PER_REQUEST) {
+            self.remove_items_from(playlist_id, chunk).await?;
+        }
+        Ok(())
+    }
+
+    /// One remove request for up to 100 items, retried like `add_items_to`.
+    async fn remove_items_from(
+        &self,
+        playlist_id: &str,
+        track_ids: &[String],
+    ) -> Result<(), SpotifyError> {
+        let url = format!("{}/playlists/{}/items", self.api_base, playlist_id);
+        let tracks: Vec<serde_json::Value> = track_ids
+            .iter()
+            .map(|id| serde_json::json!({ "uri": item_uri(id) }))
+            .collect();
+        let body = serde_json::json!({ "tracks": tracks });
         let mut can_retry_auth = true;
-        let mut can_retry_rate_limit = true;
 
         loop {
             let access_token = self.get_access_token().await?;
+            let response = self
+                .send_with_retries(RetryOn::Transient, || {
+                    self.client
+                        .delete(&url)
+                        .header("Authorization", format!("Bearer {}", access_token))
+                        .json(&body)
+                })
+                .await?;
 
-            let url = format!(
-                "https://api.spotify.com/v1/playlists/{}/items",
-                self.playlist_id
-            );
+            let status = response.status();
+            if status == 401 && can_retry_auth {
+                warn!("Got 401, clearing token cache and retrying");
+                *self.token_cache.lock().unwrap() = None;
+                can_retry_auth = false;
+                continue;
+            }
+            if status == 429 {
+                let retry_after = response
+                    .headers()
+                    .get("Retry-After")
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(1);
+                return Err(SpotifyError::RateLimit(retry_after));
+            }
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                let message = format!("Remove items failed: {} - {}", status, text);
+                return Err(match status.as_u16() {
+                    403 => SpotifyError::Forbidden(message),
+                    404 => SpotifyError::NotFound(message),
+                    _ => SpotifyError::Api(message),
+                });
+            }
+            return Ok(());
+        }
+    }
+
+    /// Removes the items at the given `(uri, position)`s, all relative to `snapshot_id`.
+    ///
+    /// Spotify accepts at most 100 items per request. Removals are sent from the end of the
+    /// playlist backwards so earlier positions stay valid as each chunk is applied, and each
+    /// request uses the snapshot returned by the previous one. Returns the final snapshot ID.
+    #[tracing::instrument(skip(self, removals))]
+    pub async fn remove_positions(
+        &self,
+        playlist_id: &str,
+        mut removals: Vec<(String, usize)>,
+        snapshot_id: String,
+    ) -> Result<String, SpotifyError> {
+        removals.sort_by_key(|(_, position)| std::cmp::Reverse(*position));
+        let url = format!("{}/playlists/{}/items", self.api_base, playlist_id);
+        let mut snapshot_id = snapshot_id;
+
+        for chunk in removals.chunks(MAX_ITEMS_PER_REQUEST) {
+            let tracks: Vec<serde_json::Value> = chunk
+                .iter()
+                .map(|(uri, position)| serde_json::json!({ "uri": uri, "positions": [position] }))
+                .collect();
+            let body = serde_json::json!({ "tracks": tracks, "snapshot_id": snapshot_id });
+            let mut can_retry_auth = true;
+
+            let response = loop {
+                let access_token = self.get_access_token().await?;
+                let response = self
+                    .send_with_retries(RetryOn::Transient, || {
+                        self.client
+                            .delete(&url)
+                            .header("Authorization", format!("Bearer {}", access_token))
+                            .json(&body)
+                    })
+                    .await?;
+                if response.status() == 401 && can_retry_auth {
+                    warn!("Got 401, clearing token cache and retrying");
+                    *self.token_cache.lock().unwrap() = None;
+                    can_retry_auth = false;
+                    continue;
+                }
+                break response;
+            };
+
+            let status = response.status();
+            if status == 429 {
+                let retry_after = response
+                    .headers()
+                    .get("Retry-After")
+                    .and_then(|h| h.to_str().ok())
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(1);
+                return Err(SpotifyError::RateLimit(retry_after));
+            }
+            if !status.is_success() {
+                let text = response.text().await.unwrap_or_default();
+                let message = format!("Remove items failed: {} - {}", status, text);
+                return Err(match status.as_u16() {
+                    403 => SpotifyError::Forbidden(message),
+                    404 => SpotifyError::NotFound(message),
+                    _ => SpotifyError::Api(message),
+                });
+            }
+
+            let json: serde_json::Value = response
+                .json()
+                .await
+                .map_err(|e| SpotifyError::Network(format!("Parse failed: {}", e)))?;
+            if let Some(next) = json["snapshot_id"].as_str() {
+                snapshot_id = next.to_string();
+            }
+        }
+
+        Ok(snapshot_id)
+    }
+
+    /// Adds tracks (bare track IDs) or any other items given as full `spotify:` URIs, in
+    /// requests of up to 100. Spotify
+    /// rejects a whole request over one bad URI, so when a batch fails with an API error its
+    /// items are retried one at a time to isolate the bad ones and still add the rest.
+    pub async fn add_tracks_to(&self, playlist_id: &str, track_ids: &[String]) -> BatchAddOutcome {
+        let mut outcome = BatchAddOutcome::default();
+        for chunk in track_ids.chunks(MAX_ITEMS_PER_REQUEST) {
+            match self.add_items_to(playlist_id, chunk).await {
+                Ok(()) => outcome.added.extend(chunk.iter().cloned()),
+                Err(SpotifyError::Api(e) | SpotifyError::NotFound(e)) if chunk.len() > 1 => {
+                    warn!(
+                        "Adding {} items failed ({}), retrying them one at a time",
+                        chunk.len(),
+                        e
+                    );
+                    for track_id in chunk {
+                        match self
+                            .add_items_to(playlist_id, std::slice::from_ref(track_id))
+                            .await
+                        {
+                            Ok(()) => outcome.added.push(track_id.clone()),
+                            Err(e) => outcome.failed.push((track_id.clone(), e)),
+                        }
+                    }
+                }
+                // Auth, network, rate-limit and 403 errors would fail each item the same way
+                Err(e) => outcome
+                    .failed
+                    .extend(chunk.iter().map(|track_id| (track_id.clone(), e.clone()))),
+            }
+        }
+        outcome
+    }
+
+    /// One add request for up to 100 items, retrying once on 401 with a fresh token and on
+    /// 429s and connect failures up to `max_retries` times. Adds aren't idempotent, so a
+    /// timeout or 5xx is not retried: Spotify may have added the tracks anyway.
+    #[tracing::instrument(skip(self))]
+    async fn add_items_to(
+        &self,
+        playlist_id: &str,
+        track_ids: &[String],
+    ) -> Result<(), SpotifyError> {
+        let mut can_retry_auth = true;
+
+        loop {
+            let access_token = self.get_access_token().await?;
+
+            let url = format!("{}/playlists/{}/items", self.api_base, playlist_id);
 
             let payload = AddTracksRequest {
-                uris: vec![format!("spotify:track:{}", track_id)],
+                uris: track_ids.iter().map(|id| item_uri(id)).collect(),
             };
 
             let response = self
-                .client
-                .post(&url)
-                .header("Authorization", format!("Bearer {}", access_token))
-                .header("Content-Type", "application/json")
-                .json(&payload)
-                .send()
-                .await
-                .map_err(|e| SpotifyError::Network(format!("Request failed: {}", e)))?;
+                .send_with_retries(RetryOn::NotApplied, || {
+                    self.client
+                        .post(&url)
+                        .header("Authorization", format!("Bearer {}", access_token))
+                        .header("Content-Type", "application/json")
+                        .json(&payload)
+                })
+                .await?;
 
             let status = response.status();
 
@@ -248,7 +1305,7 @@ impl SpotifyClient {
                 continue;
             }
 
-            // Handle 429: rate limit
+            // Still rate limited once the retries ran out
             if status == 429 {
                 let retry_after = response
                     .headers()
@@ -256,13 +1313,6 @@ impl SpotifyClient {
                     .and_then(|h| h.to_str().ok())
                     .and_then(|s| s.parse::<u64>().ok())
                     .unwrap_or(1);
-
-                if can_retry_rate_limit {
-                    warn!("Rate limited, waiting {} seconds", retry_after);
-                    tokio::time::sleep(Duration::from_secs(retry_after)).await;
-                    can_retry_rate_limit = false;
-                    continue;
-                }
                 return Err(SpotifyError::RateLimit(retry_after));
             }
 
@@ -292,8 +1342,8 @@ impl SpotifyClient {
                         .and_then(|e| e.get("reason"))
                         .and_then(|r| r.as_str());
                     let mut detail = format!(
-                        "Spotify API error: status={} playlist_id={} track_id={} message={}",
-                        status, self.playlist_id, track_id, msg
+                        "Spotify API error: status={} playlist_id={} track_ids={:?} message={}",
+                        status, playlist_id, track_ids, msg
                     );
                     if let Some(r) = reason {
                         detail.push_str(&format!(" reason={}", r));
@@ -301,6 +1351,34 @@ impl SpotifyClient {
                     detail.push_str(&header_info);
                     warn!("{}", detail);
                 }
+                if status == 403 {
+                    return Err(SpotifyError::Forbidden(format!(
+                        "Add track failed: {}",
+                        text
+                    )));
+                }
+                if status == 404 {
+                    // Only a missing track should count against the track
+                    return Err(match self.playlist_exists(playlist_id).await {
+                        Ok(true) => SpotifyError::NotFound(format!("Add track failed: {}", text)),
+                        Ok(false) => SpotifyError::PlaylistNotFound(format!(
+                            "Add track failed, playlist {} doesn't exist: {}",
+                            playlist_id, text
+                        )),
+                        Err(e) => SpotifyError::Api(format!(
+                            "Add track failed: {} - {} (and checking the playlist failed: {})",
+                            status, text, e
+                        )),
+                    });
+                }
+                // Like a timeout, a 5xx leaves it unknown whether the add went through, so it
+                // mustn't be retried item by item either
+                if status.is_server_error() {
+                    return Err(SpotifyError::Network(format!(
+                        "Add track failed, may have been applied: {} - {}",
+                        status, text
+                    )));
+                }
                 return Err(SpotifyError::Api(format!(
                     "Add track failed: {} - {}",
                     status, text
@@ -314,35 +1392,188 @@ impl SpotifyClient {
 
 impl SpotifyClient {
     /// Search for a track by artist and title. Returns the best match track ID if found.
+    ///
+    /// Tries a strict `artist:"x" track:"y"` query first. If that comes back empty (scraped
+    /// artist names often differ from Spotify's, e.g. "feat." credits or collaborations), retries
+    /// with a free-text query and only accepts a result that passes the fuzzy-match guard.
+    #[tracing::instrument(skip(self))]
     pub async fn search_track(
         &self,
         artist: &str,
         title: &str,
     ) -> Result<Option<String>, SpotifyError> {
-        let access_token = self.get_access_token().await?;
+        self.search_track_in(artist, title, self.match_threshold, self.market.as_deref())
+            .await
+    }
+
+    /// Like `search_track`, in the market of the playlist the track is going to.
+    #[tracing::instrument(skip(self))]
+    pub async fn search_track_for(
+        &self,
+        artist: &str,
+        title: &str,
+        playlist_id: &str,
+    ) -> Result<Option<String>, SpotifyError> {
+        self.search_track_in(
+            artist,
+            title,
+            self.match_threshold,
+            self.market_for(playlist_id),
+        )
+        .await
+    }
 
-        let query = format!(
+    /// Like `search_track_for`, with the loose match needing `threshold` instead of the
+    /// client's own.
+    #[tracing::instrument(skip(self))]
+    pub async fn search_track_with_threshold(
+        &self,
+        artist: &str,
+        title: &str,
+        threshold: f64,
+        playlist_id: &str,
+    ) -> Result<Option<String>, SpotifyError> {
+        self.search_track_in(artist, title, threshold, self.market_for(playlist_id))
+            .await
+    }
+
+    async fn search_track_in(
+        &self,
+        artist: &str,
+        title: &str,
+        threshold: f64,
+        market: Option<&str>,
+    ) -> Result<Option<String>, SpotifyError> {
+        let strict_query = format!(
             "artist:\"{}\" track:\"{}\"",
             artist.replace('"', "\\\""),
             title.replace('"', "\\\"")
         );
-        let encoded = urlencoding::encode(&query);
-        let url = format!(
-            "https://api.spotify.com/v1/search?q={}&type=track&limit=1",
-            encoded
+        let candidates = self.search_candidates(&strict_query, 1, market).await?;
+        if let Some(candidate) = candidates.into_iter().next() {
+            tracing::info!(
+                "Spotify search found: {} - {} -> {}",
+                artist,
+                title,
+                candidate.id
+            );
+            return Ok(Some(candidate.id));
+        }
+
+        let loose_query = format!("{} {}", title, artist);
+        let candidates = self.search_candidates(&loose_query, 5, market).await?;
+        let best = candidates
+            .into_iter()
+            .map(|c| {
+                let score = matching::match_score(artist, title, &c.artists, &c.title);
+                (c, score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((candidate, score)) if score >= threshold => {
+                tracing::info!(
+                    "Spotify loose search found: {} - {} -> {} ({} - {}, score {:.2})",
+                    artist,
+                    title,
+                    candidate.id,
+                    candidate.artists.join(", "),
+                    candidate.title,
+                    score
+                );
+                Ok(Some(candidate.id))
+            }
+            Some((candidate, score)) => {
+                tracing::info!(
+                    "Rejected loose search match for {} - {}: {} - {} (score {:.2} < {:.2})",
+                    artist,
+                    title,
+                    candidate.artists.join(", "),
+                    candidate.title,
+                    score,
+                    threshold
+                );
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Searches for free text that may hold a title and artist in any order, such as a video
+    /// title without an "Artist - Song" dash. Only accepts a result whose title appears in the
+    /// text, preferring one whose artist does too. Searches in the market of `playlist_id`.
+    #[tracing::instrument(skip(self))]
+    pub async fn search_track_by_text(
+        &self,
+        text: &str,
+        playlist_id: &str,
+    ) -> Result<Option<String>, SpotifyError> {
+        let candidates: Vec<SearchCandidate> = self
+            .search_candidates(text, 5, self.market_for(playlist_id))
+            .await?
+            .into_iter()
+            .filter(|c| matching::mentions(text, &c.title))
+            .collect();
+        let best = candidates
+            .iter()
+            .find(|c| c.artists.iter().any(|a| matching::mentions(text, a)))
+            .or(candidates.first());
+        if let Some(candidate) = best {
+            tracing::info!(
+                "Spotify text search found: {} -> {} ({} - {})",
+                text,
+                candidate.id,
+                candidate.artists.join(", "),
+                candidate.title
+            );
+        }
+        Ok(best.map(|c| c.id.clone()))
+    }
+
+    async fn search_candidates(
+        &self,
+        query: &str,
+        limit: u32,
+        market: Option<&str>,
+    ) -> Result<Vec<SearchCandidate>, SpotifyError> {
+        let access_token = self.get_access_token().await?;
+
+        let encoded = urlencoding::encode(query);
+        let mut url = format!(
+            "{}/search?q={}&type=track&limit={}",
+            self.api_base, encoded, limit
         );
+        if let Some(market) = market {
+            url.push_str(&format!("&market={}", market));
+        }
 
         let response = self
-            .client
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", access_token))
-            .send()
-            .await
-            .map_err(|e| SpotifyError::Network(format!("Search failed: {}", e)))?;
+            .send_with_retries(RetryOn::Transient, || {
+                self.client
+                    .get(&url)
+                    .header("Authorization", format!("Bearer {}", access_token))
+            })
+            .await?;
 
-        if !response.status().is_success() {
-            tracing::warn!("Spotify search failed: {}", response.status());
-            return Ok(None);
+        // Only a successful search means no match; anything else is worth trying again later
+        let status = response.status();
+        if status == 429 {
+            let retry_after = response
+                .headers()
+                .get("Retry-After")
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(1);
+            return Err(SpotifyError::RateLimit(retry_after));
+        }
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            let message = format!("Search failed: {} - {}", status, text);
+            return Err(if status.is_server_error() {
+                SpotifyError::Network(message)
+            } else {
+                SpotifyError::Api(message)
+            });
         }
 
         let json: serde_json::Value = response
@@ -350,18 +1581,566 @@ impl SpotifyClient {
             .await
             .map_err(|e| SpotifyError::Network(format!("Parse failed: {}", e)))?;
 
-        let track_id = json
+        let candidates = json
             .get("tracks")
             .and_then(|t| t.get("items"))
             .and_then(|i| i.as_array())
-            .and_then(|a| a.first())
-            .and_then(|t| t.get("id"))
-            .and_then(|id| id.as_str())
-            .map(|s| s.to_string());
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|t| {
+                        let id = t.get("id").and_then(|id| id.as_str())?.to_string();
+                        let title = t
+                            .get("name")
+                            .and_then(|n| n.as_str())
+                            .unwrap_or_default()
+                            .to_string();
+                        let artists = t
+                            .get("artists")
+                            .and_then(|a| a.as_array())
+                            .map(|a| {
+                                a.iter()
+                                    .filter_map(|ar| ar.get("name").and_then(|n| n.as_str()))
+                                    .map(|n| n.to_string())
+                                    .collect()
+                            })
+                            .unwrap_or_default();
+                        Some(SearchCandidate { id, title, artists })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    /// A client pointed at `server` with a token already cached, so no auth request is made.
+    fn mock_client(server: &MockServer) -> SpotifyClient {
+        SpotifyClient::new(
+            "id".to_string(),
+            "secret".to_string(),
+            "refresh".to_string(),
+            "playlist".to_string(),
+        )
+        .with_api_base(&server.uri())
+        .with_access_token("token")
+    }
+
+    #[tokio::test]
+    async fn get_track_metadata_reads_artists_title_album_and_duration() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/tracks/abc"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "name": "Helicopter",
+                "artists": [{ "name": "Bloc Party" }],
+                "album": { "name": "Silent Alarm" },
+                "duration_ms": 220_560,
+            })))
+            .mount(&server)
+            .await;
+
+        let meta = mock_client(&server)
+            .get_track_metadata("abc")
+            .await
+            .unwrap();
+        assert_eq!(meta.artists, vec!["Bloc Party".to_string()]);
+        assert_eq!(meta.title, "Helicopter");
+        assert_eq!(meta.album.as_deref(), Some("Silent Alarm"));
+        assert_eq!(meta.duration, Some(Duration::from_millis(220_560)));
+
+        let episode = TrackMeta::from_json(&serde_json::json!({
+            "name": "Episode 1",
+            "show": { "name": "The Show" },
+            "duration_ms": 1000,
+        }));
+        assert_eq!(episode.artists, vec!["The Show".to_string()]);
+        assert_eq!(episode.album, None);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_with_jitter_in_the_upper_half() {
+        assert_eq!(backoff_delay(0, 0.0), Duration::from_millis(250));
+        assert_eq!(backoff_delay(0, 1.0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(2, 0.5), Duration::from_millis(1500));
+        assert_eq!(backoff_delay(40, 1.0), RETRY_MAX_DELAY);
+        let j = jitter();
+        assert!((0.0..1.0).contains(&j));
+    }
+
+    #[tokio::test]
+    async fn reads_retry_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/tracks/abc"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/tracks/abc"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "name": "Song" })),
+            )
+            .mount(&server)
+            .await;
+
+        let meta = mock_client(&server)
+            .get_track_metadata("abc")
+            .await
+            .unwrap();
+        assert_eq!(meta.title, "Song");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn adds_are_not_resent_after_a_server_error() {
+        let server = MockServer::start().await;
+        // Spotify applied the add but answered 502, e.g. from a proxy timing out
+        Mock::given(method("POST"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(ResponseTemplate::new(502))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(serde_json::json!({ "snapshot_id": "s" })),
+            )
+            .mount(&server)
+            .await;
+
+        let outcome = mock_client(&server)
+            .add_tracks_to("playlist", &["abc".to_string(), "def".to_string()])
+            .await;
+        assert!(outcome.added.is_empty());
+        assert_eq!(outcome.failed.len(), 2);
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn verify_can_modify_tells_a_missing_playlist_apart() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/me"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "bot" })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/playlists/gone"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        assert!(matches!(
+            client.playlist_access("gone").await,
+            Err(SpotifyError::PlaylistNotFound(_))
+        ));
+        assert_eq!(
+            client.verify_can_modify("gone").await.unwrap_err(),
+            "Playlist gone doesn't exist or isn't visible to the bot's account"
+        );
+    }
+
+    #[tokio::test]
+    async fn search_outages_are_errors_not_misses() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/search"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let client = mock_client(&server).with_max_retries(0);
+        assert!(matches!(
+            client.search_track_by_text("Helicopter", "playlist").await,
+            Err(SpotifyError::Network(_))
+        ));
+
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/search"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .mount(&server)
+            .await;
+        assert!(matches!(
+            client.search_track_by_text("Helicopter", "playlist").await,
+            Err(SpotifyError::RateLimit(1))
+        ));
 
-        if let Some(ref id) = track_id {
-            tracing::info!("Spotify search found: {} - {} -> {}", artist, title, id);
+        server.reset().await;
+        Mock::given(method("GET"))
+            .and(path("/search"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "tracks": { "items": [] } })),
+            )
+            .mount(&server)
+            .await;
+        assert!(matches!(
+            client.search_track_by_text("Helicopter", "playlist").await,
+            Ok(None)
+        ));
+    }
+
+    #[tokio::test]
+    async fn remove_positions_chunks_from_the_end_chaining_snapshots() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "snapshot_id": "s1" })),
+            )
+            .mount(&server)
+            .await;
+
+        let removals: Vec<(String, usize)> = (0..150)
+            .map(|position| (format!("spotify:track:t{}", position), position))
+            .collect();
+        let snapshot = mock_client(&server)
+            .remove_positions("playlist", removals, "s0".to_string())
+            .await
+            .unwrap();
+        assert_eq!(snapshot, "s1");
+
+        let bodies: Vec<serde_json::Value> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| serde_json::from_slice(&r.body).unwrap())
+            .collect();
+        assert_eq!(bodies.len(), 2);
+        let positions = |body: &serde_json::Value| -> Vec<u64> {
+            body["tracks"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|t| t["positions"][0].as_u64().unwrap())
+                .collect()
+        };
+        assert_eq!(bodies[0]["snapshot_id"], "s0");
+        assert_eq!(positions(&bodies[0]), (50..150).rev().collect::<Vec<_>>());
+        assert_eq!(bodies[1]["snapshot_id"], "s1");
+        assert_eq!(positions(&bodies[1]), (0..50).rev().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn remove_positions_retries_transient_failures() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "snapshot_id": "s1" })),
+            )
+            .mount(&server)
+            .await;
+
+        let removals = vec![("spotify:track:t0".to_string(), 0)];
+        let snapshot = mock_client(&server)
+            .remove_positions("playlist", removals, "s0".to_string())
+            .await
+            .unwrap();
+        assert_eq!(snapshot, "s1");
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn playlist_uris_at_snapshot_fail_if_the_playlist_changed() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/playlists/playlist"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "snapshot_id": "s0" })),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/playlists/playlist"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "snapshot_id": "s1" })),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({ "items": [], "total": 0 })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        assert!(client
+            .get_playlist_item_uris_at_snapshot("playlist")
+            .await
+            .is_err());
+        // Unchanged from here on
+        let (snapshot, uris) = client
+            .get_playlist_item_uris_at_snapshot("playlist")
+            .await
+            .unwrap();
+        assert_eq!(snapshot, "s1");
+        assert!(uris.is_empty());
+    }
+
+    #[tokio::test]
+    async fn remove_tracks_retries_once_after_a_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "snapshot_id": "s" })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        client
+            .remove_tracks_from("playlist", &["abc".into(), "spotify:episode:xyz".into()])
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 2);
+        let body: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({ "tracks": [{ "uri": "spotify:track:abc" }, { "uri": "spotify:episode:xyz" }] })
+        );
+        assert_eq!(client.rate_limit_hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn add_tracks_isolates_bad_uris_after_a_batch_error() {
+        let server = MockServer::start().await;
+        let batch = serde_json::json!({
+            "uris": ["spotify:track:good1", "spotify:track:bad", "spotify:track:good2"]
+        });
+        let invalid = ResponseTemplate::new(400).set_body_json(
+            serde_json::json!({ "error": { "status": 400, "message": "Invalid base62 id" } }),
+        );
+        Mock::given(method("POST"))
+            .and(path("/playlists/playlist/items"))
+            .and(body_json(&batch))
+            .respond_with(invalid.clone())
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_json(
+                serde_json::json!({ "uris": ["spotify:track:bad"] }),
+            ))
+            .respond_with(invalid)
+            .expect(1)
+            .mount(&server)
+            .await;
+        for good in ["good1", "good2"] {
+            Mock::given(method("POST"))
+                .and(body_json(
+                    serde_json::json!({ "uris": [format!("spotify:track:{}", good)] }),
+                ))
+                .respond_with(
+                    ResponseTemplate::new(201)
+                        .set_body_json(serde_json::json!({ "snapshot_id": "s" })),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
         }
-        Ok(track_id)
+
+        let client = mock_client(&server);
+        let ids = ["good1", "bad", "good2"].map(String::from);
+        let outcome = client.add_tracks_to("playlist", &ids).await;
+
+        assert_eq!(outcome.added, ["good1", "good2"]);
+        assert_eq!(outcome.failed.len(), 1);
+        assert_eq!(outcome.failed[0].0, "bad");
+        assert!(matches!(outcome.failed[0].1, SpotifyError::Api(_)));
+    }
+
+    #[tokio::test]
+    async fn add_reports_a_removed_track_as_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(
+                serde_json::json!({ "error": { "status": 404, "message": "Not found." } }),
+            ))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/playlists/playlist"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "playlist" })),
+            )
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let outcome = client.add_tracks_to("playlist", &["gone".into()]).await;
+        assert!(outcome.added.is_empty());
+        assert!(matches!(outcome.failed[0].1, SpotifyError::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn add_to_a_missing_playlist_blames_the_playlist() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/playlists/playlist/items"))
+            .respond_with(ResponseTemplate::new(404).set_body_json(
+                serde_json::json!({ "error": { "status": 404, "message": "Not found." } }),
+            ))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/playlists/playlist"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let client = mock_client(&server);
+        let outcome = client
+            .add_tracks_to("playlist", &["a".into(), "b".into()])
+            .await;
+        assert!(outcome.added.is_empty());
+        assert_eq!(outcome.failed.len(), 2);
+        assert!(outcome
+            .failed
+            .iter()
+            .all(|(_, e)| matches!(e, SpotifyError::PlaylistNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn text_search_uses_the_target_playlists_market() {
+        let server = MockServer::start().await;
+        for (market, id) in [("GB", "gb"), ("US", "us")] {
+            Mock::given(method("GET"))
+                .and(path("/search"))
+                .and(query_param("market", market))
+                .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                    "tracks": { "items": [
+                        { "id": id, "name": "Helicopter", "artists": [{ "name": "Bloc Party" }] }
+                    ] }
+                })))
+                .mount(&server)
+                .await;
+        }
+        let client = mock_client(&server)
+            .with_market(Some("US"))
+            .with_playlist_markets(HashMap::from([("uk".to_string(), "GB".to_string())]));
+
+        let text = "Bloc Party Helicopter";
+        let found = client.search_track_by_text(text, "uk").await.unwrap();
+        assert_eq!(found.as_deref(), Some("gb"));
+        let found = client.search_track_by_text(text, "playlist").await.unwrap();
+        assert_eq!(found.as_deref(), Some("us"));
+    }
+
+    #[test]
+    fn token_refresh_runs_ahead_of_expiry() {
+        let now = Instant::now();
+        assert_eq!(token_refresh_delay(None, now), Duration::ZERO);
+        // An hour-long token is cached until a minute before Spotify expires it
+        assert_eq!(
+            token_refresh_delay(Some(now + Duration::from_secs(3540)), now),
+            Duration::from_secs(3540)
+        );
+        assert_eq!(token_refresh_delay(Some(now), now), Duration::ZERO);
+    }
+
+    #[test]
+    fn mood_emoji_follows_energy_then_valence() {
+        let mood = |energy, valence| AudioFeatures { valence, energy }.mood_emoji();
+        assert_eq!(mood(0.9, 0.1), "fire");
+        assert_eq!(mood(0.2, 0.2), "rain_cloud");
+        assert_eq!(mood(0.3, 0.8), "leaves");
+        assert_eq!(mood(0.6, 0.9), "sunny");
+        assert_eq!(mood(0.6, 0.1), "new_moon");
+        assert_eq!(mood(0.6, 0.5), "notes");
+    }
+
+    #[test]
+    fn relinked_id_prefers_the_served_track() {
+        let relinked = serde_json::json!({
+            "id": "served",
+            "linked_from": { "id": "requested", "uri": "spotify:track:requested" }
+        });
+        assert_eq!(relinked_id(&relinked).as_deref(), Some("served"));
+        assert_eq!(relinked_id(&serde_json::json!({ "id": "requested" })), None);
+    }
+
+    #[test]
+    fn duplicate_positions_keeps_first_occurrence() {
+        let uris = vec![
+            Some("spotify:track:a".to_string()),
+            Some("spotify:track:b".to_string()),
+            None,
+            Some("spotify:track:a".to_string()),
+            None,
+            Some("spotify:track:a".to_string()),
+            Some("spotify:track:b".to_string()),
+        ];
+        assert_eq!(
+            duplicate_positions(&uris),
+            vec![
+                ("spotify:track:a".to_string(), 3),
+                ("spotify:track:a".to_string(), 5),
+                ("spotify:track:b".to_string(), 6),
+            ]
+        );
+    }
+
+    #[test]
+    fn rate_limit_tracker_counts_recent_hits_and_pauses() {
+        let start = Instant::now();
+        let mut tracker = RateLimitTracker::default();
+        tracker.record(5, start);
+        tracker.record(2, start + Duration::from_secs(1));
+
+        let status = tracker.status(start + Duration::from_secs(2));
+        assert_eq!(status.hits_last_hour, 2);
+        assert_eq!(status.last_retry_after, Some((2, Duration::from_secs(1))));
+        // The longer of the two pauses wins
+        assert_eq!(status.paused_for, Some(Duration::from_secs(3)));
+
+        let later = tracker.status(start + RATE_LIMIT_WINDOW + Duration::from_millis(500));
+        assert_eq!(later.hits_last_hour, 1);
+        assert_eq!(later.paused_for, None);
     }
 }
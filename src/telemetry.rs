@@ -0,0 +1,135 @@
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::cell::RefCell;
+use std::future::Future;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{filter_fn, EnvFilter};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+tokio::task_local! {
+    /// Log lines collected by [`capture`] for the task it wraps.
+    static CAPTURED: RefCell<Vec<String>>;
+}
+
+/// Runs `future` and returns, with its output, every jamcraft log event it emitted at any level
+/// (debug included, whatever `RUST_LOG` says), e.g. for `/jamcraft trace`. Only events from the
+/// same task are seen, so the future shouldn't spawn the work it wants traced.
+pub async fn capture<F: Future>(future: F) -> (F::Output, Vec<String>) {
+    CAPTURED
+        .scope(RefCell::new(Vec::new()), async {
+            let output = future.await;
+            (output, CAPTURED.with(|lines| lines.take()))
+        })
+        .await
+}
+
+/// Feeds events to [`capture`] when one is running on the current task.
+struct CaptureLayer;
+
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let _ = CAPTURED.try_with(|lines| {
+            let mut message = MessageVisitor(String::new());
+            event.record(&mut message);
+            lines
+                .borrow_mut()
+                .push(format!("{} {}", event.metadata().level(), message.0));
+        });
+    }
+}
+
+/// The formatted `message` field of an event.
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+fn env_filter() -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| "jamcraft=info".into())
+}
+
+/// Sets up logging, plus OTLP trace export when `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+///
+/// The exporter reads the endpoint (and `OTEL_EXPORTER_OTLP_HEADERS` etc.) from the standard
+/// OTel env vars itself. Keep the returned provider alive for the lifetime of the process and
+/// shut it down on exit so buffered spans are flushed.
+pub fn init() -> Option<SdkTracerProvider> {
+    // Filters are per layer so captures see debug events the log output leaves out
+    let fmt_layer = tracing_subscriber::fmt::layer().with_filter(env_filter());
+    let capture_layer =
+        CaptureLayer.with_filter(filter_fn(|meta| meta.target().starts_with("jamcraft")));
+
+    let endpoint = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|e| !e.is_empty());
+    let Some(endpoint) = endpoint else {
+        tracing_subscriber::registry()
+            .with(fmt_layer)
+            .with(capture_layer)
+            .init();
+        return None;
+    };
+
+    let exporter = match opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+    {
+        Ok(exporter) => exporter,
+        Err(e) => {
+            tracing_subscriber::registry()
+                .with(fmt_layer)
+                .with(capture_layer)
+                .init();
+            tracing::error!("Failed to create OTLP exporter, traces disabled: {}", e);
+            return None;
+        }
+    };
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("jamcraft").build())
+        .build();
+    let otel_layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("jamcraft"))
+        .with_filter(env_filter());
+
+    tracing_subscriber::registry()
+        .with(fmt_layer)
+        .with(capture_layer)
+        .with(otel_layer)
+        .init();
+    tracing::info!("Exporting traces via OTLP to {}", endpoint);
+
+    Some(provider)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn capture_collects_events_from_the_wrapped_future_only() {
+        let _guard = tracing_subscriber::registry()
+            .with(CaptureLayer)
+            .set_default();
+        tracing::info!("before");
+        let (output, lines) = capture(async {
+            tracing::debug!("step {}", 1);
+            tracing::warn!("step {}", 2);
+            42
+        })
+        .await;
+        tracing::info!("after");
+
+        assert_eq!(output, 42);
+        assert_eq!(lines, ["DEBUG step 1", "WARN step 2"]);
+    }
+}
@@ -1,21 +1,211 @@
 use serde::Deserialize;
+use serde_json::Value;
+use tracing::{debug, warn};
 
-#[derive(Debug, Deserialize)]
-pub struct SlackEnvelope {
-    #[serde(rename = "type")]
-    pub event_type: String,
-    pub challenge: Option<String>,
-    pub event: Option<SlackEvent>,
+/// What an Events API request carries, as far as the bot cares.
+#[derive(Debug)]
+pub enum SlackEnvelope {
+    UrlVerification {
+        challenge: Option<String>,
+    },
+    Event(Box<SlackEvent>),
+    /// Other envelope types, events the bot doesn't handle, and events it couldn't read
+    Ignored,
+}
+
+/// Event types the bot acts on. Only these are deserialized into `SlackEvent`.
+const HANDLED_EVENT_TYPES: &[&str] = &["message", "reaction_added", "reaction_removed"];
+
+impl SlackEnvelope {
+    /// Parses a request body leniently: the envelope is read as plain JSON and only the inner
+    /// event of a handled type is deserialized, so new or reshaped fields elsewhere in the
+    /// payload can't make the whole request fail. Errs only if the body isn't JSON at all.
+    pub fn parse(body: &[u8]) -> Result<Self, serde_json::Error> {
+        let value: serde_json::Value = serde_json::from_slice(body)?;
+        match value["type"].as_str() {
+            Some("url_verification") => Ok(Self::UrlVerification {
+                challenge: value["challenge"].as_str().map(str::to_string),
+            }),
+            Some("event_callback") => {
+                let event = &value["event"];
+                let event_type = event["type"].as_str().unwrap_or_default();
+                if !HANDLED_EVENT_TYPES.contains(&event_type) {
+                    return Ok(Self::Ignored);
+                }
+                match SlackEvent::deserialize(event) {
+                    Ok(mut event) => {
+                        event.event_id = value["event_id"].as_str().map(str::to_string);
+                        Ok(Self::Event(Box::new(event)))
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Dropping {} event with an unexpected shape: {}",
+                            event_type, e
+                        );
+                        debug!("Raw event body: {}", String::from_utf8_lossy(body));
+                        Ok(Self::Ignored)
+                    }
+                }
+            }
+            _ => Ok(Self::Ignored),
+        }
+    }
+}
+
+/// Message subtypes that are still a member posting in the channel, so their links count:
+/// file uploads and messages scheduled to post later. Everything else (`channel_join`,
+/// `bot_message`, `channel_topic`, …) is automated or administrative and skipped.
+pub const USER_MESSAGE_SUBTYPES: &[&str] = &["file_share", "scheduled_message"];
+
+/// Whether a message with this subtype (or none) was posted by a member.
+pub fn is_user_message(subtype: Option<&str>) -> bool {
+    subtype.is_none_or(|s| USER_MESSAGE_SUBTYPES.contains(&s))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SlackEvent {
+    /// The envelope's `event_id`, the same on every delivery of one event
+    #[serde(skip)]
+    pub event_id: Option<String>,
     #[serde(rename = "type")]
-    #[allow(dead_code)]
     pub event_type: String,
     pub text: Option<String>,
     pub channel: Option<String>,
     pub ts: Option<String>,
+    pub user: Option<String>,
     pub bot_id: Option<String>,
     pub subtype: Option<String>,
+    /// `reaction_added`/`reaction_removed`: emoji name, without colons
+    pub reaction: Option<String>,
+    /// `reaction_added`/`reaction_removed`: the message that was reacted to
+    pub item: Option<ReactionItem>,
+    /// `message_changed`: the message as it is after the edit
+    pub message: Option<EditedMessage>,
+    /// Kept as raw JSON so an unexpected shape can't drop the event (see `block_link_urls`)
+    pub blocks: Option<Value>,
+    pub attachments: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EditedMessage {
+    pub text: Option<String>,
+    /// The original message's ts; the event's own `ts` is the edit's
+    pub ts: Option<String>,
+    pub user: Option<String>,
+    pub bot_id: Option<String>,
+    pub blocks: Option<Value>,
+    pub attachments: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReactionItem {
+    #[serde(rename = "type")]
+    pub item_type: String,
+    pub channel: Option<String>,
+    pub ts: Option<String>,
+}
+
+/// URLs of rich-text `link` elements in a message's blocks and in any forwarded message's
+/// blocks (`attachments[].message_blocks[].message.blocks`), including links nested in quotes
+/// and lists. A forward often carries its link only there, not in the message `text`.
+pub fn block_link_urls(blocks: Option<&Value>, attachments: Option<&Value>) -> Vec<String> {
+    let forwarded = attachments
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a["message_blocks"].as_array())
+        .flatten()
+        .map(|m| &m["message"]["blocks"]);
+
+    let mut urls = Vec::new();
+    for blocks in blocks.into_iter().chain(forwarded) {
+        for block in blocks.as_array().into_iter().flatten() {
+            collect_links(block, &mut urls);
+        }
+    }
+    urls
+}
+
+fn collect_links(element: &Value, urls: &mut Vec<String>) {
+    if element["type"] == "link" {
+        if let Some(url) = element["url"].as_str() {
+            if !urls.iter().any(|u| u == url) {
+                urls.push(url.to_string());
+            }
+        }
+    }
+    for child in element["elements"].as_array().into_iter().flatten() {
+        collect_links(child, urls);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(value: serde_json::Value) -> SlackEnvelope {
+        SlackEnvelope::parse(value.to_string().as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn parse_tolerates_unmodelled_and_unexpected_shapes() {
+        let message = parse(json!({
+            "type": "event_callback",
+            "authorizations": [{ "new": "field" }],
+            "event_id": "Ev1",
+            "event": { "type": "message", "channel": "C1", "ts": "1.2", "text": "hi", "blocks": [] }
+        }));
+        assert!(matches!(message, SlackEnvelope::Event(e)
+            if e.text.as_deref() == Some("hi") && e.event_id.as_deref() == Some("Ev1")));
+
+        // A handled type whose inner shape changed is dropped, not an error
+        let reshaped = parse(json!({
+            "type": "event_callback",
+            "event": { "type": "reaction_added", "item": "not an object" }
+        }));
+        assert!(matches!(reshaped, SlackEnvelope::Ignored));
+
+        let other =
+            parse(json!({ "type": "event_callback", "event": { "type": "app_home_opened" } }));
+        assert!(matches!(other, SlackEnvelope::Ignored));
+
+        let challenge = parse(json!({ "type": "url_verification", "challenge": "abc" }));
+        assert!(matches!(
+            challenge,
+            SlackEnvelope::UrlVerification { challenge: Some(c) } if c == "abc"
+        ));
+        assert!(SlackEnvelope::parse(b"not json").is_err());
+    }
+
+    #[test]
+    fn block_link_urls_finds_rich_text_and_forwarded_links() {
+        let link = |url: &str| json!({ "type": "link", "url": url });
+        let blocks = json!([{
+            "type": "rich_text",
+            "elements": [
+                { "type": "rich_text_section", "elements": [{ "type": "text", "text": "listen " }, link("https://a.example")] },
+                { "type": "rich_text_quote", "elements": [link("https://b.example"), link("https://a.example")] }
+            ]
+        }]);
+        let attachments = json!([{
+            "is_share": true,
+            "message_blocks": [{
+                "message": { "blocks": [{
+                    "type": "rich_text",
+                    "elements": [{ "type": "rich_text_section", "elements": [link("https://c.example")] }]
+                }] }
+            }]
+        }]);
+
+        assert_eq!(
+            block_link_urls(Some(&blocks), Some(&attachments)),
+            vec![
+                "https://a.example",
+                "https://b.example",
+                "https://c.example"
+            ]
+        );
+        assert!(block_link_urls(Some(&json!("unexpected")), None).is_empty());
+    }
 }
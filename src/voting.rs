@@ -0,0 +1,202 @@
+use crate::{forget_removed_tracks, AppState};
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Reaction that votes to remove a card's tracks.
+pub const REMOVE_VOTE_EMOJI: &str = "x";
+/// Reaction that votes to keep them.
+pub const KEEP_VOTE_EMOJI: &str = "heart";
+/// Added to a card once enough people voted to keep it.
+const KEPT_EMOJI: &str = "pushpin";
+
+/// How long a card takes votes after it's posted.
+pub const VOTE_WINDOW: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// A confirmation message the channel can vote on under `REACTION_VOTING`, keyed in
+/// `AppState::vote_cards` by its own `channel:ts`.
+pub struct VoteCard {
+    /// `channel:ts` of the message the tracks were added from
+    pub message_key: String,
+    pub playlist_id: String,
+    pub track_ids: Vec<String>,
+    remove_votes: HashSet<String>,
+    keep_votes: HashSet<String>,
+    /// Kept by vote, so remove votes no longer count
+    kept: bool,
+    /// Voted out and being removed, so further votes don't start a second removal
+    removing: bool,
+    pub posted_at: Instant,
+}
+
+/// What a vote tipped a card into.
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Remove,
+    Keep,
+}
+
+impl VoteCard {
+    pub fn new(message_key: String, playlist_id: String, track_ids: Vec<String>) -> Self {
+        Self {
+            message_key,
+            playlist_id,
+            track_ids,
+            remove_votes: HashSet::new(),
+            keep_votes: HashSet::new(),
+            kept: false,
+            removing: false,
+            posted_at: Instant::now(),
+        }
+    }
+
+    /// Adds or withdraws `user`'s vote, returning a verdict the moment a threshold is reached.
+    /// Each user counts once per emoji, however often they react.
+    fn vote(
+        &mut self,
+        user: &str,
+        reaction: &str,
+        added: bool,
+        remove_threshold: usize,
+        keep_threshold: usize,
+    ) -> Option<Verdict> {
+        let votes = match reaction {
+            REMOVE_VOTE_EMOJI => &mut self.remove_votes,
+            KEEP_VOTE_EMOJI => &mut self.keep_votes,
+            _ => return None,
+        };
+        if !added {
+            votes.remove(user);
+            return None;
+        }
+        if !votes.insert(user.to_string()) || self.kept || self.removing {
+            return None;
+        }
+        if self.keep_votes.len() >= keep_threshold {
+            self.kept = true;
+            Some(Verdict::Keep)
+        } else if self.remove_votes.len() >= remove_threshold {
+            self.removing = true;
+            Some(Verdict::Remove)
+        } else {
+            None
+        }
+    }
+}
+
+/// Counts a reaction added to (or removed from) a vote card, removing the card's tracks or
+/// flagging them as kept once a threshold is reached.
+pub async fn on_reaction(
+    state: AppState,
+    channel: String,
+    ts: String,
+    user: String,
+    reaction: String,
+    added: bool,
+) {
+    let key = format!("{}:{}", channel, ts);
+    let verdict = match state.vote_cards.get_mut(&key) {
+        Some(mut card) => card.vote(
+            &user,
+            &reaction,
+            added,
+            state.config.vote_remove_threshold,
+            state.config.vote_keep_threshold,
+        ),
+        None => return,
+    };
+
+    match verdict {
+        None => {}
+        Some(Verdict::Keep) => {
+            info!("Tracks on card {} kept by vote", key);
+            if let Err(e) = state.slack.reactions_add(&channel, &ts, KEPT_EMOJI).await {
+                warn!("Failed to flag kept card {}: {}", key, e);
+            }
+        }
+        Some(Verdict::Remove) => {
+            let Some((message_key, playlist_id, track_ids)) = state.vote_cards.get(&key).map(|c| {
+                (
+                    c.message_key.clone(),
+                    c.playlist_id.clone(),
+                    c.track_ids.clone(),
+                )
+            }) else {
+                return;
+            };
+            let Some(spotify) = &state.spotify else {
+                return;
+            };
+            if !state.dry_run() && !state.config.shadow_mode {
+                if let Err(e) = spotify.remove_tracks_from(&playlist_id, &track_ids).await {
+                    error!("Failed to remove voted-out tracks on {}: {}", key, e);
+                    // The votes stand, so the next one tries again
+                    if let Some(mut card) = state.vote_cards.get_mut(&key) {
+                        card.removing = false;
+                    }
+                    return;
+                }
+            }
+            // Gone if the message was undone meanwhile
+            let Some((_, card)) = state.vote_cards.remove(&key) else {
+                return;
+            };
+            state.added_tracks.remove(&message_key);
+            forget_removed_tracks(&state, &card.playlist_id, &card.track_ids);
+            info!(
+                "Removed {} track(s) from {} by vote",
+                card.track_ids.len(),
+                card.message_key
+            );
+
+            let message = format!(
+                "Removed {} track(s) from the playlist: {} people voted :{}:",
+                card.track_ids.len(),
+                card.remove_votes.len(),
+                REMOVE_VOTE_EMOJI
+            );
+            if let Err(e) = state
+                .slack
+                .chat_post_message(&channel, Some(&ts), &message)
+                .await
+            {
+                warn!("Failed to post vote removal message: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn votes_count_each_user_once_and_keep_blocks_removal() {
+        let mut card = VoteCard::new("C1:1.1".into(), "pl".into(), vec!["a".into()]);
+        let mut vote =
+            |user: &str, reaction: &str, added: bool| card.vote(user, reaction, added, 2, 2);
+
+        assert_eq!(vote("U1", REMOVE_VOTE_EMOJI, true), None);
+        assert_eq!(vote("U1", REMOVE_VOTE_EMOJI, true), None);
+        assert_eq!(vote("U1", REMOVE_VOTE_EMOJI, false), None);
+        assert_eq!(vote("U2", REMOVE_VOTE_EMOJI, true), None);
+        assert_eq!(vote("U3", "thumbsup", true), None);
+
+        assert_eq!(vote("U3", KEEP_VOTE_EMOJI, true), None);
+        assert_eq!(vote("U4", KEEP_VOTE_EMOJI, true), Some(Verdict::Keep));
+        // Kept tracks stay, however many remove votes follow
+        assert_eq!(vote("U1", REMOVE_VOTE_EMOJI, true), None);
+        assert_eq!(vote("U5", REMOVE_VOTE_EMOJI, true), None);
+    }
+
+    #[test]
+    fn remove_threshold_returns_remove_once() {
+        let mut card = VoteCard::new("C1:1.1".into(), "pl".into(), vec!["a".into()]);
+        assert_eq!(card.vote("U1", REMOVE_VOTE_EMOJI, true, 2, 5), None);
+        assert_eq!(
+            card.vote("U2", REMOVE_VOTE_EMOJI, true, 2, 5),
+            Some(Verdict::Remove)
+        );
+        assert_eq!(card.vote("U3", REMOVE_VOTE_EMOJI, true, 2, 5), None);
+    }
+}